@@ -0,0 +1,48 @@
+use crate::errors::{AccountError, AccountResult};
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+/// The bearer token of a request.
+///
+/// Holds the access token sent in the `Authorization` header. Routes
+/// verify it themselves, so a missing or invalid token is reported
+/// as a problem like any other account error.
+///
+/// # Fields
+/// * `0` - The access token, if one was sent
+///
+/// # Methods
+/// * `token` - Gets the access token
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BearerToken(pub Option<String>);
+
+/// The bearer token implementation.
+impl BearerToken {
+    /// Gets the access token.
+    ///
+    /// # Returns
+    /// The access token, or `InvalidToken` if none was sent
+    pub fn token(&self) -> AccountResult<&str> {
+        self.0.as_deref().ok_or(AccountError::InvalidToken)
+    }
+}
+
+/// The bearer token request guard.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.split_once(' '))
+            // The scheme is case-insensitive, see RFC 7235
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+            .map(|(_, token)| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+        Outcome::Success(BearerToken(token))
+    }
+}
@@ -0,0 +1,39 @@
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+/// The maximum length of a recorded user agent, in characters.
+pub const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// The client of a request.
+///
+/// Recorded with every login so users can tell their sessions apart.
+/// The ip address is the one of the connection, unless the rocket
+/// `ip_header` setting names a header set by a trusted proxy. Clients
+/// can send any header, so it is off by default.
+///
+/// # Fields
+/// * `user_agent` - The `User-Agent` header, cut to its first 256 characters
+/// * `ip` - The client ip address, if known
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// The client info request guard.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user_agent = request
+            .headers()
+            .get_one("User-Agent")
+            .filter(|agent| !agent.is_empty())
+            .map(|agent| agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        let ip = request.client_ip().map(|ip| ip.to_string());
+        Outcome::Success(ClientInfo { user_agent, ip })
+    }
+}
@@ -0,0 +1,63 @@
+use crate::data::{DaprClientConfig, PasswordHashingConfig};
+use crate::mail::MailConfig;
+use crate::services::{LockoutConfig, SignupConfig};
+use crate::tokens::{EmailVerificationConfig, MfaConfig, PasswordResetConfig, TokenConfig};
+use rocket::serde::Deserialize;
+
+/// The account store backend.
+///
+/// # Variants
+/// * `Dapr` - Accounts are kept in the dapr state store
+/// * `Memory` - Accounts are kept in process memory
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum AccountStore {
+    #[default]
+    Dapr,
+    Memory,
+}
+
+/// The account api configuration.
+///
+/// Values are read from `Rocket.toml` and can be overridden with
+/// `ROCKET_` prefixed environment variables, e.g. `ROCKET_ACCOUNT_STORE=memory`.
+///
+/// # Fields
+/// * `account_store` - The account store backend to use
+/// * `backfill_email_index` - Index the emails of accounts stored before
+///   the email index existed on startup, needs a query capable dapr state store
+/// * `dapr_client` - The timeouts, retries and circuit breaker of dapr requests
+/// * `password_hashing` - The concurrency and queue limits of password hashing
+/// * `tokens` - The issuer, audience, lifetime and signing key of access tokens
+/// * `mail` - The transport and sender address of mails
+/// * `password_reset` - The token lifetime and link of password resets
+/// * `email_verification` - The policy, token lifetime and link of email verifications
+/// * `lockout` - The thresholds and durations of login lockouts
+/// * `signup` - Whether signups reveal taken emails
+/// * `mfa` - The issuer, encryption key and challenge lifetime of two-factor authentication
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiConfig {
+    #[serde(default)]
+    pub account_store: AccountStore,
+    #[serde(default)]
+    pub backfill_email_index: bool,
+    #[serde(default)]
+    pub dapr_client: DaprClientConfig,
+    #[serde(default)]
+    pub password_hashing: PasswordHashingConfig,
+    #[serde(default)]
+    pub tokens: TokenConfig,
+    #[serde(default)]
+    pub mail: MailConfig,
+    #[serde(default)]
+    pub password_reset: PasswordResetConfig,
+    #[serde(default)]
+    pub email_verification: EmailVerificationConfig,
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    #[serde(default)]
+    pub signup: SignupConfig,
+    #[serde(default)]
+    pub mfa: MfaConfig,
+}
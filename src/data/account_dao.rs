@@ -1,25 +1,36 @@
-use super::account_entity::AccountEntity;
+use super::account_entity::{AccountEntity, MfaSettings};
+use super::account_query::{AccountPage, AccountQuery};
+use crate::errors::AccountResult;
 use rocket::async_trait;
 
 /// The Account Data Access Object.
 ///
 /// This data access object is used to access the account data.
+/// Implementations must be thread safe so they can be shared
+/// between requests as a trait object.
 ///
 /// # Methods
-/// * `get_accounts` - Gets all accounts
+/// * `get_accounts` - Gets a page of accounts
 /// * `get_account_by_id` - Gets an account by id
 /// * `get_account_by_email` - Gets an account by email
 /// * `validate_account` - Validates an account
 /// * `create_account` - Creates an account
 /// * `update_account` - Updates an account
+/// * `change_password` - Changes the password of an account
+/// * `reset_password` - Sets the password of an account without the current one
+/// * `verify_email` - Marks the email of an account as verified
+/// * `set_mfa` - Replaces the two-factor settings of an account
 /// * `delete_account` - Deletes an account
 #[async_trait]
-pub trait AccountDao {
-    /// Gets all accounts.
+pub trait AccountDao: Send + Sync {
+    /// Gets a page of accounts.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, sort order and page to get
     ///
     /// # Returns
-    /// The list of accounts
-    async fn get_accounts(&self) -> Vec<AccountEntity>;
+    /// The page of matching accounts
+    async fn get_accounts(&self, query: AccountQuery) -> AccountResult<AccountPage>;
 
     /// Gets an account by id.
     ///
@@ -27,8 +38,8 @@ pub trait AccountDao {
     /// * `id` - The id of the account
     ///
     /// # Returns
-    /// The account entity
-    async fn get_account_by_id(&self, id: String) -> Option<AccountEntity>;
+    /// The account entity, or `NotFound` if no account has the id
+    async fn get_account_by_id(&self, id: String) -> AccountResult<AccountEntity>;
 
     /// Gets an account by email.
     ///
@@ -36,18 +47,25 @@ pub trait AccountDao {
     /// * `email` - The email of the account
     ///
     /// # Returns
-    /// The account entity
-    async fn get_account_by_email(&self, email: String) -> Option<AccountEntity>;
+    /// The account entity, or `NotFound` if no account has the email
+    async fn get_account_by_email(&self, email: String) -> AccountResult<AccountEntity>;
 
     /// Validates an account.
     ///
+    /// Unknown emails take as long as wrong passwords, so logins do not
+    /// reveal which emails have an account.
+    ///
     /// # Arguments
     /// * `email` - The email of the account
     /// * `password` - The password of the account
     ///
     /// # Returns
-    /// The account entity
-    async fn validate_account(&self, email: String, password: String) -> Option<AccountEntity>;
+    /// The account entity, or `InvalidCredentials` if the email or password is wrong
+    async fn validate_account(
+        &self,
+        email: String,
+        password: String,
+    ) -> AccountResult<AccountEntity>;
 
     /// Creates an account.
     ///
@@ -55,24 +73,95 @@ pub trait AccountDao {
     /// * `account` - The account to create
     ///
     /// # Returns
-    /// `true` if the account was created, otherwise `false`
-    async fn create_account(&self, account: AccountEntity) -> bool;
+    /// Nothing, or `Conflict` if the id or email is already in use
+    async fn create_account(&self, account: AccountEntity) -> AccountResult<()>;
 
     /// Updates an account.
     ///
+    /// The update only succeeds if the stored account still has the
+    /// version in `account.etag`, or the version read by the update
+    /// itself when no etag is given. The stored password hash and roles
+    /// are kept, `account.password` and `account.roles` are ignored. The
+    /// email verification is kept too, unless the email changes.
+    ///
     /// # Arguments
     /// * `account` - The account to update
     ///
     /// # Returns
-    /// `true` if the account was updated, otherwise `false`
-    async fn update_account(&self, account: AccountEntity) -> bool;
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account was modified concurrently
+    async fn update_account(&self, account: AccountEntity) -> AccountResult<()>;
 
-    /// Deletes an account.
+    /// Changes the password of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `current_password` - The current password of the account
+    /// * `new_password` - The password to set
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, `InvalidCredentials`
+    /// if the current password is wrong, or `PreconditionFailed` if the
+    /// account was modified concurrently
+    async fn change_password(
+        &self,
+        id: String,
+        current_password: String,
+        new_password: String,
+    ) -> AccountResult<()>;
+
+    /// Sets the password of an account without the current one.
+    ///
+    /// Only used once the owner proved access to the account otherwise,
+    /// e.g. with a password reset token.
     ///
     /// # Arguments
     /// * `id` - The id of the account
+    /// * `new_password` - The password to set
+    ///
+    /// # Returns
+    /// Nothing, or `NotFound` if the account does not exist
+    async fn reset_password(&self, id: String, new_password: String) -> AccountResult<()>;
+
+    /// Marks the email of an account as verified.
+    ///
+    /// Verifying an already verified email keeps the first verification time.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `email` - The email that was verified
+    /// * `verified_at` - The verification time, in seconds since the epoch
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account has another email by now
+    async fn verify_email(&self, id: String, email: String, verified_at: u64) -> AccountResult<()>;
+
+    /// Replaces the two-factor settings of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `mfa` - The two-factor settings to store
+    /// * `etag` - The version the settings were read at, if any
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account was modified concurrently
+    async fn set_mfa(
+        &self,
+        id: String,
+        mfa: MfaSettings,
+        etag: Option<String>,
+    ) -> AccountResult<()>;
+
+    /// Deletes an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account to delete
+    /// * `etag` - The expected version of the account, if any
     ///
     /// # Returns
-    /// `true` if the account was deleted, otherwise `false`
-    async fn delete_account(&self, id: String) -> bool;
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account was modified concurrently
+    async fn delete_account(&self, id: String, etag: Option<String>) -> AccountResult<()>;
 }
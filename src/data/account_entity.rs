@@ -1,5 +1,101 @@
-use crate::services::AccountModel;
+use crate::services::{AccountModel, AccountUpdateModel};
 use rocket::serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The account status.
+///
+/// Accounts stored before the status existed are active.
+///
+/// # Variants
+/// * `Active` - The account can be used
+/// * `Suspended` - The account has been suspended, it cannot log in
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    Suspended,
+}
+
+/// The account status implementation.
+impl AccountStatus {
+    /// Parses an account status.
+    ///
+    /// # Arguments
+    /// * `value` - The lowercase status name
+    ///
+    /// # Returns
+    /// The status, or `None` if the name is unknown
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(AccountStatus::Active),
+            "suspended" => Some(AccountStatus::Suspended),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of the status as stored.
+    ///
+    /// # Returns
+    /// The lowercase status name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Suspended => "suspended",
+        }
+    }
+}
+
+/// The role every account has.
+pub const USER_ROLE: &str = "user";
+
+/// The role of operators, only granted by editing the stored account.
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Gets the roles of a new account.
+///
+/// Accounts stored before roles existed are plain users.
+///
+/// # Returns
+/// The default roles
+pub fn default_roles() -> Vec<String> {
+    vec![USER_ROLE.to_string()]
+}
+
+/// The two-factor settings of an account.
+///
+/// Accounts stored before two-factor authentication existed have none.
+///
+/// # Fields
+/// * `totp_secret` - The encrypted TOTP secret, once enrollment was confirmed
+/// * `pending_totp_secret` - The encrypted TOTP secret of an unconfirmed enrollment
+/// * `pending_totp_expires_at` - The time the unconfirmed enrollment expires, in seconds
+///   since the epoch
+/// * `last_totp_step` - The time step of the last accepted code, so codes are single-use
+/// * `recovery_codes` - The hashes of the unused recovery codes
+///
+/// # Methods
+/// * `is_enabled` - Checks if logins need a second factor
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct MfaSettings {
+    pub totp_secret: Option<String>,
+    pub pending_totp_secret: Option<String>,
+    pub pending_totp_expires_at: u64,
+    pub last_totp_step: u64,
+    pub recovery_codes: Vec<String>,
+}
+
+/// The two-factor settings implementation.
+impl MfaSettings {
+    /// Checks if logins need a second factor.
+    ///
+    /// # Returns
+    /// True once a TOTP enrollment was confirmed
+    pub fn is_enabled(&self) -> bool {
+        self.totp_secret.is_some()
+    }
+}
 
 /// The Account Entity.
 ///
@@ -11,9 +107,18 @@ use rocket::serde::{Deserialize, Serialize};
 /// * `name` - The name of the account
 /// * `email` - The email of the account
 /// * `password` - The password of the account
+/// * `status` - The status of the account
+/// * `roles` - The roles of the account, granted in access tokens
+/// * `email_verified` - True once the owner proved access to the email
+/// * `email_verified_at` - The time the email was verified, in seconds since the epoch
+/// * `mfa` - The two-factor settings of the account
+/// * `instance_id` - A random id given on creation, telling the account apart from
+///   a deleted one with the same id
+/// * `etag` - The version of the stored account, not persisted in the value
 ///
 /// # Methods
 /// * `from_model` - Creates a new account entity from an account model
+/// * `from_update` - Creates a new account entity from an account update model
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct AccountEntity {
@@ -21,6 +126,20 @@ pub struct AccountEntity {
     pub name: String,
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub status: AccountStatus,
+    #[serde(default = "default_roles")]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(default)]
+    pub email_verified_at: Option<u64>,
+    #[serde(default)]
+    pub mfa: MfaSettings,
+    #[serde(default)]
+    pub instance_id: String,
+    #[serde(skip)]
+    pub etag: Option<String>,
 }
 
 /// The account entity implementation.
@@ -38,6 +157,38 @@ impl AccountEntity {
             name: account.name.clone(),
             email: account.email.clone(),
             password: account.password.clone(),
+            status: AccountStatus::Active,
+            roles: default_roles(),
+            email_verified: false,
+            email_verified_at: None,
+            mfa: MfaSettings::default(),
+            instance_id: Uuid::new_v4().to_string(),
+            etag: None,
+        }
+    }
+
+    /// Creates a new account entity from an account update model.
+    ///
+    /// # Arguments
+    /// * `account` - The account update model to convert
+    ///
+    /// # Returns
+    /// The new account entity, without a password, status, roles, email
+    /// verification, two-factor settings or instance id since updates keep
+    /// the stored ones
+    pub fn from_update(account: &AccountUpdateModel) -> Self {
+        AccountEntity {
+            id: account.id.clone(),
+            name: account.name.clone(),
+            email: account.email.clone(),
+            password: String::new(),
+            status: AccountStatus::Active,
+            roles: default_roles(),
+            email_verified: false,
+            email_verified_at: None,
+            mfa: MfaSettings::default(),
+            instance_id: String::new(),
+            etag: None,
         }
     }
 }
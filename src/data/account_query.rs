@@ -0,0 +1,135 @@
+use super::account_entity::{AccountEntity, AccountStatus};
+use super::email_index::normalize_email;
+
+/// The default number of accounts in a page.
+pub const DEFAULT_PAGE_LIMIT: usize = 50;
+
+/// The maximum number of accounts in a page.
+pub const MAX_PAGE_LIMIT: usize = 100;
+
+/// The account field to sort a listing by.
+///
+/// # Variants
+/// * `Id` - Sort by account id
+/// * `Name` - Sort by account name
+/// * `Email` - Sort by account email
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SortField {
+    #[default]
+    Id,
+    Name,
+    Email,
+}
+
+/// The sort field implementation.
+impl SortField {
+    /// Parses a sort field.
+    ///
+    /// # Arguments
+    /// * `value` - The field name
+    ///
+    /// # Returns
+    /// The sort field, or `None` if the field cannot be sorted by
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "id" => Some(SortField::Id),
+            "name" => Some(SortField::Name),
+            "email" => Some(SortField::Email),
+            _ => None,
+        }
+    }
+
+    /// Gets the key of the field in a stored account.
+    ///
+    /// # Returns
+    /// The field key
+    pub fn key(&self) -> &'static str {
+        match self {
+            SortField::Id => "id",
+            SortField::Name => "name",
+            SortField::Email => "email",
+        }
+    }
+}
+
+/// The sort order of a listing.
+///
+/// # Variants
+/// * `Asc` - Ascending order
+/// * `Desc` - Descending order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// An account listing query.
+///
+/// # Fields
+/// * `limit` - The maximum number of accounts in the page
+/// * `page_token` - The token of the page to get, the first page if `None`
+/// * `sort` - The field to sort by
+/// * `order` - The sort order
+/// * `name` - Only list accounts with this exact name
+/// * `email` - Only list the account with this email, compared normalized
+/// * `status` - Only list accounts with this status
+///
+/// # Methods
+/// * `matches` - Checks if an account matches the filters of the query
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountQuery {
+    pub limit: usize,
+    pub page_token: Option<String>,
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub status: Option<AccountStatus>,
+}
+
+/// The default query lists the first page of all accounts by id.
+impl Default for AccountQuery {
+    fn default() -> Self {
+        AccountQuery {
+            limit: DEFAULT_PAGE_LIMIT,
+            page_token: None,
+            sort: SortField::default(),
+            order: SortOrder::default(),
+            name: None,
+            email: None,
+            status: None,
+        }
+    }
+}
+
+/// The account query implementation.
+impl AccountQuery {
+    /// Checks if an account matches the filters of the query.
+    ///
+    /// # Arguments
+    /// * `account` - The account to check
+    ///
+    /// # Returns
+    /// True if the account passes every filter
+    pub fn matches(&self, account: &AccountEntity) -> bool {
+        // Filters that are not set pass every account
+        self.name.iter().all(|name| &account.name == name)
+            && self
+                .email
+                .iter()
+                .all(|email| normalize_email(&account.email) == normalize_email(email))
+            && self.status.iter().all(|status| account.status == *status)
+    }
+}
+
+/// A page of accounts.
+///
+/// # Fields
+/// * `items` - The accounts in the page
+/// * `next_page_token` - The token of the next page, `None` on the last page
+#[derive(Clone, Debug, Default)]
+pub struct AccountPage {
+    pub items: Vec<AccountEntity>,
+    pub next_page_token: Option<String>,
+}
@@ -0,0 +1,36 @@
+use super::action_token_entity::ActionTokenEntity;
+use crate::errors::AccountResult;
+use rocket::async_trait;
+
+/// The Action Token Data Access Object.
+///
+/// This data access object is used to access stored action tokens.
+/// Implementations must be thread safe so they can be shared between
+/// requests as a trait object.
+///
+/// # Methods
+/// * `create_action_token` - Creates an action token
+/// * `consume_action_token` - Removes an action token and returns it
+#[async_trait]
+pub trait ActionTokenDao: Send + Sync {
+    /// Creates an action token.
+    ///
+    /// # Arguments
+    /// * `token` - The new token
+    ///
+    /// # Returns
+    /// Nothing if the token was stored, `Conflict` if it already exists
+    async fn create_action_token(&self, token: ActionTokenEntity) -> AccountResult<()>;
+
+    /// Removes an action token and returns it.
+    ///
+    /// Only one of several concurrent callers gets the token, so
+    /// every token can be used at most once.
+    ///
+    /// # Arguments
+    /// * `id` - The hash of the token
+    ///
+    /// # Returns
+    /// The removed token entity, or `NotFound` if no token has the hash
+    async fn consume_action_token(&self, id: String) -> AccountResult<ActionTokenEntity>;
+}
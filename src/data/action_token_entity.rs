@@ -0,0 +1,55 @@
+use rocket::serde::{Deserialize, Serialize};
+
+/// The key prefix of action tokens in the state store.
+pub const ACTION_TOKEN_PREFIX: &str = "action:";
+
+/// The action an action token allows.
+///
+/// # Variants
+/// * `PasswordReset` - Sets a new password without the current one
+/// * `EmailVerification` - Marks the email of the account as verified
+/// * `MfaChallenge` - Completes a login with a second factor
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum ActionPurpose {
+    PasswordReset,
+    EmailVerification,
+    MfaChallenge,
+}
+
+/// A stored action token.
+///
+/// Action tokens are mailed to the owner of an account and allow
+/// one action on it. Only a hash of the token is stored, and the
+/// token is deleted when it is used.
+///
+/// # Fields
+/// * `id` - The SHA-256 hash of the token
+/// * `purpose` - The action the token allows
+/// * `account_id` - The account the token belongs to
+/// * `email` - The email the token was sent to, or the email logging in for challenges
+/// * `expires_at` - The expiry time, in seconds since the epoch
+/// * `etag` - The version of the token, not stored
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct ActionTokenEntity {
+    pub id: String,
+    pub purpose: ActionPurpose,
+    pub account_id: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    pub expires_at: u64,
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// Gets the state store key of an action token.
+///
+/// # Arguments
+/// * `id` - The hash of the token
+///
+/// # Returns
+/// The token key, `action:<hash>`
+pub fn action_token_key(id: &str) -> String {
+    format!("{}{}", ACTION_TOKEN_PREFIX, id)
+}
@@ -1,28 +1,25 @@
 use std::env;
+use std::sync::Arc;
 
 use super::account_dao::AccountDao;
-use super::account_entity::AccountEntity;
-use pwhash::bcrypt;
-use reqwest::ClientBuilder;
+use super::account_entity::{AccountEntity, AccountStatus, MfaSettings};
+use super::account_query::{AccountPage, AccountQuery, SortOrder};
+use super::dapr_action_token_dao::DaprActionTokenDao;
+use super::dapr_client::{sidecar_url, DaprClientConfig};
+use super::dapr_login_attempts_dao::DaprLoginAttemptsDao;
+use super::dapr_refresh_token_dao::DaprRefreshTokenDao;
+use super::dapr_state_store::{delete_operation, upsert_operation, DaprStateStore};
+use super::email_index::{email_index_key, is_auxiliary_key, normalize_email, EmailIndexEntry};
+use super::passwords::{PasswordHasher, Verification};
+use crate::errors::{AccountError, AccountResult};
 use rocket::{
     async_trait,
-    serde::json::serde_json::json,
+    serde::json::serde_json::{self, json, Value},
     serde::{Deserialize, Serialize},
 };
 
-/// Get the the sidecar port.
-/// 
-/// # Returns
-/// The sidecar port
-fn get_sidecar_port() -> String {
-    match env::var("STATE_STORE_PORT") {
-        Ok(val) => val,
-        Err(_e) => "3500".to_string(),
-    }
-}
-
 /// Get the state store name.
-/// 
+///
 /// # Returns
 /// The state store name
 fn get_state_store_name() -> String {
@@ -32,186 +29,344 @@ fn get_state_store_name() -> String {
     }
 }
 
-/// Get the dapr url.
-/// 
-/// # Returns
-/// The dapr url
-fn get_sidecar_url() -> String {
-    format!(
-        "http://localhost:{}/v1.0/state/{}",
-        get_sidecar_port(),
-        get_state_store_name()
-    )
-}
-
-/// Get the dapr query url.
-/// 
-/// # Returns
-/// The dapr query url
-fn get_sidecar_query_url() -> String {
-    format!(
-        "http://localhost:{}/v1.0-alpha1/state/{}/query",
-        get_sidecar_port(),
-        get_state_store_name()
-    )
-}
+/// The attempts to write an account that keeps changing.
+const WRITE_ATTEMPTS: usize = 3;
 
 /// The dapr results model maps the results from the dapr state store.
 ///
 /// # Fields
 /// * `results` - The results from the dapr state store
+/// * `token` - The token of the next page, if there may be more results
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
 struct DaprResults {
     results: Vec<Entry>,
+    #[serde(default)]
+    token: Option<String>,
 }
 
 /// The dapr entry model maps the results keys from the dapr state store.
 ///
 /// # Fields
-/// * `data` - A singular account entity
+/// * `key` - The state key of the entry
+/// * `data` - The stored value, an account unless the key is auxiliary
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
 struct Entry {
-    data: AccountEntity,
+    key: String,
+    data: Value,
+}
+
+/// Build the dapr query of an account listing.
+///
+/// Name and suspended status filters are exact matches on the stored
+/// account. Accounts stored before the status field existed have no
+/// status and are active, so the active status filter is left to the
+/// caller. The email filter is not part of the query, it is answered
+/// from the email index instead.
+///
+/// # Arguments
+/// * `query` - The account query
+///
+/// # Returns
+/// The dapr query body
+fn query_body(query: &AccountQuery) -> Value {
+    // Combine the filters
+    let mut conditions = vec![];
+    if let Some(name) = &query.name {
+        conditions.push(json!({ "EQ": { "name": name } }));
+    }
+    if let Some(status) = query
+        .status
+        .filter(|status| *status != AccountStatus::Active)
+    {
+        conditions.push(json!({ "EQ": { "status": status.as_str() } }));
+    }
+    let filter = match conditions.len() {
+        0 => json!({}),
+        1 => conditions.remove(0),
+        _ => json!({ "AND": conditions }),
+    };
+
+    // Sort and page the results
+    let order = match query.order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+    let mut page = json!({ "limit": query.limit });
+    if let Some(token) = &query.page_token {
+        page["token"] = json!(token);
+    }
+    json!({
+        "filter": filter,
+        "sort": [{ "key": query.sort.key(), "order": order }],
+        "page": page,
+    })
 }
 
 /// The dapr account dao.
 ///
-/// This dao is used to access the dapr state store.
+/// This dao is used to access the dapr state store. Every account
+/// is stored under its id next to an `email:<normalized email>`
+/// index entry, and both are always written in one transaction.
+/// Lookups by id and email only use plain key reads, so they work
+/// on any dapr state store. Only listing accounts needs the query api.
+///
+/// # Fields
+/// * `store` - The dapr state store, shared with the refresh token dao
+/// * `hasher` - The password hasher
 ///
 /// # Methods
-/// * `new` - Creates a new dapr account dao
-/// * `get_accounts` - Gets all accounts from the dapr state store
+/// * `new` - Creates a new dapr account dao for the configured sidecar
+/// * `with_sidecar` - Creates a new dapr account dao for the given sidecar
+/// * `with_password_hasher` - Replaces the password hasher
+/// * `refresh_token_dao` - Creates a refresh token dao on the same state store
+/// * `action_token_dao` - Creates an action token dao on the same state store
+/// * `login_attempts_dao` - Creates a login attempts dao on the same state store
+/// * `get_email_index` - Gets the email index entry of an email
+/// * `query_accounts` - Queries accounts from the dapr state store
+/// * `backfill_email_index` - Adds missing email index entries
+/// * `get_accounts` - Gets a page of accounts from the dapr state store
 /// * `get_account_by_id` - Gets an account by id from the dapr state store
 /// * `get_account_by_email` - Gets an account by email from the dapr state store
 /// * `validate_account` - Validates an account in the dapr state store
 /// * `create_account` - Creates an account in the dapr state store
 /// * `update_account` - Updates an account in the dapr state store
+/// * `change_password` - Changes the password of an account in the dapr state store
+/// * `reset_password` - Sets the password of an account in the dapr state store
+/// * `verify_email` - Marks the email of an account in the dapr state store as verified
 /// * `delete_account` - Deletes an account in the dapr state store
 ///
 /// # Traits
 /// * `AccountDao` - The account dao trait
-pub struct DaprAccountDao {}
+pub struct DaprAccountDao {
+    store: Arc<DaprStateStore>,
+    hasher: PasswordHasher,
+}
 
 /// The dapr account dao implementation.
 impl DaprAccountDao {
-    /// Creates a new dapr account dao.
+    /// Creates a new dapr account dao for the configured sidecar.
+    ///
+    /// The sidecar port and state store name are read from the
+    /// `STATE_STORE_PORT` and `STATE_STORE_NAME` environment variables.
+    ///
+    /// # Arguments
+    /// * `config` - The dapr client configuration
     ///
     /// # Returns
     /// The new dapr account dao
-    pub fn new() -> Self {
-        DaprAccountDao {}
+    pub fn new(config: DaprClientConfig) -> AccountResult<Self> {
+        DaprAccountDao::with_sidecar(sidecar_url(), get_state_store_name(), config)
     }
 
-    /// Hash a password using bcrypt.
+    /// Creates a new dapr account dao for the given sidecar.
     ///
     /// # Arguments
-    /// * `password` - The password to hash
+    /// * `sidecar_url` - The base url of the dapr sidecar, e.g. `http://localhost:3500`
+    /// * `store_name` - The name of the dapr state store
+    /// * `config` - The dapr client configuration
     ///
     /// # Returns
-    /// The hashed password
-    pub fn hash_password(&self, password: String) -> String {
-        bcrypt::hash(password).unwrap()
+    /// The new dapr account dao
+    pub fn with_sidecar(
+        sidecar_url: impl Into<String>,
+        store_name: impl Into<String>,
+        config: DaprClientConfig,
+    ) -> AccountResult<Self> {
+        Ok(DaprAccountDao {
+            store: Arc::new(DaprStateStore::new(sidecar_url, store_name, config)?),
+            hasher: PasswordHasher::default(),
+        })
     }
 
-    /// Validate a password using bcrypt.
+    /// Replaces the password hasher.
     ///
     /// # Arguments
-    /// * `password` - The password to validate
-    /// * `hash` - The password hash
+    /// * `hasher` - The password hasher to use
     ///
     /// # Returns
-    /// True if the password is valid
-    pub fn validate_password(&self, password: String, hash: &str) -> bool {
-        bcrypt::verify(password, hash)
+    /// The dao using the hasher
+    pub fn with_password_hasher(self, hasher: PasswordHasher) -> Self {
+        DaprAccountDao { hasher, ..self }
     }
 
-    /// Save an account to the dapr state store.
+    /// Creates a refresh token dao on the same state store.
     ///
-    /// # Arguments
-    /// * `account` - The account to save
+    /// # Returns
+    /// The refresh token dao, sharing the client of this dao
+    pub fn refresh_token_dao(&self) -> DaprRefreshTokenDao {
+        DaprRefreshTokenDao::new(self.store.clone())
+    }
+
+    /// Creates an action token dao on the same state store.
     ///
     /// # Returns
-    /// True if the account was saved successfully
-    pub async fn save_account(&self, account: AccountEntity) -> bool {
-        // Reqwest client
-        let client = ClientBuilder::new().build().unwrap();
+    /// The action token dao, sharing the client of this dao
+    pub fn action_token_dao(&self) -> DaprActionTokenDao {
+        DaprActionTokenDao::new(self.store.clone())
+    }
 
-        // Hash the password in the account
-        let hashed_account = AccountEntity {
-            password: self.hash_password(account.password),
-            ..account
-        };
+    /// Creates a login attempts dao on the same state store.
+    ///
+    /// # Returns
+    /// The login attempts dao, sharing the client of this dao
+    pub fn login_attempts_dao(&self) -> DaprLoginAttemptsDao {
+        DaprLoginAttemptsDao::new(self.store.clone())
+    }
 
-        // Post if account creation is successful
-        client
-            // Post to the url
-            .post(get_sidecar_url())
-            // Add body to the post request
-            .body(
-                json!(
-                    [
-                        {
-                            "key": hashed_account.id,
-                            "value": hashed_account,
-                        },
-                    ]
-                )
-                .to_string(),
+    /// Get the email index entry of an email.
+    ///
+    /// # Arguments
+    /// * `email` - The email, normalized before lookup
+    ///
+    /// # Returns
+    /// The index entry and its etag, or `None` if no account owns the email
+    async fn get_email_index(
+        &self,
+        email: &str,
+    ) -> AccountResult<Option<(EmailIndexEntry, Option<String>)>> {
+        match self.store.get_state(&email_index_key(email)).await {
+            Ok(entry) => Ok(Some(entry)),
+            Err(AccountError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Query accounts from the dapr state store.
+    ///
+    /// Auxiliary records such as email index entries are skipped,
+    /// so a page may hold fewer accounts than its limit.
+    ///
+    /// # Arguments
+    /// * `body` - The dapr query
+    ///
+    /// # Returns
+    /// The matching account entities and the token of the next page
+    async fn query_accounts(
+        &self,
+        body: Value,
+    ) -> AccountResult<(Vec<AccountEntity>, Option<String>)> {
+        // Get all matching data from dapr
+        let client = self.store.client();
+        let results = client
+            .send(
+                client
+                    // Post to the query url
+                    .post(self.store.query_url())
+                    // Add body to the post request
+                    .body(body.to_string()),
+                true,
             )
-            // Send the request
-            .send()
-            .await
-            .unwrap()
-            // Check if the request was successful
-            .status()
-            .is_success()
+            .await?
+            // Get the json response and map to DaprResults
+            .json::<DaprResults>()
+            .await?;
+
+        // Map account entries to entities
+        let accounts = results
+            .results
+            .into_iter()
+            .filter(|entry: &Entry| !is_auxiliary_key(&entry.key))
+            .map(|entry: Entry| Ok(serde_json::from_value(entry.data)?))
+            .collect::<AccountResult<Vec<AccountEntity>>>()?;
+        let token = results.token.filter(|token| !token.is_empty());
+        Ok((accounts, token))
+    }
+
+    /// Add missing email index entries.
+    ///
+    /// Accounts stored before the email index existed cannot be found
+    /// by email. This claims the email of every such account. It uses
+    /// the query api, so it only runs on query capable state stores.
+    ///
+    /// # Returns
+    /// The number of index entries added
+    pub async fn backfill_email_index(&self) -> AccountResult<usize> {
+        let mut added = 0;
+        let (accounts, _) = self.query_accounts(json!({ "filter": {} })).await?;
+        for account in accounts {
+            // Skip accounts whose email is already claimed
+            if self.get_email_index(&account.email).await?.is_some() {
+                continue;
+            }
+
+            // Claim the email, losing a race to a concurrent claim is fine
+            let index = EmailIndexEntry {
+                account_id: account.id.clone(),
+            };
+            match self
+                .store
+                .transact(vec![upsert_operation(
+                    &email_index_key(&account.email),
+                    json!(index),
+                    None,
+                )])
+                .await
+            {
+                Ok(()) => added += 1,
+                Err(AccountError::PreconditionFailed) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(added)
     }
 }
 
 /// The dapr account dao implementation.
 #[async_trait]
 impl AccountDao for DaprAccountDao {
-    /// Gets all accounts from the dapr state store.
+    /// Gets a page of accounts from the dapr state store.
+    ///
+    /// Page tokens are the opaque tokens of the dapr query api. Auxiliary
+    /// records share the state store and take up room in dapr pages, so
+    /// pages are queried until the limit is reached or no results are left.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, sort order and page to get
     ///
     /// # Returns
-    /// A vector of account entities
-    async fn get_accounts(&self) -> Vec<AccountEntity> {
-        // Reqwest client
-        let client = ClientBuilder::new().build().unwrap();
-
-        // Empty list of entities
-        let mut entities: Vec<AccountEntity> = vec![];
-
-        // Get all data from dapr and map to entities
-        client
-            // Post to the url
-            .post(get_sidecar_query_url())
-            // Add body to the post request
-            .body(
-                json!(
-                    {
-                        "filter": {},
-                    }
-                )
-                .to_string(),
-            )
-            // Send the request
-            .send()
-            .await
-            .unwrap()
-            // Get the json response and map to DaprResults
-            .json::<DaprResults>()
-            .await
-            .unwrap_or(DaprResults { results: vec![] })
-            // Loop through all results and add to entities
-            .results
-            .iter()
-            .for_each(|entry: &Entry| entities.push(entry.data.clone()));
+    /// The page of matching accounts
+    async fn get_accounts(&self, query: AccountQuery) -> AccountResult<AccountPage> {
+        // An email matches at most one account, look it up in the index
+        if let Some(email) = &query.email {
+            let items = match self.get_account_by_email(email.clone()).await {
+                Ok(account) if query.matches(&account) => vec![account],
+                Ok(_) | Err(AccountError::NotFound) => vec![],
+                Err(e) => return Err(e),
+            };
+            return Ok(AccountPage {
+                items,
+                next_page_token: None,
+            });
+        }
 
-        // Return entities
-        entities
+        // Query only as many results as are missing, so the last token
+        // continues right after the last result taken, and apply the
+        // filters the store does not answer
+        let mut items = vec![];
+        let mut next_page_token = query.page_token.clone();
+        loop {
+            let remaining = AccountQuery {
+                limit: query.limit - items.len(),
+                page_token: next_page_token,
+                ..query.clone()
+            };
+            let (accounts, token) = self.query_accounts(query_body(&remaining)).await?;
+            items.extend(
+                accounts
+                    .into_iter()
+                    .filter(|account| query.matches(account)),
+            );
+            next_page_token = token;
+            if items.len() >= query.limit || next_page_token.is_none() {
+                break;
+            }
+        }
+        Ok(AccountPage {
+            items,
+            next_page_token,
+        })
     }
 
     /// Gets an account by id from the dapr state store.
@@ -220,27 +375,10 @@ impl AccountDao for DaprAccountDao {
     /// * `id` - The account id
     ///
     /// # Returns
-    /// An optional account entity
-    async fn get_account_by_id(&self, id: String) -> Option<AccountEntity> {
-        // Create the url
-        let url = format!("{}/{}", get_sidecar_url(), id);
-
-        // Reqwest client
-        let client = ClientBuilder::new().build().unwrap();
-
-        // Get account from dapr
-        client
-            // Get request on the url
-            .get(url)
-            // Send the request
-            .send()
-            .await
-            .unwrap()
-            // Get the json response and map to AccountEntity
-            .json::<AccountEntity>()
-            .await
-            // Return the entity
-            .ok()
+    /// The account entity
+    async fn get_account_by_id(&self, id: String) -> AccountResult<AccountEntity> {
+        let (account, etag): (AccountEntity, Option<String>) = self.store.get_state(&id).await?;
+        Ok(AccountEntity { etag, ..account })
     }
 
     /// Gets an account by email from the dapr state store.
@@ -249,139 +387,451 @@ impl AccountDao for DaprAccountDao {
     /// * `email` - The account email
     ///
     /// # Returns
-    /// An optional account entity
-    async fn get_account_by_email(&self, email: String) -> Option<AccountEntity> {
-        // Reqwest client
-        let client = ClientBuilder::new().build().unwrap();
-
-        // Get first entry from dapr
-        let first_entry = client
-            // Post to the query url
-            .post(get_sidecar_query_url())
-            // Add body to the post request
-            .body(
-                json!(
-                    {
-                        "filter": {
-                            "EQ": { "email": email }
-                        }
-                    }
-                )
-                .to_string(),
-            )
-            // Send the request
-            .send()
-            .await
-            .unwrap()
-            // Get the json response and map to DaprResults
-            .json::<DaprResults>()
-            .await
-            .unwrap_or(DaprResults { results: vec![] })
-            // Get the first entry
-            .results
-            .first()
-            // Clone the entry
-            .cloned();
-
-        // Return account entity if exists
-        match first_entry {
-            Some(entry) => Some(entry.data),
-            None => None,
+    /// The account entity
+    async fn get_account_by_email(&self, email: String) -> AccountResult<AccountEntity> {
+        // Look up the owner of the email in the index
+        let (entry, _) = self
+            .get_email_index(&email)
+            .await?
+            .ok_or(AccountError::NotFound)?;
+
+        // Get the owner, ignoring an index entry that no longer matches
+        let account = self.get_account_by_id(entry.account_id).await?;
+        if normalize_email(&account.email) == normalize_email(&email) {
+            Ok(account)
+        } else {
+            Err(AccountError::NotFound)
         }
     }
 
     /// Validates an account in the dapr state store.
     ///
+    /// An outdated password hash is replaced on success. The upgrade
+    /// only applies to the version that was read and a failure is
+    /// ignored, the next login simply tries again.
+    ///
     /// # Arguments
     /// * `email` - The account email
     /// * `password` - The account password
     ///
     /// # Returns
-    /// An optional account entity
-    async fn validate_account(&self, email: String, password: String) -> Option<AccountEntity> {
+    /// The account entity
+    async fn validate_account(
+        &self,
+        email: String,
+        password: String,
+    ) -> AccountResult<AccountEntity> {
         // Get an account by email
-        match self.get_account_by_email(email).await {
-            // Check if account exists
-            Some(account) => {
-                // Check if password matches
-                if self.validate_password(password, account.password.clone().as_str()) {
-                    // Return valid account
-                    Some(account)
-                } else {
-                    // Invalid credentials, return none
-                    None
-                }
+        let account = match self.get_account_by_email(email).await {
+            Ok(account) => account,
+            // Account with email does not exist, take as long as a wrong password
+            Err(AccountError::NotFound) => {
+                self.hasher.verify_dummy(password).await?;
+                return Err(AccountError::InvalidCredentials);
             }
-            // Account with email does not exist
-            None => None,
+            Err(e) => return Err(e),
+        };
+
+        // Check if password matches
+        let password = match self
+            .hasher
+            .verify(password, account.password.clone())
+            .await?
+        {
+            Verification::Invalid => return Err(AccountError::InvalidCredentials),
+            Verification::Valid => return Ok(account),
+            Verification::Upgraded(password) => password,
+        };
+
+        // Store the upgraded hash over the version that was read
+        let upgraded = AccountEntity {
+            password,
+            etag: None,
+            ..account.clone()
+        };
+        let stored = self
+            .store
+            .transact(vec![upsert_operation(
+                &upgraded.id,
+                json!(upgraded),
+                account.etag.clone(),
+            )])
+            .await;
+        match stored {
+            Ok(()) => Ok(upgraded),
+            Err(_) => Ok(account),
         }
     }
 
     /// Creates an account in the dapr state store.
     ///
+    /// The account and its email index entry are inserted in one
+    /// transaction that fails if either key already exists.
+    ///
     /// # Arguments
     /// * `account` - The account entity
     ///
     /// # Returns
-    /// A boolean indicating if the account was created
-    async fn create_account(&self, account: AccountEntity) -> bool {
-        // Check if account exists with email
-        if self
-            .get_account_by_email(account.email.clone())
-            .await
-            .is_some()
-        {
-            return false;
-        }
+    /// Nothing if the account was created
+    async fn create_account(&self, account: AccountEntity) -> AccountResult<()> {
+        // Hash the password in the account
+        let hashed_account = AccountEntity {
+            password: self.hasher.hash(account.password).await?,
+            etag: None,
+            ..account
+        };
+        let index = EmailIndexEntry {
+            account_id: hashed_account.id.clone(),
+        };
 
-        self.save_account(account).await
+        // Insert the account and claim its email together
+        let result = self
+            .store
+            .transact(vec![
+                upsert_operation(&hashed_account.id, json!(hashed_account), None),
+                upsert_operation(&email_index_key(&hashed_account.email), json!(index), None),
+            ])
+            .await;
+
+        // Find out which key was taken if the transaction failed. An unavailable
+        // store may have committed the transaction and only lost the reply, so the
+        // account written here, known by its freshly salted hash, is no conflict.
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                let stored = match self.get_account_by_id(hashed_account.id.clone()).await {
+                    Ok(stored) => Some(stored),
+                    Err(AccountError::NotFound) => None,
+                    Err(e) => return Err(e),
+                };
+                if let Some(stored) = stored {
+                    let committed = stored.password == hashed_account.password
+                        && stored.instance_id == hashed_account.instance_id;
+                    if committed {
+                        Ok(())
+                    } else {
+                        Err(AccountError::Conflict("id already in use".to_string()))
+                    }
+                } else if self.get_email_index(&hashed_account.email).await?.is_some() {
+                    Err(AccountError::Conflict("email already in use".to_string()))
+                } else {
+                    result
+                }
+            }
+            result => result,
+        }
     }
 
     /// Updates an account in the dapr state store.
     ///
+    /// When the email changes the new email is claimed and the old one
+    /// released in the same transaction as the account update. The
+    /// stored password hash and roles are kept.
+    ///
     /// # Arguments
     /// * `account` - The account entity
     ///
     /// # Returns
-    /// A boolean indicating if the account was updated
-    async fn update_account(&self, account: AccountEntity) -> bool {
-        // Return false if account not found
-        if self.get_account_by_id(account.id.clone()).await.is_none() {
-            return false;
+    /// Nothing if the account was updated
+    async fn update_account(&self, account: AccountEntity) -> AccountResult<()> {
+        // Fail if account not found
+        let current = self.get_account_by_id(account.id.clone()).await?;
+
+        // Only overwrite the version the caller expects, or the one just read.
+        // The password hash, status, roles, two-factor settings and instance id are kept,
+        // they are not part of the profile, and so is the email verification unless the
+        // email changes.
+        let etag = account.etag.clone().or(current.etag.clone());
+        let email_changed = normalize_email(&current.email) != normalize_email(&account.email);
+        let updated = AccountEntity {
+            password: current.password.clone(),
+            status: current.status,
+            roles: current.roles.clone(),
+            email_verified: current.email_verified && !email_changed,
+            email_verified_at: current.email_verified_at.filter(|_| !email_changed),
+            mfa: current.mfa.clone(),
+            instance_id: current.instance_id.clone(),
+            etag: None,
+            ..account
+        };
+        let mut operations = vec![upsert_operation(&updated.id, json!(updated), etag.clone())];
+
+        // Move the email index entry if the email changes
+        if email_changed {
+            let index = EmailIndexEntry {
+                account_id: updated.id.clone(),
+            };
+            operations.push(upsert_operation(
+                &email_index_key(&updated.email),
+                json!(index),
+                None,
+            ));
+            if let Some((entry, index_etag)) = self.get_email_index(&current.email).await? {
+                if entry.account_id == updated.id {
+                    operations.push(delete_operation(
+                        &email_index_key(&current.email),
+                        index_etag,
+                    ));
+                }
+            }
+        }
+
+        // Find out what changed if the transaction failed
+        let result = self.store.transact(operations).await;
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                let latest = self.get_account_by_id(updated.id.clone()).await?;
+                if latest.etag != etag {
+                    Err(AccountError::PreconditionFailed)
+                } else if email_changed && self.get_email_index(&updated.email).await?.is_some() {
+                    Err(AccountError::Conflict("email already in use".to_string()))
+                } else {
+                    result
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Changes the password of an account in the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `current_password` - The current password of the account
+    /// * `new_password` - The password to set
+    ///
+    /// # Returns
+    /// Nothing if the password was changed
+    async fn change_password(
+        &self,
+        id: String,
+        current_password: String,
+        new_password: String,
+    ) -> AccountResult<()> {
+        // Fail if account not found
+        let current = self.get_account_by_id(id).await?;
+
+        // Check the current password
+        let verification = self
+            .hasher
+            .verify(current_password, current.password.clone())
+            .await?;
+        if verification == Verification::Invalid {
+            return Err(AccountError::InvalidCredentials);
         }
 
-        self.save_account(account).await
+        // Store the new hash over the version that was checked
+        let updated = AccountEntity {
+            password: self.hasher.hash(new_password).await?,
+            etag: None,
+            ..current.clone()
+        };
+        let result = self
+            .store
+            .transact(vec![upsert_operation(
+                &updated.id,
+                json!(updated),
+                current.etag.clone(),
+            )])
+            .await;
+
+        // Find out if the account changed if the transaction failed
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                let latest = self.get_account_by_id(updated.id.clone()).await?;
+                if latest.etag != current.etag {
+                    Err(AccountError::PreconditionFailed)
+                } else {
+                    result
+                }
+            }
+            result => result,
+        }
     }
 
-    /// Deletes an account in the dapr state store.
+    /// Sets the password of an account in the dapr state store.
+    ///
+    /// Concurrent changes of the account are retried over, a reset
+    /// password must not be lost to a profile update.
     ///
     /// # Arguments
     /// * `id` - The account id
+    /// * `new_password` - The password to set
     ///
     /// # Returns
-    /// A boolean indicating if the account was deleted
-    async fn delete_account(&self, id: String) -> bool {
-        // Create the url
-        let url = format!("{}/{}", get_sidecar_url(), id);
+    /// Nothing if the password was set
+    async fn reset_password(&self, id: String, new_password: String) -> AccountResult<()> {
+        let password = self.hasher.hash(new_password).await?;
+        let mut result = Err(AccountError::PreconditionFailed);
+        for _ in 0..WRITE_ATTEMPTS {
+            // Fail if account not found
+            let current = self.get_account_by_id(id.clone()).await?;
+
+            // Store the new hash over the version just read
+            let updated = AccountEntity {
+                password: password.clone(),
+                etag: None,
+                ..current.clone()
+            };
+            result = self
+                .store
+                .transact(vec![upsert_operation(
+                    &updated.id,
+                    json!(updated),
+                    current.etag.clone(),
+                )])
+                .await;
+
+            // Try again if the account changed, otherwise report the failure
+            match result {
+                Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                    let latest = self.get_account_by_id(id.clone()).await?;
+                    if latest.etag == current.etag {
+                        return result;
+                    }
+                }
+                result => return result,
+            }
+        }
+        result
+    }
 
-        // Reqwest client
-        let client = ClientBuilder::new().build().unwrap();
+    /// Marks the email of an account in the dapr state store as verified.
+    ///
+    /// Concurrent changes of the account are retried over, unless
+    /// they change the email.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `email` - The email that was verified
+    /// * `verified_at` - The verification time, in seconds since the epoch
+    ///
+    /// # Returns
+    /// Nothing if the email is verified
+    async fn verify_email(&self, id: String, email: String, verified_at: u64) -> AccountResult<()> {
+        let mut result = Err(AccountError::PreconditionFailed);
+        for _ in 0..WRITE_ATTEMPTS {
+            // Only the current email can be verified, and only once
+            let current = self.get_account_by_id(id.clone()).await?;
+            if normalize_email(&current.email) != normalize_email(&email) {
+                return Err(AccountError::PreconditionFailed);
+            }
+            if current.email_verified {
+                return Ok(());
+            }
+
+            // Store the verification over the version just read
+            let updated = AccountEntity {
+                email_verified: true,
+                email_verified_at: Some(verified_at),
+                etag: None,
+                ..current.clone()
+            };
+            result = self
+                .store
+                .transact(vec![upsert_operation(
+                    &updated.id,
+                    json!(updated),
+                    current.etag.clone(),
+                )])
+                .await;
+
+            // Try again if the account changed, otherwise report the failure
+            match result {
+                Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                    let latest = self.get_account_by_id(id.clone()).await?;
+                    if latest.etag == current.etag {
+                        return result;
+                    }
+                }
+                result => return result,
+            }
+        }
+        result
+    }
+
+    /// Replaces the two-factor settings of an account in the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `mfa` - The two-factor settings to store
+    /// * `etag` - The version the settings were read at, if any
+    ///
+    /// # Returns
+    /// Nothing if the settings were stored
+    async fn set_mfa(
+        &self,
+        id: String,
+        mfa: MfaSettings,
+        etag: Option<String>,
+    ) -> AccountResult<()> {
+        // Fail if account not found
+        let current = self.get_account_by_id(id.clone()).await?;
+
+        // Only overwrite the version the caller expects, or the one just read
+        let etag = etag.or(current.etag.clone());
+        let updated = AccountEntity {
+            mfa,
+            etag: None,
+            ..current
+        };
+        let operations = vec![upsert_operation(&updated.id, json!(updated), etag.clone())];
+
+        // Find out if the account changed if the transaction failed
+        let result = self.store.transact(operations).await;
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                let latest = self.get_account_by_id(id).await?;
+                if latest.etag != etag {
+                    Err(AccountError::PreconditionFailed)
+                } else {
+                    result
+                }
+            }
+            result => result,
+        }
+    }
 
-        // return false if account not found
-        if self.get_account_by_id(id.clone()).await.is_none() {
-            return false;
+    /// Deletes an account in the dapr state store.
+    ///
+    /// The email index entry of the account is deleted in the same transaction.
+    /// An account gone once the transaction failed counts as deleted, the
+    /// store may have committed it and only lost the reply.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `etag` - The expected version of the account, if any
+    ///
+    /// # Returns
+    /// Nothing if the account was deleted, `NotFound` if it never existed
+    async fn delete_account(&self, id: String, etag: Option<String>) -> AccountResult<()> {
+        // Fail if account not found
+        let current = self.get_account_by_id(id.clone()).await?;
+
+        // Delete account, guarded by the expected version
+        let etag = etag.or(current.etag.clone());
+        let mut operations = vec![delete_operation(&id, etag.clone())];
+
+        // Release the email of the account
+        if let Some((entry, index_etag)) = self.get_email_index(&current.email).await? {
+            if entry.account_id == id {
+                operations.push(delete_operation(
+                    &email_index_key(&current.email),
+                    index_etag,
+                ));
+            }
         }
 
-        // Delete account if exists
-        client
-            // Delete to the url
-            .delete(url)
-            // Send the request
-            .send()
-            .await
-            .unwrap()
-            // Check if the request was successful
-            .status()
-            .is_success()
+        // Find out if the account changed or is gone already if the transaction failed
+        let result = self.store.transact(operations).await;
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                match self.get_account_by_id(id).await {
+                    Ok(latest) if latest.etag != etag => Err(AccountError::PreconditionFailed),
+                    Err(AccountError::NotFound) => Ok(()),
+                    _ => result,
+                }
+            }
+            result => result,
+        }
     }
 }
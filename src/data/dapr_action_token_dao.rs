@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use super::action_token_dao::ActionTokenDao;
+use super::action_token_entity::{action_token_key, ActionTokenEntity};
+use super::dapr_state_store::{
+    delete_operation, ttl_until, upsert_operation, with_ttl, DaprStateStore,
+};
+use crate::errors::{AccountError, AccountResult};
+use rocket::{async_trait, serde::json::serde_json::json};
+
+/// The dapr action token dao.
+///
+/// Tokens are stored under `action:<hash>`, next to the accounts in
+/// the same state store, and expire with the state store ttl where
+/// supported. Using a token deletes it over the version that was read.
+///
+/// # Fields
+/// * `store` - The dapr state store, shared with the account dao
+///
+/// # Methods
+/// * `new` - Creates a new dapr action token dao
+/// * `create_action_token` - Creates an action token in the dapr state store
+/// * `consume_action_token` - Removes an action token from the dapr state store
+///
+/// # Traits
+/// * `ActionTokenDao` - The action token dao trait
+pub struct DaprActionTokenDao {
+    store: Arc<DaprStateStore>,
+}
+
+/// The dapr action token dao implementation.
+impl DaprActionTokenDao {
+    /// Creates a new dapr action token dao.
+    ///
+    /// # Arguments
+    /// * `store` - The dapr state store
+    ///
+    /// # Returns
+    /// The new dapr action token dao
+    pub fn new(store: Arc<DaprStateStore>) -> Self {
+        DaprActionTokenDao { store }
+    }
+}
+
+/// The dapr action token dao implementation.
+#[async_trait]
+impl ActionTokenDao for DaprActionTokenDao {
+    /// Creates an action token in the dapr state store.
+    ///
+    /// # Arguments
+    /// * `token` - The new token
+    ///
+    /// # Returns
+    /// Nothing if the token was stored
+    async fn create_action_token(&self, token: ActionTokenEntity) -> AccountResult<()> {
+        let key = action_token_key(&token.id);
+        let result = self
+            .store
+            .transact(vec![with_ttl(
+                upsert_operation(&key, json!(token), None),
+                ttl_until(token.expires_at),
+            )])
+            .await;
+
+        // Find out if the token was taken if the transaction failed
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                match self.store.get_state::<ActionTokenEntity>(&key).await {
+                    Ok(_) => Err(AccountError::Conflict(
+                        "action token already in use".to_string(),
+                    )),
+                    Err(AccountError::NotFound) => result,
+                    Err(e) => Err(e),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Removes an action token from the dapr state store and returns it.
+    ///
+    /// # Arguments
+    /// * `id` - The hash of the token
+    ///
+    /// # Returns
+    /// The removed token entity
+    async fn consume_action_token(&self, id: String) -> AccountResult<ActionTokenEntity> {
+        let key = action_token_key(&id);
+        let (token, etag): (ActionTokenEntity, Option<String>) = self.store.get_state(&key).await?;
+
+        // Delete the version that was read, only one caller can win
+        let result = self
+            .store
+            .transact(vec![delete_operation(&key, etag.clone())])
+            .await;
+
+        // A token that changed or is gone was used by someone else
+        match result {
+            Ok(()) => Ok(ActionTokenEntity { etag, ..token }),
+            Err(e @ (AccountError::PreconditionFailed | AccountError::StoreUnavailable(_))) => {
+                match self.store.get_state::<ActionTokenEntity>(&key).await {
+                    Ok((_, latest)) if latest == etag => Err(e),
+                    Ok(_) | Err(AccountError::NotFound) => Err(AccountError::NotFound),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
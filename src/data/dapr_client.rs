@@ -0,0 +1,292 @@
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::errors::{AccountError, AccountResult};
+use reqwest::{Client, ClientBuilder, IntoUrl, RequestBuilder, Response, StatusCode};
+use ring::rand::{SecureRandom, SystemRandom};
+use rocket::serde::Deserialize;
+
+/// Get the url of the local dapr sidecar.
+///
+/// # Returns
+/// The base url, with the port from `STATE_STORE_PORT` or 3500
+pub fn sidecar_url() -> String {
+    let port = match env::var("STATE_STORE_PORT") {
+        Ok(val) => val,
+        Err(_e) => "3500".to_string(),
+    };
+    format!("http://localhost:{}", port)
+}
+
+/// The dapr client configuration.
+///
+/// # Fields
+/// * `connect_timeout_ms` - The time allowed to connect to the sidecar
+/// * `request_timeout_ms` - The time allowed for a whole request
+/// * `max_retries` - The retries of a failed idempotent request
+/// * `retry_backoff_ms` - The base delay between retries, doubled on every retry
+/// * `breaker_threshold` - The consecutive sidecar failures that open the circuit
+/// * `breaker_cooldown_ms` - The time the circuit stays open
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct DaprClientConfig {
+    pub connect_timeout_ms: u64,
+    pub request_timeout_ms: u64,
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_ms: u64,
+}
+
+/// The default dapr client configuration.
+impl Default for DaprClientConfig {
+    fn default() -> Self {
+        DaprClientConfig {
+            connect_timeout_ms: 1_000,
+            request_timeout_ms: 5_000,
+            max_retries: 2,
+            retry_backoff_ms: 50,
+            breaker_threshold: 5,
+            breaker_cooldown_ms: 10_000,
+        }
+    }
+}
+
+/// The circuit breaker state.
+///
+/// # Fields
+/// * `failures` - The consecutive sidecar failures
+/// * `open_until` - The end of the cooldown, if the circuit is open
+/// * `probe_started` - The start of the probe after the cooldown, if one is in flight
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    probe_started: Option<Instant>,
+}
+
+/// The dapr client.
+///
+/// Holds one pooled http client for every request to the sidecar.
+/// Idempotent requests are retried with jittered exponential backoff
+/// when the sidecar cannot be reached, times out or answers with 502,
+/// 503 or 504. After `breaker_threshold` such failures in a row the
+/// circuit opens and requests fail fast until the cooldown has passed.
+/// The next request then probes the sidecar while all others still
+/// fail fast: success closes the circuit, failure opens it again. A
+/// probe that has not been recorded within the request timeout is
+/// given up, so the next request probes instead.
+///
+/// # Fields
+/// * `client` - The pooled http client
+/// * `config` - The client configuration
+/// * `breaker` - The circuit breaker state
+///
+/// # Methods
+/// * `new` - Creates a new dapr client
+/// * `get` - Starts a get request
+/// * `post` - Starts a post request
+/// * `send` - Sends a request, with retries if it is idempotent
+/// * `check_circuit` - Fails fast while the circuit is open
+/// * `record` - Records the health of the sidecar
+/// * `backoff` - Gets the delay before a retry
+pub struct DaprClient {
+    client: Client,
+    config: DaprClientConfig,
+    breaker: Mutex<BreakerState>,
+}
+
+/// The dapr client implementation.
+impl DaprClient {
+    /// Creates a new dapr client.
+    ///
+    /// # Arguments
+    /// * `config` - The client configuration
+    ///
+    /// # Returns
+    /// The new dapr client, or an internal error if the http client could not be built
+    pub fn new(config: DaprClientConfig) -> AccountResult<Self> {
+        let client = ClientBuilder::new()
+            .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+            .timeout(Duration::from_millis(config.request_timeout_ms))
+            .build()
+            .map_err(|e| AccountError::Internal(e.to_string()))?;
+        Ok(DaprClient {
+            client,
+            config,
+            breaker: Mutex::new(BreakerState::default()),
+        })
+    }
+
+    /// Starts a get request.
+    ///
+    /// # Arguments
+    /// * `url` - The url to get
+    ///
+    /// # Returns
+    /// The request builder
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Starts a post request.
+    ///
+    /// # Arguments
+    /// * `url` - The url to post to
+    ///
+    /// # Returns
+    /// The request builder
+    pub fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// Sends a request to the dapr sidecar.
+    ///
+    /// # Arguments
+    /// * `request` - The request to send
+    /// * `idempotent` - True if the request may be sent again after a failure
+    ///
+    /// # Returns
+    /// The response, `PreconditionFailed` if dapr rejected an etag, or
+    /// `StoreUnavailable` if the sidecar could not be reached, the circuit
+    /// is open or the sidecar did not answer with a success status
+    pub async fn send(&self, request: RequestBuilder, idempotent: bool) -> AccountResult<Response> {
+        let attempts = if idempotent {
+            self.config.max_retries + 1
+        } else {
+            1
+        };
+        let mut attempt = 1;
+        loop {
+            self.check_circuit()?;
+
+            // Send a copy so the request can be retried
+            let outcome = request
+                .try_clone()
+                .ok_or_else(|| AccountError::Internal("request cannot be cloned".to_string()))?
+                .send()
+                .await;
+
+            // Only transport failures and gateway errors mean the sidecar is unhealthy
+            let unhealthy = match &outcome {
+                Ok(response) => matches!(
+                    response.status(),
+                    StatusCode::BAD_GATEWAY
+                        | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT
+                ),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+            self.record(!unhealthy);
+
+            if !unhealthy || attempt >= attempts {
+                return check_status(outcome?);
+            }
+            rocket::tokio::time::sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Fails fast while the circuit is open.
+    ///
+    /// Once the cooldown has passed, only one request at a time is let
+    /// through to probe the sidecar.
+    ///
+    /// # Returns
+    /// Nothing if requests may be sent, otherwise `StoreUnavailable`
+    fn check_circuit(&self) -> AccountResult<()> {
+        let mut breaker = self.breaker.lock().unwrap();
+        let now = Instant::now();
+        let open = match (breaker.open_until, breaker.probe_started) {
+            // Closed circuits let every request through
+            (None, _) => false,
+            // Open circuits fail fast until the cooldown has passed
+            (Some(open_until), _) if now < open_until => true,
+            // Half open circuits fail fast while a probe is in flight
+            (_, Some(probe_started))
+                if now < probe_started + Duration::from_millis(self.config.request_timeout_ms) =>
+            {
+                true
+            }
+            // Otherwise this request is the probe
+            _ => {
+                breaker.probe_started = Some(now);
+                false
+            }
+        };
+        if open {
+            return Err(AccountError::StoreUnavailable(
+                "dapr sidecar circuit is open".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Records the health of the sidecar.
+    ///
+    /// # Arguments
+    /// * `healthy` - True if the sidecar answered the last request properly
+    fn record(&self, healthy: bool) {
+        let mut breaker = self.breaker.lock().unwrap();
+        if healthy {
+            *breaker = BreakerState::default();
+            return;
+        }
+        breaker.failures += 1;
+        if breaker.failures >= self.config.breaker_threshold {
+            breaker.open_until =
+                Some(Instant::now() + Duration::from_millis(self.config.breaker_cooldown_ms));
+            breaker.probe_started = None;
+        }
+    }
+
+    /// Gets the delay before a retry.
+    ///
+    /// The delay doubles on every retry, half of it is random so
+    /// instances that failed together do not retry together. Without
+    /// randomness the full delay is used.
+    ///
+    /// # Arguments
+    /// * `attempt` - The number of the failed attempt, starting at 1
+    ///
+    /// # Returns
+    /// The delay
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .config
+            .retry_backoff_ms
+            .saturating_mul(1 << (attempt - 1).min(16));
+        let mut bytes = [0u8; 8];
+        let jitter = match SystemRandom::new().fill(&mut bytes) {
+            Ok(()) => u64::from_le_bytes(bytes) % (delay / 2 + 1),
+            Err(_) => delay / 2,
+        };
+        Duration::from_millis(delay / 2 + jitter)
+    }
+}
+
+/// Checks the status of a dapr response.
+///
+/// # Arguments
+/// * `response` - The response
+///
+/// # Returns
+/// The response, `PreconditionFailed` on a conflict, or
+/// `StoreUnavailable` on any other unsuccessful status
+fn check_status(response: Response) -> AccountResult<Response> {
+    // Dapr answers etag mismatches with a conflict
+    if response.status() == StatusCode::CONFLICT {
+        return Err(AccountError::PreconditionFailed);
+    }
+
+    // Fail on any unsuccessful status
+    if !response.status().is_success() {
+        return Err(AccountError::StoreUnavailable(format!(
+            "dapr sidecar responded with {}",
+            response.status()
+        )));
+    }
+
+    Ok(response)
+}
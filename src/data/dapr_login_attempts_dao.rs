@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use super::dapr_state_store::{
+    delete_operation, ttl_until, upsert_operation, with_ttl, DaprStateStore,
+};
+use super::login_attempts_dao::LoginAttemptsDao;
+use super::login_attempts_entity::{login_attempts_key, LoginAttemptsEntity};
+use crate::errors::{AccountError, AccountResult};
+use rocket::{async_trait, serde::json::serde_json::json};
+
+/// The attempts to write a counter that keeps changing.
+const WRITE_ATTEMPTS: usize = 3;
+
+/// The dapr login attempts dao.
+///
+/// Counters are stored under `attempts:<subject>`, next to the accounts
+/// in the same state store, so every instance sees the same counts.
+/// They expire with the state store ttl where supported.
+///
+/// # Fields
+/// * `store` - The dapr state store, shared with the account dao
+///
+/// # Methods
+/// * `new` - Creates a new dapr login attempts dao
+/// * `get_counter` - Gets a counter and its version, expired or not
+/// * `get_login_attempts` - Gets the failed logins of a subject from the dapr state store
+/// * `record_login_failure` - Counts a failed login of a subject in the dapr state store
+/// * `clear_login_attempts` - Forgets the failed logins of a subject in the dapr state store
+///
+/// # Traits
+/// * `LoginAttemptsDao` - The login attempts dao trait
+pub struct DaprLoginAttemptsDao {
+    store: Arc<DaprStateStore>,
+}
+
+/// The dapr login attempts dao implementation.
+impl DaprLoginAttemptsDao {
+    /// Creates a new dapr login attempts dao.
+    ///
+    /// # Arguments
+    /// * `store` - The dapr state store
+    ///
+    /// # Returns
+    /// The new dapr login attempts dao
+    pub fn new(store: Arc<DaprStateStore>) -> Self {
+        DaprLoginAttemptsDao { store }
+    }
+
+    /// Gets a counter and its version, expired or not.
+    ///
+    /// # Arguments
+    /// * `key` - The counter key
+    ///
+    /// # Returns
+    /// The counter entity, or `None` if the key does not exist
+    async fn get_counter(&self, key: &str) -> AccountResult<Option<LoginAttemptsEntity>> {
+        match self.store.get_state::<LoginAttemptsEntity>(key).await {
+            Ok((attempts, etag)) => Ok(Some(LoginAttemptsEntity { etag, ..attempts })),
+            Err(AccountError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The dapr login attempts dao implementation.
+#[async_trait]
+impl LoginAttemptsDao for DaprLoginAttemptsDao {
+    /// Gets the failed logins of a subject from the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    /// * `now` - The current time, in seconds since the epoch
+    ///
+    /// # Returns
+    /// The counter entity
+    async fn get_login_attempts(&self, id: String, now: u64) -> AccountResult<LoginAttemptsEntity> {
+        // State stores without ttl support keep expired counters
+        self.get_counter(&login_attempts_key(&id))
+            .await?
+            .filter(|attempts| attempts.expires_at > now)
+            .ok_or(AccountError::NotFound)
+    }
+
+    /// Counts a failed login of a subject in the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    /// * `now` - The time of the failed login, in seconds since the epoch
+    /// * `window_secs` - How long the counter is kept after the failure
+    ///
+    /// # Returns
+    /// The counter including the failure
+    async fn record_login_failure(
+        &self,
+        id: String,
+        now: u64,
+        window_secs: u64,
+    ) -> AccountResult<LoginAttemptsEntity> {
+        let key = login_attempts_key(&id);
+        let mut error = AccountError::PreconditionFailed;
+        for _ in 0..WRITE_ATTEMPTS {
+            // Count on top of the version just read
+            let current = self.get_counter(&key).await?;
+            let etag = current.as_ref().and_then(|current| current.etag.clone());
+            let next = LoginAttemptsEntity::next(current.as_ref(), &id, now, window_secs);
+            let result = self
+                .store
+                .transact(vec![with_ttl(
+                    upsert_operation(&key, json!(next), etag.clone()),
+                    ttl_until(next.expires_at),
+                )])
+                .await;
+
+            // Try again if another failure was counted, otherwise report the failure
+            match result {
+                Ok(()) => return Ok(next),
+                Err(e @ (AccountError::PreconditionFailed | AccountError::StoreUnavailable(_))) => {
+                    let latest = self.get_counter(&key).await?;
+                    if latest.and_then(|latest| latest.etag) == etag {
+                        return Err(e);
+                    }
+                    error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(error)
+    }
+
+    /// Forgets the failed logins of a subject in the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    ///
+    /// # Returns
+    /// Nothing
+    async fn clear_login_attempts(&self, id: String) -> AccountResult<()> {
+        self.store
+            .transact(vec![delete_operation(&login_attempts_key(&id), None)])
+            .await
+    }
+}
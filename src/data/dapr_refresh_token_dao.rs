@@ -0,0 +1,347 @@
+use std::sync::Arc;
+
+use super::dapr_state_store::{ttl_until, unix_now, upsert_operation, with_ttl, DaprStateStore};
+use super::refresh_token_dao::RefreshTokenDao;
+use super::refresh_token_entity::{
+    refresh_family_key, refresh_token_key, session_index_key, RefreshFamilyEntity,
+    RefreshTokenEntity, SessionIndexEntry,
+};
+use crate::errors::{AccountError, AccountResult};
+use rocket::{async_trait, serde::json::serde_json::json};
+
+/// The attempts to write a family or session index that keeps changing.
+const WRITE_ATTEMPTS: usize = 3;
+
+/// The dapr refresh token dao.
+///
+/// Tokens are stored under `refresh:<hash>` and families under
+/// `refresh_family:<id>`, next to the accounts in the same state
+/// store. Both expire with the state store ttl where supported.
+/// The families of an account are listed in a `sessions:<account id>`
+/// index entry, written in the same transaction as a new family.
+///
+/// # Fields
+/// * `store` - The dapr state store, shared with the account dao
+///
+/// # Methods
+/// * `new` - Creates a new dapr refresh token dao
+/// * `get_session_index` - Gets the session index entry of an account
+/// * `get_live_families` - Gets the live families listed in a session index entry
+/// * `get_refresh_token` - Gets a refresh token from the dapr state store
+/// * `get_refresh_family` - Gets a refresh token family from the dapr state store
+/// * `get_refresh_families` - Gets the families of an account from the dapr state store
+/// * `create_refresh_family` - Creates a family in the dapr state store
+/// * `rotate_refresh_token` - Rotates a refresh token in the dapr state store
+/// * `revoke_refresh_family` - Revokes a family in the dapr state store
+///
+/// # Traits
+/// * `RefreshTokenDao` - The refresh token dao trait
+pub struct DaprRefreshTokenDao {
+    store: Arc<DaprStateStore>,
+}
+
+/// The dapr refresh token dao implementation.
+impl DaprRefreshTokenDao {
+    /// Creates a new dapr refresh token dao.
+    ///
+    /// # Arguments
+    /// * `store` - The dapr state store
+    ///
+    /// # Returns
+    /// The new dapr refresh token dao
+    pub fn new(store: Arc<DaprStateStore>) -> Self {
+        DaprRefreshTokenDao { store }
+    }
+
+    /// Gets the session index entry of an account.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    ///
+    /// # Returns
+    /// The index entry and its etag, empty without an etag if the account has no sessions
+    async fn get_session_index(
+        &self,
+        account_id: &str,
+    ) -> AccountResult<(SessionIndexEntry, Option<String>)> {
+        match self.store.get_state(&session_index_key(account_id)).await {
+            Err(AccountError::NotFound) => Ok((SessionIndexEntry::default(), None)),
+            result => result,
+        }
+    }
+
+    /// Gets the live families listed in a session index entry.
+    ///
+    /// # Arguments
+    /// * `index` - The session index entry of an account
+    ///
+    /// # Returns
+    /// The listed families that are neither expired nor revoked
+    async fn get_live_families(
+        &self,
+        index: &SessionIndexEntry,
+    ) -> AccountResult<Vec<RefreshFamilyEntity>> {
+        // Read every indexed family, the expired ones are gone or stale
+        let now = unix_now();
+        let mut families = vec![];
+        for id in &index.family_ids {
+            match self.get_refresh_family(id.clone()).await {
+                Ok(family) if !family.revoked && family.expires_at > now => families.push(family),
+                Ok(_) | Err(AccountError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(families)
+    }
+}
+
+/// The dapr refresh token dao implementation.
+#[async_trait]
+impl RefreshTokenDao for DaprRefreshTokenDao {
+    /// Gets a refresh token from the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The hash of the token
+    ///
+    /// # Returns
+    /// The token entity
+    async fn get_refresh_token(&self, id: String) -> AccountResult<RefreshTokenEntity> {
+        let (token, etag): (RefreshTokenEntity, Option<String>) =
+            self.store.get_state(&refresh_token_key(&id)).await?;
+        Ok(RefreshTokenEntity { etag, ..token })
+    }
+
+    /// Gets a refresh token family from the dapr state store.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the family
+    ///
+    /// # Returns
+    /// The family entity
+    async fn get_refresh_family(&self, id: String) -> AccountResult<RefreshFamilyEntity> {
+        let (family, etag): (RefreshFamilyEntity, Option<String>) =
+            self.store.get_state(&refresh_family_key(&id)).await?;
+        Ok(RefreshFamilyEntity { etag, ..family })
+    }
+
+    /// Gets the families of an account from the dapr state store.
+    ///
+    /// Families that expired or were revoked are removed from the
+    /// session index on the way, if the index did not change meanwhile.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    ///
+    /// # Returns
+    /// The live family entities
+    async fn get_refresh_families(
+        &self,
+        account_id: String,
+    ) -> AccountResult<Vec<RefreshFamilyEntity>> {
+        let (index, etag) = self.get_session_index(&account_id).await?;
+        let families = self.get_live_families(&index).await?;
+
+        // Prune the index, best-effort since the next listing prunes again
+        if families.len() < index.family_ids.len() {
+            let pruned = SessionIndexEntry {
+                family_ids: families.iter().map(|family| family.id.clone()).collect(),
+            };
+            let _ = self
+                .store
+                .transact(vec![upsert_operation(
+                    &session_index_key(&account_id),
+                    json!(pruned),
+                    etag,
+                )])
+                .await;
+        }
+        Ok(families)
+    }
+
+    /// Creates a family in the dapr state store.
+    ///
+    /// The family, its first token and the session index entry of the
+    /// account are written in one transaction. Families that expired or
+    /// were revoked are dropped from the index on the way, so it only
+    /// grows with live sessions. Concurrent logins of the same account
+    /// retry on the changed index.
+    ///
+    /// # Arguments
+    /// * `family` - The new family
+    /// * `token` - The first token of the family
+    ///
+    /// # Returns
+    /// Nothing if both were stored
+    async fn create_refresh_family(
+        &self,
+        family: RefreshFamilyEntity,
+        token: RefreshTokenEntity,
+    ) -> AccountResult<()> {
+        let ttl = ttl_until(token.expires_at);
+        for _ in 0..WRITE_ATTEMPTS {
+            // List the family in the session index of the account, next to the live ones
+            let (index, index_etag) = self.get_session_index(&family.account_id).await?;
+            let mut family_ids: Vec<String> = self
+                .get_live_families(&index)
+                .await?
+                .into_iter()
+                .map(|live| live.id)
+                .collect();
+            family_ids.push(family.id.clone());
+            let index = SessionIndexEntry { family_ids };
+            let result = self
+                .store
+                .transact(vec![
+                    with_ttl(
+                        upsert_operation(&refresh_family_key(&family.id), json!(family), None),
+                        ttl,
+                    ),
+                    with_ttl(
+                        upsert_operation(&refresh_token_key(&token.id), json!(token), None),
+                        ttl,
+                    ),
+                    upsert_operation(
+                        &session_index_key(&family.account_id),
+                        json!(index),
+                        index_etag.clone(),
+                    ),
+                ])
+                .await;
+
+            // Find out which key was taken if the transaction failed
+            match result {
+                Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                    if self.get_refresh_family(family.id.clone()).await.is_ok() {
+                        return Err(AccountError::Conflict(
+                            "refresh token already in use".to_string(),
+                        ));
+                    }
+                    let (_, latest_etag) = self.get_session_index(&family.account_id).await?;
+                    if latest_etag == index_etag {
+                        return result;
+                    }
+                }
+                result => return result,
+            }
+        }
+        Err(AccountError::PreconditionFailed)
+    }
+
+    /// Rotates a refresh token in the dapr state store.
+    ///
+    /// The consumed token, the family and the successor are written in
+    /// one transaction, guarded by the versions of the token and family.
+    ///
+    /// # Arguments
+    /// * `token` - The token to consume, as read
+    /// * `family` - The family of the token, with its new last use and expiry
+    /// * `next` - The successor of the token
+    ///
+    /// # Returns
+    /// Nothing if the token was rotated
+    async fn rotate_refresh_token(
+        &self,
+        token: RefreshTokenEntity,
+        family: RefreshFamilyEntity,
+        next: RefreshTokenEntity,
+    ) -> AccountResult<()> {
+        // Keep the consumed token until it expires to detect its reuse,
+        // and the family as long as its newest token
+        let consumed = RefreshTokenEntity {
+            consumed: true,
+            etag: None,
+            ..token.clone()
+        };
+        let extended = RefreshFamilyEntity {
+            etag: None,
+            ..family.clone()
+        };
+        let result = self
+            .store
+            .transact(vec![
+                with_ttl(
+                    upsert_operation(
+                        &refresh_token_key(&consumed.id),
+                        json!(consumed),
+                        token.etag.clone(),
+                    ),
+                    ttl_until(consumed.expires_at),
+                ),
+                with_ttl(
+                    upsert_operation(
+                        &refresh_family_key(&extended.id),
+                        json!(extended),
+                        family.etag.clone(),
+                    ),
+                    ttl_until(extended.expires_at),
+                ),
+                with_ttl(
+                    upsert_operation(&refresh_token_key(&next.id), json!(next), None),
+                    ttl_until(next.expires_at),
+                ),
+            ])
+            .await;
+
+        // Find out if the token or family changed if the transaction failed
+        match result {
+            Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                let latest_token = self.get_refresh_token(token.id.clone()).await?;
+                let latest_family = self.get_refresh_family(family.id.clone()).await?;
+                if latest_token.etag != token.etag || latest_family.etag != family.etag {
+                    Err(AccountError::PreconditionFailed)
+                } else {
+                    result
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Revokes a family in the dapr state store.
+    ///
+    /// Rotations racing the revocation are retried over, since a
+    /// revoked family can never be rotated again.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the family
+    ///
+    /// # Returns
+    /// Nothing if the family is revoked
+    async fn revoke_refresh_family(&self, id: String) -> AccountResult<()> {
+        for _ in 0..WRITE_ATTEMPTS {
+            // Nothing to do if the family is already revoked
+            let family = self.get_refresh_family(id.clone()).await?;
+            if family.revoked {
+                return Ok(());
+            }
+            let revoked = RefreshFamilyEntity {
+                revoked: true,
+                etag: None,
+                ..family.clone()
+            };
+            let result = self
+                .store
+                .transact(vec![with_ttl(
+                    upsert_operation(
+                        &refresh_family_key(&id),
+                        json!(revoked),
+                        family.etag.clone(),
+                    ),
+                    ttl_until(revoked.expires_at),
+                )])
+                .await;
+
+            // Try again if the family changed, otherwise report the failure
+            match result {
+                Err(AccountError::PreconditionFailed) | Err(AccountError::StoreUnavailable(_)) => {
+                    let latest = self.get_refresh_family(id.clone()).await?;
+                    if latest.etag == family.etag {
+                        return result;
+                    }
+                }
+                result => return result,
+            }
+        }
+        Err(AccountError::PreconditionFailed)
+    }
+}
@@ -0,0 +1,249 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::dapr_client::{DaprClient, DaprClientConfig};
+use crate::errors::{AccountError, AccountResult};
+use reqwest::{header::ETAG, StatusCode, Url};
+use rocket::serde::{
+    de::DeserializeOwned,
+    json::serde_json::{self, json, Value},
+};
+
+/// Build an upsert operation for a dapr state transaction.
+///
+/// All writes use first-write concurrency: with an etag the key must
+/// still have that version, without one the key must not exist yet.
+///
+/// # Arguments
+/// * `key` - The state key
+/// * `value` - The value to store
+/// * `etag` - The expected version of the key, if it exists
+///
+/// # Returns
+/// The transaction operation
+pub fn upsert_operation(key: &str, value: Value, etag: Option<String>) -> Value {
+    let mut request = json!({
+        "key": key,
+        "value": value,
+        "options": { "concurrency": "first-write" },
+    });
+    if let Some(etag) = etag {
+        request["etag"] = json!(etag);
+    }
+    json!({ "operation": "upsert", "request": request })
+}
+
+/// Build a delete operation for a dapr state transaction.
+///
+/// # Arguments
+/// * `key` - The state key
+/// * `etag` - The expected version of the key, if any
+///
+/// # Returns
+/// The transaction operation
+pub fn delete_operation(key: &str, etag: Option<String>) -> Value {
+    let mut request = json!({
+        "key": key,
+        "options": { "concurrency": "first-write" },
+    });
+    if let Some(etag) = etag {
+        request["etag"] = json!(etag);
+    }
+    json!({ "operation": "delete", "request": request })
+}
+
+/// Let the state store expire the key of a transaction operation.
+///
+/// State stores without ttl support keep the key, so readers must
+/// still check the expiry of the value.
+///
+/// # Arguments
+/// * `operation` - The upsert operation
+/// * `ttl_secs` - The lifetime of the key
+///
+/// # Returns
+/// The operation with its ttl
+pub fn with_ttl(mut operation: Value, ttl_secs: u64) -> Value {
+    operation["request"]["metadata"] = json!({ "ttlInSeconds": ttl_secs.to_string() });
+    operation
+}
+
+/// Gets the current time.
+///
+/// # Returns
+/// The seconds since the epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// Gets the time left until an expiry.
+///
+/// # Arguments
+/// * `expires_at` - The expiry time, in seconds since the epoch
+///
+/// # Returns
+/// The seconds left, at least one so the state store accepts the ttl
+pub fn ttl_until(expires_at: u64) -> u64 {
+    expires_at.saturating_sub(unix_now()).max(1)
+}
+
+/// A dapr state store.
+///
+/// Wraps the state api of one store behind a dapr sidecar, so every
+/// dao on that store shares one client and its circuit breaker.
+///
+/// # Fields
+/// * `client` - The client shared by every request to the sidecar
+/// * `sidecar_url` - The base url of the dapr sidecar
+/// * `store_name` - The name of the dapr state store
+///
+/// # Methods
+/// * `new` - Creates a new dapr state store
+/// * `client` - Gets the dapr client
+/// * `state_url` - Gets the dapr state url
+/// * `key_url` - Gets the dapr url of a single state key
+/// * `transaction_url` - Gets the dapr transaction url
+/// * `query_url` - Gets the dapr query url
+/// * `transact` - Applies operations in one dapr state transaction
+/// * `get_state` - Gets a value and its etag from the dapr state store
+pub struct DaprStateStore {
+    client: DaprClient,
+    sidecar_url: String,
+    store_name: String,
+}
+
+/// The dapr state store implementation.
+impl DaprStateStore {
+    /// Creates a new dapr state store.
+    ///
+    /// # Arguments
+    /// * `sidecar_url` - The base url of the dapr sidecar, e.g. `http://localhost:3500`
+    /// * `store_name` - The name of the dapr state store
+    /// * `config` - The dapr client configuration
+    ///
+    /// # Returns
+    /// The new dapr state store
+    pub fn new(
+        sidecar_url: impl Into<String>,
+        store_name: impl Into<String>,
+        config: DaprClientConfig,
+    ) -> AccountResult<Self> {
+        Ok(DaprStateStore {
+            client: DaprClient::new(config)?,
+            sidecar_url: sidecar_url.into().trim_end_matches('/').to_string(),
+            store_name: store_name.into(),
+        })
+    }
+
+    /// Get the dapr client.
+    ///
+    /// # Returns
+    /// The client shared by every request to the sidecar
+    pub fn client(&self) -> &DaprClient {
+        &self.client
+    }
+
+    /// Get the dapr state url.
+    ///
+    /// # Returns
+    /// The dapr state url
+    fn state_url(&self) -> String {
+        format!("{}/v1.0/state/{}", self.sidecar_url, self.store_name)
+    }
+
+    /// Get the dapr url of a single state key.
+    ///
+    /// # Arguments
+    /// * `key` - The state key, percent encoded by this function
+    ///
+    /// # Returns
+    /// The dapr url of the key
+    fn key_url(&self, key: &str) -> AccountResult<Url> {
+        let mut url =
+            Url::parse(&self.state_url()).map_err(|e| AccountError::Internal(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|_| AccountError::Internal("invalid sidecar url".to_string()))?
+            .push(key);
+        Ok(url)
+    }
+
+    /// Get the dapr transaction url.
+    ///
+    /// # Returns
+    /// The dapr transaction url
+    fn transaction_url(&self) -> String {
+        format!("{}/transaction", self.state_url())
+    }
+
+    /// Get the dapr query url.
+    ///
+    /// # Returns
+    /// The dapr query url
+    pub fn query_url(&self) -> String {
+        format!(
+            "{}/v1.0-alpha1/state/{}/query",
+            self.sidecar_url, self.store_name
+        )
+    }
+
+    /// Apply operations in one dapr state transaction.
+    ///
+    /// # Arguments
+    /// * `operations` - The upsert and delete operations
+    ///
+    /// # Returns
+    /// Nothing if every operation was applied, otherwise none is
+    pub async fn transact(&self, operations: Vec<Value>) -> AccountResult<()> {
+        // Post the operations to the transaction url, never retried since
+        // a transaction that was applied would fail its etags the second time
+        self.client
+            .send(
+                self.client
+                    .post(self.transaction_url())
+                    .body(json!({ "operations": operations }).to_string()),
+                false,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get a value and its etag from the dapr state store.
+    ///
+    /// # Arguments
+    /// * `key` - The state key
+    ///
+    /// # Returns
+    /// The value and its etag, or `NotFound` if the key does not exist
+    pub async fn get_state<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> AccountResult<(T, Option<String>)> {
+        // Get the value from dapr
+        let response = self
+            .client
+            .send(self.client.get(self.key_url(key)?), true)
+            .await?;
+
+        // Dapr answers missing keys with no content
+        if response.status() == StatusCode::NO_CONTENT {
+            return Err(AccountError::NotFound);
+        }
+
+        // Keep the version of the value
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+
+        // Read the body and map to the value
+        let body = response.bytes().await?;
+        if body.is_empty() {
+            return Err(AccountError::NotFound);
+        }
+        Ok((serde_json::from_slice(&body)?, etag))
+    }
+}
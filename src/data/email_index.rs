@@ -0,0 +1,54 @@
+use rocket::serde::{Deserialize, Serialize};
+
+/// The key prefix of email index entries.
+///
+/// Account ids cannot contain `:`, so index entries never
+/// collide with accounts in the state store.
+pub const EMAIL_INDEX_PREFIX: &str = "email:";
+
+/// An email index entry.
+///
+/// Maps a normalized email to the account that owns it. The entry
+/// is written in the same state transaction as the account, which
+/// makes email uniqueness atomic.
+///
+/// # Fields
+/// * `account_id` - The id of the account owning the email
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct EmailIndexEntry {
+    pub account_id: String,
+}
+
+/// Normalizes an email for uniqueness checks.
+///
+/// # Arguments
+/// * `email` - The email to normalize
+///
+/// # Returns
+/// The trimmed, lowercased email
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Gets the state store key of an email index entry.
+///
+/// # Arguments
+/// * `email` - The email, normalized by this function
+///
+/// # Returns
+/// The index key, `email:<normalized email>`
+pub fn email_index_key(email: &str) -> String {
+    format!("{}{}", EMAIL_INDEX_PREFIX, normalize_email(email))
+}
+
+/// Checks if a state store key belongs to an auxiliary record.
+///
+/// # Arguments
+/// * `key` - The state store key
+///
+/// # Returns
+/// True if the key is not an account key
+pub fn is_auxiliary_key(key: &str) -> bool {
+    key.contains(':')
+}
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use super::account_dao::AccountDao;
+use super::account_entity::{AccountEntity, MfaSettings};
+use super::account_query::{AccountPage, AccountQuery, SortField, SortOrder};
+use super::email_index::normalize_email;
+use super::passwords::{PasswordHasher, Verification};
+use crate::errors::{AccountError, AccountResult, FieldError};
+use rocket::async_trait;
+
+/// The in-memory account state.
+///
+/// # Fields
+/// * `accounts` - The stored accounts, keyed by id, each tagged with its version
+/// * `emails` - The email index, mapping normalized emails to account ids
+#[derive(Default)]
+struct MemoryState {
+    accounts: HashMap<String, AccountEntity>,
+    emails: HashMap<String, String>,
+}
+
+/// The in-memory account dao.
+///
+/// This dao keeps accounts in process memory. It is used for local
+/// runs and tests where no dapr sidecar is available. Data is lost
+/// when the dao is dropped. Accounts and the email index are changed
+/// under one lock, mirroring the state transactions of the dapr dao.
+///
+/// # Fields
+/// * `state` - The stored accounts and email index
+/// * `versions` - The source of account versions
+/// * `hasher` - The password hasher
+///
+/// # Methods
+/// * `new` - Creates a new in-memory account dao
+/// * `with_password_hasher` - Replaces the password hasher
+/// * `next_version` - Gets a new account version
+/// * `get_accounts` - Gets a page of accounts
+/// * `get_account_by_id` - Gets an account by id
+/// * `get_account_by_email` - Gets an account by email
+/// * `validate_account` - Validates an account
+/// * `create_account` - Creates an account
+/// * `update_account` - Updates an account
+/// * `change_password` - Changes the password of an account
+/// * `reset_password` - Sets the password of an account without the current one
+/// * `verify_email` - Marks the email of an account as verified
+/// * `delete_account` - Deletes an account
+///
+/// # Traits
+/// * `AccountDao` - The account dao trait
+#[derive(Default)]
+pub struct InMemoryAccountDao {
+    state: RwLock<MemoryState>,
+    versions: AtomicU64,
+    hasher: PasswordHasher,
+}
+
+/// The in-memory account dao implementation.
+impl InMemoryAccountDao {
+    /// Creates a new, empty in-memory account dao.
+    ///
+    /// # Returns
+    /// The new in-memory account dao
+    pub fn new() -> Self {
+        InMemoryAccountDao::default()
+    }
+
+    /// Replaces the password hasher.
+    ///
+    /// # Arguments
+    /// * `hasher` - The password hasher to use
+    ///
+    /// # Returns
+    /// The dao using the hasher
+    pub fn with_password_hasher(self, hasher: PasswordHasher) -> Self {
+        InMemoryAccountDao { hasher, ..self }
+    }
+
+    /// Gets a new account version.
+    ///
+    /// # Returns
+    /// The new version, unique within this dao
+    fn next_version(&self) -> Option<String> {
+        Some((self.versions.fetch_add(1, Ordering::SeqCst) + 1).to_string())
+    }
+}
+
+/// The in-memory account dao implementation.
+#[async_trait]
+impl AccountDao for InMemoryAccountDao {
+    /// Gets a page of accounts from memory.
+    ///
+    /// Page tokens are offsets into the sorted, filtered accounts.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, sort order and page to get
+    ///
+    /// # Returns
+    /// The page of matching accounts
+    async fn get_accounts(&self, query: AccountQuery) -> AccountResult<AccountPage> {
+        // Find where the page starts
+        let offset = match &query.page_token {
+            Some(token) => token.parse::<usize>().map_err(|_| {
+                AccountError::Validation(vec![FieldError::new(
+                    "page_token",
+                    "is not a valid page token",
+                )])
+            })?,
+            None => 0,
+        };
+
+        // Filter the accounts
+        let mut entities: Vec<AccountEntity> = self
+            .state
+            .read()
+            .unwrap()
+            .accounts
+            .values()
+            .filter(|account| query.matches(account))
+            .cloned()
+            .collect();
+
+        // Sort by the requested field, ties ordered by id
+        entities.sort_by(|a, b| {
+            let ordering = match query.sort {
+                SortField::Id => a.id.cmp(&b.id),
+                SortField::Name => a.name.cmp(&b.name),
+                SortField::Email => a.email.cmp(&b.email),
+            }
+            .then_with(|| a.id.cmp(&b.id));
+            match query.order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
+
+        // Cut out the page
+        let end = offset.saturating_add(query.limit);
+        let next_page_token = if end < entities.len() {
+            Some(end.to_string())
+        } else {
+            None
+        };
+        Ok(AccountPage {
+            items: entities
+                .into_iter()
+                .skip(offset)
+                .take(query.limit)
+                .collect(),
+            next_page_token,
+        })
+    }
+
+    /// Gets an account by id from memory.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    ///
+    /// # Returns
+    /// The account entity
+    async fn get_account_by_id(&self, id: String) -> AccountResult<AccountEntity> {
+        self.state
+            .read()
+            .unwrap()
+            .accounts
+            .get(&id)
+            .cloned()
+            .ok_or(AccountError::NotFound)
+    }
+
+    /// Gets an account by email from memory.
+    ///
+    /// # Arguments
+    /// * `email` - The account email
+    ///
+    /// # Returns
+    /// The account entity
+    async fn get_account_by_email(&self, email: String) -> AccountResult<AccountEntity> {
+        let state = self.state.read().unwrap();
+
+        // Look up the owner of the email in the index
+        state
+            .emails
+            .get(&normalize_email(&email))
+            .and_then(|id| state.accounts.get(id))
+            .cloned()
+            .ok_or(AccountError::NotFound)
+    }
+
+    /// Validates an account in memory.
+    ///
+    /// An outdated password hash is replaced on success, unless the
+    /// account changed while the password was being verified.
+    ///
+    /// # Arguments
+    /// * `email` - The account email
+    /// * `password` - The account password
+    ///
+    /// # Returns
+    /// The account entity
+    async fn validate_account(
+        &self,
+        email: String,
+        password: String,
+    ) -> AccountResult<AccountEntity> {
+        // Get an account by email
+        let account = match self.get_account_by_email(email).await {
+            Ok(account) => account,
+            // Account with email does not exist, take as long as a wrong password
+            Err(AccountError::NotFound) => {
+                self.hasher.verify_dummy(password).await?;
+                return Err(AccountError::InvalidCredentials);
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Check if password matches
+        let password = match self
+            .hasher
+            .verify(password, account.password.clone())
+            .await?
+        {
+            Verification::Invalid => return Err(AccountError::InvalidCredentials),
+            Verification::Valid => return Ok(account),
+            Verification::Upgraded(password) => password,
+        };
+
+        // Store the upgraded hash over the version that was read
+        let mut state = self.state.write().unwrap();
+        match state.accounts.get_mut(&account.id) {
+            Some(current) if current.etag == account.etag => {
+                current.password = password;
+                current.etag = self.next_version();
+                Ok(current.clone())
+            }
+            _ => Ok(account),
+        }
+    }
+
+    /// Creates an account in memory.
+    ///
+    /// # Arguments
+    /// * `account` - The account entity
+    ///
+    /// # Returns
+    /// Nothing if the account was created
+    async fn create_account(&self, account: AccountEntity) -> AccountResult<()> {
+        // Hash the password in the account
+        let password = self.hasher.hash(account.password.clone()).await?;
+        let email = normalize_email(&account.email);
+
+        let mut state = self.state.write().unwrap();
+
+        // Check if the id or email is taken
+        if state.accounts.contains_key(&account.id) {
+            return Err(AccountError::Conflict("id already in use".to_string()));
+        }
+        if state.emails.contains_key(&email) {
+            return Err(AccountError::Conflict("email already in use".to_string()));
+        }
+
+        // Insert the account and claim its email together
+        state.emails.insert(email, account.id.clone());
+        state.accounts.insert(
+            account.id.clone(),
+            AccountEntity {
+                password,
+                etag: self.next_version(),
+                ..account
+            },
+        );
+        Ok(())
+    }
+
+    /// Updates an account in memory.
+    ///
+    /// The stored password hash and roles are kept.
+    ///
+    /// # Arguments
+    /// * `account` - The account entity
+    ///
+    /// # Returns
+    /// Nothing if the account was updated
+    async fn update_account(&self, account: AccountEntity) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Fail if account not found or at another version
+        let current = state
+            .accounts
+            .get(&account.id)
+            .ok_or(AccountError::NotFound)?;
+        if account.etag.is_some() && current.etag != account.etag {
+            return Err(AccountError::PreconditionFailed);
+        }
+
+        // Keep the password hash, status, roles, two-factor settings and instance id,
+        // not part of the profile
+        let password = current.password.clone();
+        let status = current.status;
+        let roles = current.roles.clone();
+        let mfa = current.mfa.clone();
+        let instance_id = current.instance_id.clone();
+
+        // Keep the email verification unless the email changes
+        let old_email = normalize_email(&current.email);
+        let new_email = normalize_email(&account.email);
+        let (email_verified, email_verified_at) = if old_email == new_email {
+            (current.email_verified, current.email_verified_at)
+        } else {
+            (false, None)
+        };
+
+        // Move the email index entry if the email changes
+        if old_email != new_email {
+            if state.emails.contains_key(&new_email) {
+                return Err(AccountError::Conflict("email already in use".to_string()));
+            }
+            state.emails.remove(&old_email);
+            state.emails.insert(new_email, account.id.clone());
+        }
+
+        // Replace the account with a new version
+        state.accounts.insert(
+            account.id.clone(),
+            AccountEntity {
+                password,
+                status,
+                roles,
+                email_verified,
+                email_verified_at,
+                mfa,
+                instance_id,
+                etag: self.next_version(),
+                ..account
+            },
+        );
+        Ok(())
+    }
+
+    /// Changes the password of an account in memory.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `current_password` - The current password of the account
+    /// * `new_password` - The password to set
+    ///
+    /// # Returns
+    /// Nothing if the password was changed
+    async fn change_password(
+        &self,
+        id: String,
+        current_password: String,
+        new_password: String,
+    ) -> AccountResult<()> {
+        // Fail if account not found
+        let current = self.get_account_by_id(id).await?;
+
+        // Check the current password, then hash the new one
+        let verification = self
+            .hasher
+            .verify(current_password, current.password.clone())
+            .await?;
+        if verification == Verification::Invalid {
+            return Err(AccountError::InvalidCredentials);
+        }
+        let password = self.hasher.hash(new_password).await?;
+
+        // Store the new hash over the version that was checked
+        let mut state = self.state.write().unwrap();
+        let stored = state
+            .accounts
+            .get_mut(&current.id)
+            .ok_or(AccountError::NotFound)?;
+        if stored.etag != current.etag {
+            return Err(AccountError::PreconditionFailed);
+        }
+        stored.password = password;
+        stored.etag = self.next_version();
+        Ok(())
+    }
+
+    /// Sets the password of an account in memory without the current one.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `new_password` - The password to set
+    ///
+    /// # Returns
+    /// Nothing if the password was set
+    async fn reset_password(&self, id: String, new_password: String) -> AccountResult<()> {
+        // Fail if account not found, before hashing
+        self.get_account_by_id(id.clone()).await?;
+        let password = self.hasher.hash(new_password).await?;
+
+        // Store the new hash over whatever version is current
+        let mut state = self.state.write().unwrap();
+        let stored = state.accounts.get_mut(&id).ok_or(AccountError::NotFound)?;
+        stored.password = password;
+        stored.etag = self.next_version();
+        Ok(())
+    }
+
+    /// Marks the email of an account in memory as verified.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `email` - The email that was verified
+    /// * `verified_at` - The verification time, in seconds since the epoch
+    ///
+    /// # Returns
+    /// Nothing if the email is verified
+    async fn verify_email(&self, id: String, email: String, verified_at: u64) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+        let version = self.next_version();
+        let stored = state.accounts.get_mut(&id).ok_or(AccountError::NotFound)?;
+
+        // Only the current email can be verified, and only once
+        if normalize_email(&stored.email) != normalize_email(&email) {
+            return Err(AccountError::PreconditionFailed);
+        }
+        if !stored.email_verified {
+            stored.email_verified = true;
+            stored.email_verified_at = Some(verified_at);
+            stored.etag = version;
+        }
+        Ok(())
+    }
+
+    /// Replaces the two-factor settings of an account in memory.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `mfa` - The two-factor settings to store
+    /// * `etag` - The version the settings were read at, if any
+    ///
+    /// # Returns
+    /// Nothing if the settings were stored
+    async fn set_mfa(
+        &self,
+        id: String,
+        mfa: MfaSettings,
+        etag: Option<String>,
+    ) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+        let version = self.next_version();
+
+        // Fail if account not found or at another version
+        let stored = state.accounts.get_mut(&id).ok_or(AccountError::NotFound)?;
+        if etag.is_some() && stored.etag != etag {
+            return Err(AccountError::PreconditionFailed);
+        }
+        stored.mfa = mfa;
+        stored.etag = version;
+        Ok(())
+    }
+
+    /// Deletes an account from memory.
+    ///
+    /// # Arguments
+    /// * `id` - The account id
+    /// * `etag` - The expected version of the account, if any
+    ///
+    /// # Returns
+    /// Nothing if the account was deleted
+    async fn delete_account(&self, id: String, etag: Option<String>) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Fail if account not found or at another version
+        let current = state.accounts.get(&id).ok_or(AccountError::NotFound)?;
+        if etag.is_some() && current.etag != etag {
+            return Err(AccountError::PreconditionFailed);
+        }
+
+        // Remove the account and release its email
+        let email = normalize_email(&current.email);
+        state.accounts.remove(&id);
+        state.emails.remove(&email);
+        Ok(())
+    }
+}
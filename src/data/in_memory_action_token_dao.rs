@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use super::action_token_dao::ActionTokenDao;
+use super::action_token_entity::ActionTokenEntity;
+use crate::errors::{AccountError, AccountResult};
+use rocket::async_trait;
+
+/// The in-memory action token dao.
+///
+/// This dao keeps action tokens in process memory, next to the
+/// in-memory account dao. Expired tokens are kept until they are
+/// used or the dao is dropped.
+///
+/// # Fields
+/// * `tokens` - The stored tokens, keyed by hash
+///
+/// # Methods
+/// * `new` - Creates a new in-memory action token dao
+/// * `create_action_token` - Creates an action token
+/// * `consume_action_token` - Removes an action token and returns it
+///
+/// # Traits
+/// * `ActionTokenDao` - The action token dao trait
+#[derive(Default)]
+pub struct InMemoryActionTokenDao {
+    tokens: RwLock<HashMap<String, ActionTokenEntity>>,
+}
+
+/// The in-memory action token dao implementation.
+impl InMemoryActionTokenDao {
+    /// Creates a new, empty in-memory action token dao.
+    ///
+    /// # Returns
+    /// The new in-memory action token dao
+    pub fn new() -> Self {
+        InMemoryActionTokenDao::default()
+    }
+}
+
+/// The in-memory action token dao implementation.
+#[async_trait]
+impl ActionTokenDao for InMemoryActionTokenDao {
+    /// Creates an action token in memory.
+    ///
+    /// # Arguments
+    /// * `token` - The new token
+    ///
+    /// # Returns
+    /// Nothing if the token was stored
+    async fn create_action_token(&self, token: ActionTokenEntity) -> AccountResult<()> {
+        let mut tokens = self.tokens.write().unwrap();
+        if tokens.contains_key(&token.id) {
+            return Err(AccountError::Conflict(
+                "action token already in use".to_string(),
+            ));
+        }
+        tokens.insert(token.id.clone(), token);
+        Ok(())
+    }
+
+    /// Removes an action token from memory and returns it.
+    ///
+    /// # Arguments
+    /// * `id` - The hash of the token
+    ///
+    /// # Returns
+    /// The removed token entity
+    async fn consume_action_token(&self, id: String) -> AccountResult<ActionTokenEntity> {
+        self.tokens
+            .write()
+            .unwrap()
+            .remove(&id)
+            .ok_or(AccountError::NotFound)
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use super::login_attempts_dao::LoginAttemptsDao;
+use super::login_attempts_entity::LoginAttemptsEntity;
+use crate::errors::{AccountError, AccountResult};
+use rocket::async_trait;
+
+/// The in-memory login attempts dao.
+///
+/// This dao keeps failed login counters in process memory, so every
+/// instance counts on its own. Expired counters are replaced by the
+/// next failure of their subject.
+///
+/// # Fields
+/// * `attempts` - The counters, keyed by subject
+///
+/// # Methods
+/// * `new` - Creates a new in-memory login attempts dao
+/// * `get_login_attempts` - Gets the failed logins of a subject from memory
+/// * `record_login_failure` - Counts a failed login of a subject in memory
+/// * `clear_login_attempts` - Forgets the failed logins of a subject in memory
+///
+/// # Traits
+/// * `LoginAttemptsDao` - The login attempts dao trait
+#[derive(Default)]
+pub struct InMemoryLoginAttemptsDao {
+    attempts: RwLock<HashMap<String, LoginAttemptsEntity>>,
+}
+
+/// The in-memory login attempts dao implementation.
+impl InMemoryLoginAttemptsDao {
+    /// Creates a new, empty in-memory login attempts dao.
+    ///
+    /// # Returns
+    /// The new in-memory login attempts dao
+    pub fn new() -> Self {
+        InMemoryLoginAttemptsDao::default()
+    }
+}
+
+/// The in-memory login attempts dao implementation.
+#[async_trait]
+impl LoginAttemptsDao for InMemoryLoginAttemptsDao {
+    /// Gets the failed logins of a subject from memory.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    /// * `now` - The current time, in seconds since the epoch
+    ///
+    /// # Returns
+    /// The counter entity
+    async fn get_login_attempts(&self, id: String, now: u64) -> AccountResult<LoginAttemptsEntity> {
+        self.attempts
+            .read()
+            .unwrap()
+            .get(&id)
+            .filter(|attempts| attempts.expires_at > now)
+            .cloned()
+            .ok_or(AccountError::NotFound)
+    }
+
+    /// Counts a failed login of a subject in memory.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    /// * `now` - The time of the failed login, in seconds since the epoch
+    /// * `window_secs` - How long the counter is kept after the failure
+    ///
+    /// # Returns
+    /// The counter including the failure
+    async fn record_login_failure(
+        &self,
+        id: String,
+        now: u64,
+        window_secs: u64,
+    ) -> AccountResult<LoginAttemptsEntity> {
+        let mut attempts = self.attempts.write().unwrap();
+        let next = LoginAttemptsEntity::next(attempts.get(&id), &id, now, window_secs);
+        attempts.insert(id, next.clone());
+        Ok(next)
+    }
+
+    /// Forgets the failed logins of a subject in memory.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    ///
+    /// # Returns
+    /// Nothing
+    async fn clear_login_attempts(&self, id: String) -> AccountResult<()> {
+        self.attempts.write().unwrap().remove(&id);
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use super::refresh_token_dao::RefreshTokenDao;
+use super::refresh_token_entity::{RefreshFamilyEntity, RefreshTokenEntity};
+use crate::errors::{AccountError, AccountResult};
+use rocket::async_trait;
+
+/// The in-memory refresh token state.
+///
+/// # Fields
+/// * `tokens` - The stored tokens, keyed by hash, each tagged with its version
+/// * `families` - The stored families, keyed by id, each tagged with its version
+#[derive(Default)]
+struct MemoryState {
+    tokens: HashMap<String, RefreshTokenEntity>,
+    families: HashMap<String, RefreshFamilyEntity>,
+}
+
+/// The in-memory refresh token dao.
+///
+/// This dao keeps refresh tokens in process memory, next to the
+/// in-memory account dao. Expired tokens are kept until the dao is
+/// dropped. Tokens and families are changed under one lock,
+/// mirroring the state transactions of the dapr dao.
+///
+/// # Fields
+/// * `state` - The stored tokens and families
+/// * `versions` - The source of token and family versions
+///
+/// # Methods
+/// * `new` - Creates a new in-memory refresh token dao
+/// * `next_version` - Gets a new version
+/// * `get_refresh_token` - Gets a refresh token
+/// * `get_refresh_family` - Gets a refresh token family
+/// * `get_refresh_families` - Gets the families of an account
+/// * `create_refresh_family` - Creates a family
+/// * `rotate_refresh_token` - Rotates a refresh token
+/// * `revoke_refresh_family` - Revokes a family
+///
+/// # Traits
+/// * `RefreshTokenDao` - The refresh token dao trait
+#[derive(Default)]
+pub struct InMemoryRefreshTokenDao {
+    state: RwLock<MemoryState>,
+    versions: AtomicU64,
+}
+
+/// The in-memory refresh token dao implementation.
+impl InMemoryRefreshTokenDao {
+    /// Creates a new, empty in-memory refresh token dao.
+    ///
+    /// # Returns
+    /// The new in-memory refresh token dao
+    pub fn new() -> Self {
+        InMemoryRefreshTokenDao::default()
+    }
+
+    /// Gets a new version.
+    ///
+    /// # Returns
+    /// The new version, unique within this dao
+    fn next_version(&self) -> Option<String> {
+        Some((self.versions.fetch_add(1, Ordering::SeqCst) + 1).to_string())
+    }
+}
+
+/// The in-memory refresh token dao implementation.
+#[async_trait]
+impl RefreshTokenDao for InMemoryRefreshTokenDao {
+    /// Gets a refresh token from memory.
+    ///
+    /// # Arguments
+    /// * `id` - The hash of the token
+    ///
+    /// # Returns
+    /// The token entity
+    async fn get_refresh_token(&self, id: String) -> AccountResult<RefreshTokenEntity> {
+        self.state
+            .read()
+            .unwrap()
+            .tokens
+            .get(&id)
+            .cloned()
+            .ok_or(AccountError::NotFound)
+    }
+
+    /// Gets a refresh token family from memory.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the family
+    ///
+    /// # Returns
+    /// The family entity
+    async fn get_refresh_family(&self, id: String) -> AccountResult<RefreshFamilyEntity> {
+        self.state
+            .read()
+            .unwrap()
+            .families
+            .get(&id)
+            .cloned()
+            .ok_or(AccountError::NotFound)
+    }
+
+    /// Gets the families of an account from memory.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    ///
+    /// # Returns
+    /// The family entities, revoked and expired ones included
+    async fn get_refresh_families(
+        &self,
+        account_id: String,
+    ) -> AccountResult<Vec<RefreshFamilyEntity>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .families
+            .values()
+            .filter(|family| family.account_id == account_id)
+            .cloned()
+            .collect())
+    }
+
+    /// Creates a family in memory.
+    ///
+    /// # Arguments
+    /// * `family` - The new family
+    /// * `token` - The first token of the family
+    ///
+    /// # Returns
+    /// Nothing if both were stored
+    async fn create_refresh_family(
+        &self,
+        family: RefreshFamilyEntity,
+        token: RefreshTokenEntity,
+    ) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+        if state.families.contains_key(&family.id) || state.tokens.contains_key(&token.id) {
+            return Err(AccountError::Conflict(
+                "refresh token already in use".to_string(),
+            ));
+        }
+        state.families.insert(
+            family.id.clone(),
+            RefreshFamilyEntity {
+                etag: self.next_version(),
+                ..family
+            },
+        );
+        state.tokens.insert(
+            token.id.clone(),
+            RefreshTokenEntity {
+                etag: self.next_version(),
+                ..token
+            },
+        );
+        Ok(())
+    }
+
+    /// Rotates a refresh token in memory.
+    ///
+    /// # Arguments
+    /// * `token` - The token to consume, as read
+    /// * `family` - The family of the token, with its new last use and expiry
+    /// * `next` - The successor of the token
+    ///
+    /// # Returns
+    /// Nothing if the token was rotated
+    async fn rotate_refresh_token(
+        &self,
+        token: RefreshTokenEntity,
+        family: RefreshFamilyEntity,
+        next: RefreshTokenEntity,
+    ) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Only rotate the versions that were read
+        let token_etag = state.tokens.get(&token.id).and_then(|t| t.etag.clone());
+        let family_etag = state.families.get(&family.id).and_then(|f| f.etag.clone());
+        if token_etag.is_none() || token_etag != token.etag || family_etag != family.etag {
+            return Err(AccountError::PreconditionFailed);
+        }
+        if state.tokens.contains_key(&next.id) {
+            return Err(AccountError::Conflict(
+                "refresh token already in use".to_string(),
+            ));
+        }
+
+        // Consume the token, update the family and add the successor
+        state.tokens.insert(
+            token.id.clone(),
+            RefreshTokenEntity {
+                consumed: true,
+                etag: self.next_version(),
+                ..token
+            },
+        );
+        state.families.insert(
+            family.id.clone(),
+            RefreshFamilyEntity {
+                etag: self.next_version(),
+                ..family
+            },
+        );
+        state.tokens.insert(
+            next.id.clone(),
+            RefreshTokenEntity {
+                etag: self.next_version(),
+                ..next
+            },
+        );
+        Ok(())
+    }
+
+    /// Revokes a family in memory.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the family
+    ///
+    /// # Returns
+    /// Nothing if the family is revoked
+    async fn revoke_refresh_family(&self, id: String) -> AccountResult<()> {
+        let mut state = self.state.write().unwrap();
+        let version = self.next_version();
+        let family = state.families.get_mut(&id).ok_or(AccountError::NotFound)?;
+        if !family.revoked {
+            family.revoked = true;
+            family.etag = version;
+        }
+        Ok(())
+    }
+}
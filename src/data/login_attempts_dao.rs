@@ -0,0 +1,53 @@
+use super::login_attempts_entity::LoginAttemptsEntity;
+use crate::errors::AccountResult;
+use rocket::async_trait;
+
+/// The Login Attempts Data Access Object.
+///
+/// This data access object is used to count failed logins.
+/// Implementations must be thread safe so they can be shared
+/// between requests as a trait object.
+///
+/// # Methods
+/// * `get_login_attempts` - Gets the failed logins of a subject
+/// * `record_login_failure` - Counts a failed login of a subject
+/// * `clear_login_attempts` - Forgets the failed logins of a subject
+#[async_trait]
+pub trait LoginAttemptsDao: Send + Sync {
+    /// Gets the failed logins of a subject.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    /// * `now` - The current time, in seconds since the epoch
+    ///
+    /// # Returns
+    /// The counter, or `NotFound` if there is none or it expired
+    async fn get_login_attempts(&self, id: String, now: u64) -> AccountResult<LoginAttemptsEntity>;
+
+    /// Counts a failed login of a subject.
+    ///
+    /// Concurrent failures are all counted.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    /// * `now` - The time of the failed login, in seconds since the epoch
+    /// * `window_secs` - How long the counter is kept after the failure
+    ///
+    /// # Returns
+    /// The counter including the failure
+    async fn record_login_failure(
+        &self,
+        id: String,
+        now: u64,
+        window_secs: u64,
+    ) -> AccountResult<LoginAttemptsEntity>;
+
+    /// Forgets the failed logins of a subject.
+    ///
+    /// # Arguments
+    /// * `id` - The counted subject
+    ///
+    /// # Returns
+    /// Nothing, also if no failures were counted
+    async fn clear_login_attempts(&self, id: String) -> AccountResult<()>;
+}
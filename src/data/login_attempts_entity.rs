@@ -0,0 +1,64 @@
+use rocket::serde::{Deserialize, Serialize};
+
+/// The key prefix of failed login counters in the state store.
+pub const LOGIN_ATTEMPTS_PREFIX: &str = "attempts:";
+
+/// The failed logins of an email or client.
+///
+/// Counters are forgotten once no login failed for a while, so
+/// only failures in quick succession add up to a lockout.
+///
+/// # Fields
+/// * `id` - The counted subject, `email:<normalized email>` or `ip:<address>`
+/// * `failures` - The failed logins in a row
+/// * `last_failure_at` - The time of the last failed login, in seconds since the epoch
+/// * `expires_at` - The time the counter is forgotten, in seconds since the epoch
+/// * `etag` - The version of the counter, not stored
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct LoginAttemptsEntity {
+    pub id: String,
+    pub failures: u32,
+    pub last_failure_at: u64,
+    pub expires_at: u64,
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// The login attempts entity implementation.
+impl LoginAttemptsEntity {
+    /// Counts another failed login.
+    ///
+    /// # Arguments
+    /// * `current` - The stored counter, if any
+    /// * `id` - The counted subject
+    /// * `now` - The time of the failed login, in seconds since the epoch
+    /// * `window_secs` - How long the counter is kept after the failure
+    ///
+    /// # Returns
+    /// The counter with the failure, restarted if the stored one expired
+    pub fn next(current: Option<&Self>, id: &str, now: u64, window_secs: u64) -> Self {
+        let failures = match current {
+            Some(current) if current.expires_at > now => current.failures.saturating_add(1),
+            _ => 1,
+        };
+        LoginAttemptsEntity {
+            id: id.to_string(),
+            failures,
+            last_failure_at: now,
+            expires_at: now + window_secs,
+            etag: None,
+        }
+    }
+}
+
+/// Gets the state store key of a failed login counter.
+///
+/// # Arguments
+/// * `id` - The counted subject
+///
+/// # Returns
+/// The counter key, `attempts:<subject>`
+pub fn login_attempts_key(id: &str) -> String {
+    format!("{}{}", LOGIN_ATTEMPTS_PREFIX, id)
+}
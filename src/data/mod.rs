@@ -1,9 +1,43 @@
 // Exports the data layer modules
 mod account_dao;
 mod account_entity;
+mod account_query;
+mod action_token_dao;
+mod action_token_entity;
 mod dapr_account_dao;
+mod dapr_action_token_dao;
+mod dapr_client;
+mod dapr_login_attempts_dao;
+mod dapr_refresh_token_dao;
+mod dapr_state_store;
+mod email_index;
+mod in_memory_account_dao;
+mod in_memory_action_token_dao;
+mod in_memory_login_attempts_dao;
+mod in_memory_refresh_token_dao;
+mod login_attempts_dao;
+mod login_attempts_entity;
+mod password_schemes;
+mod passwords;
+mod refresh_token_dao;
+mod refresh_token_entity;
 
 // Public exports
 pub use account_dao::AccountDao;
-pub use account_entity::AccountEntity;
+pub use account_entity::{default_roles, AccountEntity, AccountStatus, MfaSettings, ADMIN_ROLE};
+pub use account_query::{
+    AccountPage, AccountQuery, SortField, SortOrder, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
+};
+pub use action_token_dao::ActionTokenDao;
+pub use action_token_entity::{ActionPurpose, ActionTokenEntity};
 pub use dapr_account_dao::DaprAccountDao;
+pub use dapr_client::{sidecar_url, DaprClient, DaprClientConfig};
+pub use email_index::normalize_email;
+pub use in_memory_account_dao::InMemoryAccountDao;
+pub use in_memory_action_token_dao::InMemoryActionTokenDao;
+pub use in_memory_login_attempts_dao::InMemoryLoginAttemptsDao;
+pub use in_memory_refresh_token_dao::InMemoryRefreshTokenDao;
+pub use login_attempts_dao::LoginAttemptsDao;
+pub use passwords::{PasswordHasher, PasswordHashingConfig};
+pub use refresh_token_dao::RefreshTokenDao;
+pub use refresh_token_entity::{RefreshFamilyEntity, RefreshTokenEntity};
@@ -0,0 +1,173 @@
+use crate::errors::{AccountError, AccountResult};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher as _, SaltString},
+    Algorithm, Argon2, Params, PasswordVerifier, Version,
+};
+use pwhash::bcrypt::{self, BcryptSetup, BcryptVariant};
+
+/// A password hashing scheme.
+///
+/// Every scheme recognizes its own hashes, so hashes of several
+/// schemes can live side by side while users are migrated.
+///
+/// # Methods
+/// * `hash` - Hashes a password
+/// * `verify` - Verifies a password against a hash of this scheme
+/// * `recognizes` - Checks if a hash belongs to this scheme
+/// * `is_current` - Checks if a hash uses the configured parameters
+pub trait PasswordScheme: Send + Sync {
+    /// Hashes a password.
+    ///
+    /// # Arguments
+    /// * `password` - The password to hash
+    ///
+    /// # Returns
+    /// The hash, or an internal error if hashing failed
+    fn hash(&self, password: &str) -> AccountResult<String>;
+
+    /// Verifies a password against a hash of this scheme.
+    ///
+    /// # Arguments
+    /// * `password` - The password to verify
+    /// * `hash` - The hash to verify against
+    ///
+    /// # Returns
+    /// True if the password matches
+    fn verify(&self, password: &str, hash: &str) -> bool;
+
+    /// Checks if a hash belongs to this scheme.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash to check
+    ///
+    /// # Returns
+    /// True if this scheme can verify the hash
+    fn recognizes(&self, hash: &str) -> bool;
+
+    /// Checks if a hash of this scheme uses the configured parameters.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash to check
+    ///
+    /// # Returns
+    /// True if the hash does not need to be rehashed
+    fn is_current(&self, hash: &str) -> bool;
+}
+
+/// The bcrypt password scheme.
+///
+/// # Fields
+/// * `cost` - The bcrypt cost, the log2 of the rounds
+pub struct BcryptScheme {
+    cost: u32,
+}
+
+/// The bcrypt scheme implementation.
+impl BcryptScheme {
+    /// Creates a new bcrypt scheme.
+    ///
+    /// # Arguments
+    /// * `cost` - The bcrypt cost, the log2 of the rounds
+    ///
+    /// # Returns
+    /// The new bcrypt scheme
+    pub fn new(cost: u32) -> Self {
+        BcryptScheme { cost }
+    }
+}
+
+/// The bcrypt password scheme.
+impl PasswordScheme for BcryptScheme {
+    fn hash(&self, password: &str) -> AccountResult<String> {
+        let setup = BcryptSetup {
+            cost: Some(self.cost),
+            variant: Some(BcryptVariant::V2b),
+            ..Default::default()
+        };
+        bcrypt::hash_with(setup, password).map_err(|e| AccountError::Internal(e.to_string()))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        bcrypt::verify(password, hash)
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+    }
+
+    fn is_current(&self, hash: &str) -> bool {
+        // The cost follows the variant, e.g. `$2b$10$...`
+        hash.split('$').nth(2).and_then(|cost| cost.parse().ok()) == Some(self.cost)
+    }
+}
+
+/// The argon2id password scheme.
+///
+/// # Fields
+/// * `params` - The memory, time and parallelism costs
+pub struct Argon2idScheme {
+    params: Params,
+}
+
+/// The argon2id scheme implementation.
+impl Argon2idScheme {
+    /// Creates a new argon2id scheme.
+    ///
+    /// # Arguments
+    /// * `memory_kib` - The memory cost in KiB
+    /// * `iterations` - The time cost
+    /// * `parallelism` - The degree of parallelism
+    ///
+    /// # Returns
+    /// The new argon2id scheme, or an internal error if the costs are out of range
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> AccountResult<Self> {
+        let params = Params::new(memory_kib, iterations, parallelism, None)
+            .map_err(|e| AccountError::Internal(format!("invalid argon2 parameters: {}", e)))?;
+        Ok(Argon2idScheme { params })
+    }
+
+    /// Gets the argon2id hasher with the configured costs.
+    ///
+    /// # Returns
+    /// The hasher
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+/// The argon2id password scheme.
+impl PasswordScheme for Argon2idScheme {
+    fn hash(&self, password: &str) -> AccountResult<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AccountError::Internal(e.to_string()))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> bool {
+        // The costs of the hash itself are used, so outdated hashes still verify
+        PasswordHash::new(hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$argon2id$")
+    }
+
+    fn is_current(&self, hash: &str) -> bool {
+        PasswordHash::new(hash)
+            .ok()
+            .and_then(|hash| Params::try_from(&hash).ok())
+            .is_some_and(|params| {
+                params.m_cost() == self.params.m_cost()
+                    && params.t_cost() == self.params.t_cost()
+                    && params.p_cost() == self.params.p_cost()
+            })
+    }
+}
@@ -0,0 +1,281 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use super::password_schemes::{Argon2idScheme, BcryptScheme, PasswordScheme};
+use crate::errors::{AccountError, AccountResult};
+use rocket::serde::Deserialize;
+use rocket::tokio::{sync::Semaphore, task};
+
+/// The password behind the dummy hash.
+const DUMMY_PASSWORD: &str = "dummy password for unknown accounts";
+
+/// The password hashing configuration.
+///
+/// # Fields
+/// * `max_concurrency` - The hashes computed at once, one per cpu if `0`
+/// * `max_queue` - The hashes waiting for a free slot before new ones are rejected
+/// * `algorithm` - The scheme of new hashes, `argon2id` or `bcrypt`
+/// * `bcrypt_cost` - The bcrypt cost, the log2 of the rounds
+/// * `argon2_memory_kib` - The argon2id memory cost in KiB
+/// * `argon2_iterations` - The argon2id time cost
+/// * `argon2_parallelism` - The argon2id degree of parallelism
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct PasswordHashingConfig {
+    pub max_concurrency: usize,
+    pub max_queue: usize,
+    pub algorithm: String,
+    pub bcrypt_cost: u32,
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+}
+
+/// The default password hashing configuration.
+impl Default for PasswordHashingConfig {
+    fn default() -> Self {
+        PasswordHashingConfig {
+            max_concurrency: 0,
+            max_queue: 64,
+            algorithm: "argon2id".to_string(),
+            bcrypt_cost: 10,
+            argon2_memory_kib: 19_456,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+        }
+    }
+}
+
+/// The outcome of a password verification.
+///
+/// # Variants
+/// * `Invalid` - The password does not match
+/// * `Valid` - The password matches and the hash is current
+/// * `Upgraded` - The password matches, with a new hash replacing an outdated one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    Upgraded(String),
+}
+
+/// The password hasher.
+///
+/// New passwords are hashed with the configured scheme. Hashes of the
+/// other schemes, or with other costs, still verify, and are rehashed
+/// with the configured scheme on the next successful login, so users
+/// migrate without a password reset.
+///
+/// Hashing is slow on purpose, so hashing and verification run on the
+/// blocking thread pool instead of the async workers serving requests.
+/// At most `max_concurrency` of them run at once and `max_queue` more
+/// may wait; anything beyond that is rejected with `TooManyRequests`.
+///
+/// Logins with unknown emails verify against a dummy hash, so they take
+/// as long as logins with a wrong password and cannot be told apart.
+/// The dummy hash uses the current scheme, so accounts whose hash is
+/// still of a legacy scheme answer in a different time until they are
+/// migrated by their next login.
+///
+/// # Fields
+/// * `current` - The scheme of new hashes
+/// * `legacy` - The schemes of hashes still to be migrated
+/// * `permits` - The free hashing slots
+/// * `pending` - The hashes running or waiting for a slot
+/// * `capacity` - The hashes that may be running or waiting at once
+/// * `dummy` - The dummy hash, created with the current scheme on first use
+///
+/// # Methods
+/// * `new` - Creates a new password hasher
+/// * `hash` - Hashes a password
+/// * `verify` - Verifies a password against a hash
+/// * `verify_dummy` - Verifies a password against the dummy hash
+/// * `run` - Runs a hashing job on the blocking thread pool
+pub struct PasswordHasher {
+    current: Arc<dyn PasswordScheme>,
+    legacy: Arc<Vec<Box<dyn PasswordScheme>>>,
+    permits: Arc<Semaphore>,
+    pending: Arc<AtomicUsize>,
+    capacity: usize,
+    dummy: Arc<OnceLock<String>>,
+}
+
+/// The default password hasher has one slot per cpu.
+impl Default for PasswordHasher {
+    fn default() -> Self {
+        PasswordHasher::new(&PasswordHashingConfig::default())
+            .expect("default password hashing config is valid")
+    }
+}
+
+/// The password hasher implementation.
+impl PasswordHasher {
+    /// Creates a new password hasher.
+    ///
+    /// # Arguments
+    /// * `config` - The password hashing configuration
+    ///
+    /// # Returns
+    /// The new password hasher, or an internal error if the
+    /// algorithm is unknown or its costs are out of range
+    pub fn new(config: &PasswordHashingConfig) -> AccountResult<Self> {
+        // Hashes of the other scheme are verified with it until they are upgraded
+        let bcrypt = Box::new(BcryptScheme::new(config.bcrypt_cost));
+        let argon2id = Box::new(Argon2idScheme::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+        )?);
+        let (current, legacy): (Box<dyn PasswordScheme>, Box<dyn PasswordScheme>) =
+            match config.algorithm.as_str() {
+                "argon2id" => (argon2id, bcrypt),
+                "bcrypt" => (bcrypt, argon2id),
+                algorithm => {
+                    return Err(AccountError::Internal(format!(
+                        "unknown password hashing algorithm: {}",
+                        algorithm
+                    )))
+                }
+            };
+
+        let concurrency = match config.max_concurrency {
+            0 => std::thread::available_parallelism().map_or(1, |cpus| cpus.get()),
+            max_concurrency => max_concurrency,
+        };
+        Ok(PasswordHasher {
+            current: Arc::from(current),
+            legacy: Arc::new(vec![legacy]),
+            permits: Arc::new(Semaphore::new(concurrency)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            capacity: concurrency + config.max_queue,
+            dummy: Arc::new(OnceLock::new()),
+        })
+    }
+
+    /// Hash a password with the configured scheme.
+    ///
+    /// # Arguments
+    /// * `password` - The password to hash
+    ///
+    /// # Returns
+    /// The hashed password, `TooManyRequests` if the queue is full,
+    /// or an internal error if hashing failed
+    pub async fn hash(&self, password: String) -> AccountResult<String> {
+        let current = self.current.clone();
+        self.run(move || current.hash(&password)).await
+    }
+
+    /// Verify a password against a hash of any known scheme.
+    ///
+    /// An outdated hash is rehashed in the same job, so the caller
+    /// can store the new hash without waiting for another slot.
+    ///
+    /// # Arguments
+    /// * `password` - The password to verify
+    /// * `hash` - The password hash
+    ///
+    /// # Returns
+    /// The verification, `TooManyRequests` if the queue is full,
+    /// or an internal error if rehashing failed
+    pub async fn verify(&self, password: String, hash: String) -> AccountResult<Verification> {
+        let current = self.current.clone();
+        let legacy = self.legacy.clone();
+        self.run(move || {
+            // Find the scheme of the hash, unknown hashes never match
+            let scheme = std::iter::once(current.as_ref())
+                .chain(legacy.iter().map(|scheme| scheme.as_ref()))
+                .find(|scheme| scheme.recognizes(&hash));
+            let Some(scheme) = scheme else {
+                return Ok(Verification::Invalid);
+            };
+            if !scheme.verify(&password, &hash) {
+                return Ok(Verification::Invalid);
+            }
+
+            // Rehash if the scheme or its costs changed
+            if current.recognizes(&hash) && current.is_current(&hash) {
+                Ok(Verification::Valid)
+            } else {
+                Ok(Verification::Upgraded(current.hash(&password)?))
+            }
+        })
+        .await
+    }
+
+    /// Verify a password against the dummy hash.
+    ///
+    /// Takes as long as verifying a current hash, for accounts that do
+    /// not exist. The first call also creates the dummy hash.
+    ///
+    /// # Arguments
+    /// * `password` - The password to verify
+    ///
+    /// # Returns
+    /// Nothing, `TooManyRequests` if the queue is full, or an internal
+    /// error if the dummy hash could not be created
+    pub async fn verify_dummy(&self, password: String) -> AccountResult<()> {
+        let current = self.current.clone();
+        let dummy = self.dummy.clone();
+        self.run(move || {
+            let hash = match dummy.get() {
+                Some(hash) => hash,
+                None => {
+                    let hash = current.hash(DUMMY_PASSWORD)?;
+                    dummy.get_or_init(|| hash)
+                }
+            };
+            current.verify(&password, hash);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run a hashing job on the blocking thread pool.
+    ///
+    /// # Arguments
+    /// * `job` - The job to run
+    ///
+    /// # Returns
+    /// The result of the job, or `TooManyRequests` if the queue is full
+    async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> AccountResult<T> + Send + 'static,
+    ) -> AccountResult<T> {
+        // Claim a place in the queue, or reject the job
+        let claimed = self.pending.fetch_add(1, Ordering::SeqCst);
+        let place = QueuePlace(self.pending.clone());
+        if claimed >= self.capacity {
+            return Err(AccountError::TooManyRequests);
+        }
+
+        // Wait for a free slot, then hash without blocking the async workers.
+        // The job owns its slot, so a cancelled request cannot free it early.
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| AccountError::Internal(e.to_string()))?;
+        task::spawn_blocking(move || {
+            let _slot = (place, permit);
+            job()
+        })
+        .await
+        .map_err(|e| AccountError::Internal(e.to_string()))?
+    }
+}
+
+/// A place in the hashing queue.
+///
+/// The place is released when dropped.
+///
+/// # Fields
+/// * `0` - The hashes running or waiting for a slot
+struct QueuePlace(Arc<AtomicUsize>);
+
+/// Releases the place in the queue.
+impl Drop for QueuePlace {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
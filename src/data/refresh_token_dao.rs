@@ -0,0 +1,95 @@
+use super::refresh_token_entity::{RefreshFamilyEntity, RefreshTokenEntity};
+use crate::errors::AccountResult;
+use rocket::async_trait;
+
+/// The Refresh Token Data Access Object.
+///
+/// This data access object is used to access stored refresh tokens
+/// and their families. Implementations must be thread safe so they
+/// can be shared between requests as a trait object.
+///
+/// # Methods
+/// * `get_refresh_token` - Gets a refresh token by hash
+/// * `get_refresh_family` - Gets a refresh token family by id
+/// * `get_refresh_families` - Gets the refresh token families of an account
+/// * `create_refresh_family` - Creates a family with its first token
+/// * `rotate_refresh_token` - Consumes a token and adds its successor
+/// * `revoke_refresh_family` - Revokes a family and every token in it
+#[async_trait]
+pub trait RefreshTokenDao: Send + Sync {
+    /// Gets a refresh token by hash.
+    ///
+    /// # Arguments
+    /// * `id` - The hash of the token
+    ///
+    /// # Returns
+    /// The token entity, or `NotFound` if no token has the hash
+    async fn get_refresh_token(&self, id: String) -> AccountResult<RefreshTokenEntity>;
+
+    /// Gets a refresh token family by id.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the family
+    ///
+    /// # Returns
+    /// The family entity, or `NotFound` if no family has the id
+    async fn get_refresh_family(&self, id: String) -> AccountResult<RefreshFamilyEntity>;
+
+    /// Gets the refresh token families of an account.
+    ///
+    /// Families that are revoked or expired may be left out.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    ///
+    /// # Returns
+    /// The family entities, possibly none
+    async fn get_refresh_families(
+        &self,
+        account_id: String,
+    ) -> AccountResult<Vec<RefreshFamilyEntity>>;
+
+    /// Creates a family with its first token.
+    ///
+    /// # Arguments
+    /// * `family` - The new family
+    /// * `token` - The first token of the family
+    ///
+    /// # Returns
+    /// Nothing if both were stored, `Conflict` if either already exists
+    async fn create_refresh_family(
+        &self,
+        family: RefreshFamilyEntity,
+        token: RefreshTokenEntity,
+    ) -> AccountResult<()>;
+
+    /// Consumes a token and adds its successor.
+    ///
+    /// The token and the family are written over the versions that
+    /// were read, so a token can only be exchanged once and never
+    /// after its family was revoked.
+    ///
+    /// # Arguments
+    /// * `token` - The token to consume, as read
+    /// * `family` - The family of the token, with its new last use and expiry
+    /// * `next` - The successor of the token
+    ///
+    /// # Returns
+    /// Nothing if the token was rotated, `PreconditionFailed` if the
+    /// token or its family changed since they were read
+    async fn rotate_refresh_token(
+        &self,
+        token: RefreshTokenEntity,
+        family: RefreshFamilyEntity,
+        next: RefreshTokenEntity,
+    ) -> AccountResult<()>;
+
+    /// Revokes a family and every token in it.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the family
+    ///
+    /// # Returns
+    /// Nothing if the family is revoked, `NotFound` if it does not exist
+    async fn revoke_refresh_family(&self, id: String) -> AccountResult<()>;
+}
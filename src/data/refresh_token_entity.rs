@@ -0,0 +1,124 @@
+use rocket::serde::{Deserialize, Serialize};
+
+/// The key prefix of refresh tokens in the state store.
+pub const REFRESH_TOKEN_PREFIX: &str = "refresh:";
+
+/// The key prefix of refresh token families in the state store.
+pub const REFRESH_FAMILY_PREFIX: &str = "refresh_family:";
+
+/// The key prefix of session index entries in the state store.
+pub const SESSION_INDEX_PREFIX: &str = "sessions:";
+
+/// A stored refresh token.
+///
+/// Only a hash of the token is stored, the token itself is only
+/// known to the client. A token is consumed when it is exchanged
+/// for a new one, and can never be exchanged again.
+///
+/// # Fields
+/// * `id` - The SHA-256 hash of the token
+/// * `family_id` - The family of tokens rotated from one login
+/// * `account_id` - The account the token belongs to
+/// * `expires_at` - The expiry time, in seconds since the epoch
+/// * `consumed` - True once the token was exchanged
+/// * `etag` - The version of the token, not stored
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct RefreshTokenEntity {
+    pub id: String,
+    pub family_id: String,
+    pub account_id: String,
+    pub expires_at: u64,
+    #[serde(default)]
+    pub consumed: bool,
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// A stored refresh token family.
+///
+/// Every login starts a family, and every token rotated from it
+/// joins the family. Revoking the family revokes all of them. A
+/// family is the session of one login, so it also records when
+/// and from where the login happened.
+///
+/// # Fields
+/// * `id` - The id of the family, also the session id
+/// * `account_id` - The account the family belongs to
+/// * `account_instance_id` - The instance id of the account, so an account
+///   created again with the same id does not inherit the family
+/// * `created_at` - The login time, in seconds since the epoch
+/// * `last_used_at` - The time of the last refresh, in seconds since the epoch
+/// * `expires_at` - The expiry time of its newest token, in seconds since the epoch
+/// * `user_agent` - The user agent of the login, if sent
+/// * `ip` - The client ip address of the login, if known
+/// * `revoked` - True once the family was revoked
+/// * `etag` - The version of the family, not stored
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct RefreshFamilyEntity {
+    pub id: String,
+    pub account_id: String,
+    #[serde(default)]
+    pub account_instance_id: String,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub last_used_at: u64,
+    pub expires_at: u64,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub revoked: bool,
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// A session index entry.
+///
+/// Lists the refresh token families of an account, so its sessions
+/// can be found with plain key reads. Families may outlive their
+/// entry briefly, and entries may name families that expired.
+///
+/// # Fields
+/// * `family_ids` - The ids of the families of the account
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionIndexEntry {
+    pub family_ids: Vec<String>,
+}
+
+/// Gets the state store key of a refresh token.
+///
+/// # Arguments
+/// * `id` - The hash of the token
+///
+/// # Returns
+/// The token key, `refresh:<hash>`
+pub fn refresh_token_key(id: &str) -> String {
+    format!("{}{}", REFRESH_TOKEN_PREFIX, id)
+}
+
+/// Gets the state store key of a refresh token family.
+///
+/// # Arguments
+/// * `id` - The id of the family
+///
+/// # Returns
+/// The family key, `refresh_family:<id>`
+pub fn refresh_family_key(id: &str) -> String {
+    format!("{}{}", REFRESH_FAMILY_PREFIX, id)
+}
+
+/// Gets the state store key of the session index entry of an account.
+///
+/// # Arguments
+/// * `account_id` - The id of the account
+///
+/// # Returns
+/// The index key, `sessions:<account id>`
+pub fn session_index_key(account_id: &str) -> String {
+    format!("{}{}", SESSION_INDEX_PREFIX, account_id)
+}
@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::field_error::FieldError;
+
+/// The result of an account operation.
+pub type AccountResult<T> = Result<T, AccountError>;
+
+/// The account error.
+///
+/// This error is shared by the data and service layers so that
+/// a missing account, a bad request and a failing state store
+/// can be told apart by the presentation layer.
+///
+/// # Variants
+/// * `NotFound` - The account does not exist
+/// * `Conflict` - The account clashes with an existing account
+/// * `InvalidCredentials` - The email or password is wrong
+/// * `InvalidToken` - The token is unknown, expired or revoked
+/// * `InvalidMfaCode` - The second factor code is wrong or was already used
+/// * `Forbidden` - The access token does not allow the operation
+/// * `Suspended` - The account is suspended and cannot be used
+/// * `EmailNotVerified` - The account has to verify its email first
+/// * `PreconditionFailed` - The account changed since the given version was read
+/// * `StoreUnavailable` - The state store could not be reached or failed
+/// * `TooManyRequests` - The server is too busy to handle the request now
+/// * `LockedOut` - Too many logins failed, retry after the given seconds
+/// * `Serialization` - Data from the state store could not be decoded
+/// * `Validation` - The request data is invalid, listing every offending field
+/// * `Internal` - An unexpected internal failure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountError {
+    NotFound,
+    Conflict(String),
+    InvalidCredentials,
+    InvalidToken,
+    InvalidMfaCode,
+    Forbidden,
+    Suspended,
+    EmailNotVerified,
+    PreconditionFailed,
+    StoreUnavailable(String),
+    TooManyRequests,
+    LockedOut(u64),
+    Serialization(String),
+    Validation(Vec<FieldError>),
+    Internal(String),
+}
+
+/// The account error display implementation.
+impl Display for AccountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            AccountError::NotFound => write!(f, "Account not found"),
+            AccountError::Conflict(reason) => write!(f, "Account conflict: {}", reason),
+            AccountError::InvalidCredentials => write!(f, "Invalid email or password"),
+            AccountError::InvalidToken => write!(f, "Invalid, expired or revoked token"),
+            AccountError::InvalidMfaCode => {
+                write!(f, "Invalid or already used two-factor code")
+            }
+            AccountError::Forbidden => write!(f, "Not allowed to perform this operation"),
+            AccountError::Suspended => write!(f, "Account suspended"),
+            AccountError::EmailNotVerified => write!(f, "Email not verified"),
+            AccountError::PreconditionFailed => {
+                write!(f, "Account was modified since it was last read")
+            }
+            AccountError::StoreUnavailable(reason) => {
+                write!(f, "Account store unavailable: {}", reason)
+            }
+            AccountError::TooManyRequests => write!(f, "Too many requests, retry later"),
+            AccountError::LockedOut(retry_after) => write!(
+                f,
+                "Too many failed logins, retry in {} seconds",
+                retry_after
+            ),
+            AccountError::Serialization(reason) => {
+                write!(f, "Account data could not be decoded: {}", reason)
+            }
+            AccountError::Validation(errors) => {
+                let fields: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{} {}", error.field, error.message))
+                    .collect();
+                write!(f, "Invalid account data: {}", fields.join(", "))
+            }
+            AccountError::Internal(reason) => write!(f, "Internal error: {}", reason),
+        }
+    }
+}
+
+/// The account error implementation.
+impl Error for AccountError {}
+
+/// Converts http client errors into account errors.
+impl From<reqwest::Error> for AccountError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            AccountError::Serialization(error.to_string())
+        } else {
+            AccountError::StoreUnavailable(error.to_string())
+        }
+    }
+}
+
+/// Converts json errors into account errors.
+impl From<rocket::serde::json::serde_json::Error> for AccountError {
+    fn from(error: rocket::serde::json::serde_json::Error) -> Self {
+        AccountError::Serialization(error.to_string())
+    }
+}
@@ -0,0 +1,86 @@
+use super::problem::Problem;
+use rocket::{http::Status, Catcher, Request};
+
+/// Catcher for malformed requests.
+#[catch(400)]
+fn bad_request() -> Problem {
+    Problem::new(Status::BadRequest, "The request could not be understood")
+}
+
+/// Catcher for unknown routes.
+#[catch(404)]
+fn not_found(request: &Request) -> Problem {
+    Problem::new(
+        Status::NotFound,
+        format!("No resource matches {} {}", request.method(), request.uri()),
+    )
+}
+
+/// Catcher for conflicting requests.
+#[catch(409)]
+fn conflict() -> Problem {
+    Problem::new(
+        Status::Conflict,
+        "The request conflicts with the current state of the resource",
+    )
+}
+
+/// Catcher for unsupported content types.
+#[catch(415)]
+fn unsupported_media_type(request: &Request) -> Problem {
+    let content_type = request
+        .content_type()
+        .map(|content_type| content_type.to_string())
+        .unwrap_or_else(|| "none".to_string());
+    Problem::new(
+        Status::UnsupportedMediaType,
+        format!("Content type {} is not supported", content_type),
+    )
+}
+
+/// Catcher for request bodies that cannot be parsed.
+#[catch(422)]
+fn unprocessable_entity() -> Problem {
+    Problem::new(
+        Status::UnprocessableEntity,
+        "The request body could not be parsed",
+    )
+}
+
+/// Catcher for unexpected failures.
+#[catch(500)]
+fn internal_server_error() -> Problem {
+    Problem::new(Status::InternalServerError, "An unexpected error occurred")
+}
+
+/// Catcher for an unavailable service.
+#[catch(503)]
+fn service_unavailable() -> Problem {
+    Problem::new(
+        Status::ServiceUnavailable,
+        "The service is temporarily unavailable",
+    )
+}
+
+/// Catcher for any other error status.
+#[catch(default)]
+fn default_catcher(status: Status, _request: &Request) -> Problem {
+    Problem::new(status, "The request failed")
+}
+
+/// Gets the problem+json catchers for the server.
+///
+/// # Returns
+/// The catchers to register
+pub fn catchers() -> Vec<Catcher> {
+    catchers![
+        bad_request,
+        not_found,
+        conflict,
+        unsupported_media_type,
+        unprocessable_entity,
+        internal_server_error,
+        service_unavailable,
+        default_catcher
+    ]
+}
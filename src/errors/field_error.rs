@@ -0,0 +1,31 @@
+use rocket::serde::Serialize;
+
+/// A validation failure on a single field.
+///
+/// # Fields
+/// * `field` - The name of the offending field
+/// * `message` - What is wrong with the field
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// The field error implementation.
+impl FieldError {
+    /// Creates a new field error.
+    ///
+    /// # Arguments
+    /// * `field` - The name of the offending field
+    /// * `message` - What is wrong with the field
+    ///
+    /// # Returns
+    /// The new field error
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+// Exports the error modules
+mod account_error;
+mod catchers;
+mod field_error;
+mod problem;
+
+// Public exports
+pub use account_error::{AccountError, AccountResult};
+pub use catchers::catchers;
+pub use field_error::FieldError;
+pub use problem::Problem;
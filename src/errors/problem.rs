@@ -0,0 +1,111 @@
+use crate::request_id::RequestId;
+use rocket::{
+    http::{ContentType, Status},
+    response::{self, Responder},
+    serde::{
+        json::{serde_json::Map, Json, Value},
+        Serialize,
+    },
+    Request,
+};
+
+/// The generic problem type, see RFC 7807 section 4.2.
+const ABOUT_BLANK: &str = "about:blank";
+
+/// An RFC 7807 problem details document.
+///
+/// Every failure of the api is reported as `application/problem+json`.
+/// The `instance` and `request_id` members are filled in from the
+/// request when the problem is sent.
+///
+/// # Fields
+/// * `problem_type` - A URI reference identifying the problem type
+/// * `title` - A short summary of the problem type
+/// * `status` - The http status code
+/// * `detail` - An explanation specific to this occurrence
+/// * `instance` - The request path the problem occurred on
+/// * `request_id` - The id of the request the problem occurred on
+/// * `extensions` - Additional problem type specific members
+///
+/// # Methods
+/// * `new` - Creates a new generic problem for a status
+/// * `with_type` - Sets the problem type and title
+/// * `with_extension` - Adds an extension member
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub instance: String,
+    pub request_id: String,
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// The problem implementation.
+impl Problem {
+    /// Creates a new generic problem for a status.
+    ///
+    /// # Arguments
+    /// * `status` - The http status
+    /// * `detail` - An explanation of the problem
+    ///
+    /// # Returns
+    /// The new problem, titled with the status reason
+    pub fn new(status: Status, detail: impl Into<String>) -> Self {
+        Problem {
+            problem_type: ABOUT_BLANK.to_string(),
+            title: status.reason_lossy().to_string(),
+            status: status.code,
+            detail: detail.into(),
+            instance: String::new(),
+            request_id: String::new(),
+            extensions: Map::new(),
+        }
+    }
+
+    /// Sets the problem type and title.
+    ///
+    /// # Arguments
+    /// * `problem_type` - The problem type slug, e.g. `account-not-found`
+    /// * `title` - A short summary of the problem type
+    ///
+    /// # Returns
+    /// The problem
+    pub fn with_type(mut self, problem_type: &str, title: &str) -> Self {
+        self.problem_type = format!("urn:account-api:problem:{}", problem_type);
+        self.title = title.to_string();
+        self
+    }
+
+    /// Adds an extension member.
+    ///
+    /// # Arguments
+    /// * `name` - The member name
+    /// * `value` - The member value
+    ///
+    /// # Returns
+    /// The problem
+    pub fn with_extension(mut self, name: &str, value: Value) -> Self {
+        self.extensions.insert(name.to_string(), value);
+        self
+    }
+}
+
+/// Sends the problem as `application/problem+json`.
+impl<'r> Responder<'r, 'static> for Problem {
+    fn respond_to(mut self, request: &'r Request<'_>) -> response::Result<'static> {
+        // Tie the problem to the request
+        self.instance = request.uri().path().to_string();
+        self.request_id = RequestId::of(request).0;
+
+        let status = Status::from_code(self.status).unwrap_or(Status::InternalServerError);
+        let mut response = Json(self).respond_to(request)?;
+        response.set_status(status);
+        response.set_header(ContentType::new("application", "problem+json"));
+        Ok(response)
+    }
+}
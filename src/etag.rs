@@ -0,0 +1,63 @@
+use rocket::{
+    http::Header,
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+    Request,
+};
+
+/// The `If-Match` request header.
+///
+/// Holds the entity tag the client expects the resource to have.
+/// A missing header or `*` matches any version.
+///
+/// # Fields
+/// * `0` - The unquoted entity tag, if a specific version is expected
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IfMatch(pub Option<String>);
+
+/// The if match request guard.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let etag = request
+            .headers()
+            .get_one("If-Match")
+            // Only the first tag of a list is used
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty() && *tag != "*")
+            .map(unquote);
+        Outcome::Success(IfMatch(etag))
+    }
+}
+
+/// A response tagged with an `ETag` header.
+///
+/// # Fields
+/// * `0` - The inner response
+/// * `1` - The unquoted entity tag, if known
+pub struct Tagged<R>(pub R, pub Option<String>);
+
+/// Sends the inner response with its `ETag` header.
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Tagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.0.respond_to(request)?;
+        if let Some(etag) = self.1 {
+            response.set_header(Header::new("ETag", format!("\"{}\"", etag)));
+        }
+        Ok(response)
+    }
+}
+
+/// Removes the quotes and weakness prefix from an entity tag.
+///
+/// # Arguments
+/// * `tag` - The entity tag as sent by the client
+///
+/// # Returns
+/// The bare entity tag
+fn unquote(tag: &str) -> String {
+    tag.trim_start_matches("W/").trim_matches('"').to_string()
+}
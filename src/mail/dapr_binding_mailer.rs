@@ -0,0 +1,88 @@
+use super::mailer::{MailMessage, Mailer};
+use crate::data::{DaprClient, DaprClientConfig};
+use crate::errors::AccountResult;
+use rocket::async_trait;
+use rocket::serde::json::serde_json::json;
+
+/// The dapr binding mailer.
+///
+/// Sends mails through a dapr SMTP output binding, which holds the
+/// SMTP server and its credentials.
+///
+/// # Fields
+/// * `client` - The client sending requests to the sidecar
+/// * `binding_url` - The url of the output binding
+/// * `from` - The sender address of every mail
+///
+/// # Methods
+/// * `new` - Creates a new dapr binding mailer
+/// * `send` - Sends a mail through the output binding
+///
+/// # Traits
+/// * `Mailer` - The mailer trait
+pub struct DaprBindingMailer {
+    client: DaprClient,
+    binding_url: String,
+    from: String,
+}
+
+/// The dapr binding mailer implementation.
+impl DaprBindingMailer {
+    /// Creates a new dapr binding mailer.
+    ///
+    /// # Arguments
+    /// * `sidecar_url` - The base url of the dapr sidecar, e.g. `http://localhost:3500`
+    /// * `binding` - The name of the SMTP output binding
+    /// * `from` - The sender address of every mail
+    /// * `config` - The dapr client configuration
+    ///
+    /// # Returns
+    /// The new dapr binding mailer, or an internal error if the client could not be built
+    pub fn new(
+        sidecar_url: impl Into<String>,
+        binding: &str,
+        from: impl Into<String>,
+        config: DaprClientConfig,
+    ) -> AccountResult<Self> {
+        Ok(DaprBindingMailer {
+            client: DaprClient::new(config)?,
+            binding_url: format!("{}/v1.0/bindings/{}", sidecar_url.into(), binding),
+            from: from.into(),
+        })
+    }
+}
+
+/// The dapr binding mailer implementation.
+#[async_trait]
+impl Mailer for DaprBindingMailer {
+    /// Sends a mail through the output binding.
+    ///
+    /// # Arguments
+    /// * `message` - The mail to send
+    ///
+    /// # Returns
+    /// Nothing once the binding accepted the mail
+    async fn send(&self, message: MailMessage) -> AccountResult<()> {
+        let request = json!({
+            "operation": "create",
+            "data": message.body,
+            "metadata": {
+                "emailFrom": self.from,
+                "emailTo": message.to,
+                "subject": message.subject,
+            },
+        });
+
+        // Never retried, a mail that was sent would be sent twice
+        self.client
+            .send(
+                self.client
+                    .post(&self.binding_url)
+                    .body(request.to_string()),
+                false,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
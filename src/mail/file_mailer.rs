@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+
+use super::mailer::{MailMessage, Mailer};
+use crate::errors::{AccountError, AccountResult};
+use rocket::async_trait;
+use rocket::tokio::fs;
+use uuid::Uuid;
+
+/// The file mailer.
+///
+/// Writes every mail to its own `.eml` file in a directory instead
+/// of delivering it. File names start with a time ordered id, so a
+/// directory listing shows the mails in the order they were sent.
+///
+/// # Fields
+/// * `from` - The sender address of every mail
+/// * `directory` - The directory mails are written to, created on first use
+///
+/// # Methods
+/// * `new` - Creates a new file mailer
+/// * `send` - Writes a mail to a new file
+///
+/// # Traits
+/// * `Mailer` - The mailer trait
+pub struct FileMailer {
+    from: String,
+    directory: PathBuf,
+}
+
+/// The file mailer implementation.
+impl FileMailer {
+    /// Creates a new file mailer.
+    ///
+    /// # Arguments
+    /// * `from` - The sender address of every mail
+    /// * `directory` - The directory mails are written to
+    ///
+    /// # Returns
+    /// The new file mailer
+    pub fn new(from: impl Into<String>, directory: impl AsRef<Path>) -> Self {
+        FileMailer {
+            from: from.into(),
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+}
+
+/// The file mailer implementation.
+#[async_trait]
+impl Mailer for FileMailer {
+    /// Writes a mail to a new file.
+    ///
+    /// # Arguments
+    /// * `message` - The mail to write
+    ///
+    /// # Returns
+    /// Nothing once the file was written
+    async fn send(&self, message: MailMessage) -> AccountResult<()> {
+        let contents = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from, message.to, message.subject, message.body
+        );
+        let path = self.directory.join(format!("{}.eml", Uuid::now_v7()));
+        fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| AccountError::Internal(format!("mail directory not writable: {}", e)))?;
+        fs::write(&path, contents)
+            .await
+            .map_err(|e| AccountError::Internal(format!("mail not written: {}", e)))
+    }
+}
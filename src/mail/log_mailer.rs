@@ -0,0 +1,54 @@
+use super::mailer::{MailMessage, Mailer};
+use crate::errors::AccountResult;
+use rocket::async_trait;
+
+/// The log mailer.
+///
+/// Writes every mail to the log instead of delivering it, so the
+/// links they contain can be followed during local development.
+///
+/// # Fields
+/// * `from` - The sender address of every mail
+///
+/// # Methods
+/// * `new` - Creates a new log mailer
+/// * `send` - Writes a mail to the log
+///
+/// # Traits
+/// * `Mailer` - The mailer trait
+pub struct LogMailer {
+    from: String,
+}
+
+/// The log mailer implementation.
+impl LogMailer {
+    /// Creates a new log mailer.
+    ///
+    /// # Arguments
+    /// * `from` - The sender address of every mail
+    ///
+    /// # Returns
+    /// The new log mailer
+    pub fn new(from: impl Into<String>) -> Self {
+        LogMailer { from: from.into() }
+    }
+}
+
+/// The log mailer implementation.
+#[async_trait]
+impl Mailer for LogMailer {
+    /// Writes a mail to the log.
+    ///
+    /// # Arguments
+    /// * `message` - The mail to write
+    ///
+    /// # Returns
+    /// Nothing
+    async fn send(&self, message: MailMessage) -> AccountResult<()> {
+        info!(
+            "Mail from {} to {}: {}\n{}",
+            self.from, message.to, message.subject, message.body
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crate::errors::AccountResult;
+use rocket::async_trait;
+use rocket::serde::Deserialize;
+
+/// The mail transport.
+///
+/// # Variants
+/// * `Log` - Mails are written to the log
+/// * `File` - Mails are written to files in a directory
+/// * `Dapr` - Mails are sent through a dapr SMTP output binding
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum MailTransport {
+    #[default]
+    Log,
+    File,
+    Dapr,
+}
+
+/// The mail configuration.
+///
+/// # Fields
+/// * `transport` - The way mails are delivered
+/// * `from` - The sender address of every mail
+/// * `binding` - The name of the dapr SMTP output binding, for the dapr transport
+/// * `directory` - The directory mails are written to, for the file transport
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    pub from: String,
+    pub binding: String,
+    pub directory: String,
+}
+
+/// The default mail configuration.
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            transport: MailTransport::Log,
+            from: "no-reply@localhost".to_string(),
+            binding: "smtp".to_string(),
+            directory: "mail".to_string(),
+        }
+    }
+}
+
+/// A plain text mail.
+///
+/// # Fields
+/// * `to` - The recipient address
+/// * `subject` - The subject line
+/// * `body` - The plain text body
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// A mailer.
+///
+/// Mailers deliver mails to account owners. Implementations must be
+/// thread safe so they can be shared between requests.
+///
+/// # Methods
+/// * `send` - Sends a mail
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// Sends a mail.
+    ///
+    /// # Arguments
+    /// * `message` - The mail to send
+    ///
+    /// # Returns
+    /// Nothing once the mail was handed over for delivery
+    async fn send(&self, message: MailMessage) -> AccountResult<()>;
+}
+
+/// Sends a mail without waiting for it.
+///
+/// Failures are logged, since nobody is left to report them to.
+///
+/// # Arguments
+/// * `mailer` - The mailer to send with
+/// * `message` - The mail to send
+pub fn send_in_background(mailer: Arc<dyn Mailer>, message: MailMessage) {
+    rocket::tokio::spawn(async move {
+        let subject = message.subject.clone();
+        if let Err(e) = mailer.send(message).await {
+            error!("Mail \"{}\" could not be sent: {}", subject, e);
+        }
+    });
+}
@@ -0,0 +1,11 @@
+// Exports the mail modules
+mod dapr_binding_mailer;
+mod file_mailer;
+mod log_mailer;
+mod mailer;
+
+// Public exports
+pub use dapr_binding_mailer::DaprBindingMailer;
+pub use file_mailer::FileMailer;
+pub use log_mailer::LogMailer;
+pub use mailer::{send_in_background, MailConfig, MailMessage, MailTransport, Mailer};
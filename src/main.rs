@@ -1,36 +1,91 @@
+mod bearer;
+mod client_info;
+mod config;
 mod data;
+mod errors;
+mod etag;
+mod mail;
+mod request_id;
 mod services;
+mod tokens;
 
-use data::DaprAccountDao;
+use bearer::BearerToken;
+use client_info::ClientInfo;
+use config::{AccountStore, ApiConfig};
+use data::{
+    sidecar_url, AccountDao, ActionTokenDao, DaprAccountDao, InMemoryAccountDao,
+    InMemoryActionTokenDao, InMemoryLoginAttemptsDao, InMemoryRefreshTokenDao, LoginAttemptsDao,
+    PasswordHasher, RefreshTokenDao, ADMIN_ROLE,
+};
+use errors::{AccountError, AccountResult, FieldError, Problem};
+use etag::{IfMatch, Tagged};
+use mail::{DaprBindingMailer, FileMailer, LogMailer, MailConfig, MailTransport, Mailer};
+use request_id::{RequestId, RequestIdFairing};
 use rocket::{
-    fairing::{Fairing, Info, Kind},
+    fairing::{AdHoc, Fairing, Info, Kind},
     http::{Header, Status},
-    response::status::Custom,
+    response::{
+        self,
+        status::{Created, Custom},
+        Responder,
+    },
     serde::json::{
         serde_json::{json, Value},
         Json,
     },
-    Request, Response, State,
+    Build, Either, Request, Response, Rocket, State,
+};
+use services::{
+    AccountDetails, AccountModel, AccountPatchModel, AccountQueryModel, AccountService,
+    AccountUpdateModel, CredentialsModel, DaprAccountService, EmailVerificationModel,
+    ForgotPasswordModel, LockoutConfig, Lockouts, MfaCodeModel, MfaEnrollmentModel, MfaLoginModel,
+    PasswordChangeModel, PasswordResetModel, SignupConfig, Signups, Validate,
+    VerificationRequestModel,
+};
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokens::{
+    EmailVerificationConfig, EmailVerifications, LoginDetails, MfaConfig, PasswordResetConfig,
+    PasswordResets, RefreshTokenModel, RefreshTokens, TokenConfig, TokenIssuer, TwoFactor,
 };
-use services::{AccountModel, AccountService, CredentialsModel, DaprAccountService};
 
 // Set testing file
 #[cfg(test)]
+mod test_support;
+#[cfg(test)]
 mod tests;
 
 #[macro_use]
 extern crate rocket;
 
-/// API endpoint to get all accounts.
+/// API endpoint to get a page of accounts.
+///
+/// The page holds the emails of the accounts and can be filtered by
+/// email, so in enumeration safe mode only accounts with the admin
+/// role may list accounts.
 ///
 /// # Arguments
 /// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the admin, in enumeration safe mode
+/// * `query` - The `limit`, `page_token`, `sort`, `name`, `email` and `status` parameters
 ///
 /// # Returns
-/// * `Custom<Value>` - The list of accounts
-#[get("/")]
-async fn get_accounts(provider: &State<ServiceProvider>) -> Custom<Value> {
-    Custom(Status::Ok, json!(provider.service.get_accounts().await))
+/// * `Custom<Value>` - The page of accounts, with `items` and `next_page_token`
+#[get("/?<query..>")]
+async fn get_accounts(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    query: AccountQueryModel,
+) -> Result<Custom<Value>, AccountError> {
+    if provider.signups.is_enumeration_safe() {
+        provider
+            .tokens
+            .verify(bearer.token()?)?
+            .require_role(ADMIN_ROLE)?;
+    }
+    let page = provider.service.get_accounts(query).await?;
+    Ok(Custom(Status::Ok, json!(page)))
 }
 
 /// API endpoint to get an account by id.
@@ -40,111 +95,832 @@ async fn get_accounts(provider: &State<ServiceProvider>) -> Custom<Value> {
 /// * `id` - The id of the account to get
 ///
 /// # Returns
-/// * `Custom<Value>` - The account
+/// * `Tagged<Custom<Value>>` - The account, with its version as `ETag`
 #[get("/id/<id>")]
-async fn get_account_by_id(provider: &State<ServiceProvider>, id: String) -> Custom<Value> {
-    match provider.service.get_account_by_id(id).await {
-        Some(account) => Custom(Status::Ok, json!(account)),
-        None => Custom(Status::NotFound, json!({})),
-    }
+async fn get_account_by_id(
+    provider: &State<ServiceProvider>,
+    id: String,
+) -> Result<Tagged<Custom<Value>>, AccountError> {
+    let account = provider.service.get_account_by_id(id).await?;
+    let etag = account.etag.clone();
+    Ok(Tagged(Custom(Status::Ok, json!(account)), etag))
 }
 
 /// API endpoint to get an account by email.
 ///
+/// The lookup tells whether an email has an account, so in enumeration
+/// safe mode only accounts with the admin role may use it.
+///
 /// # Arguments
 /// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the admin, in enumeration safe mode
 /// * `email` - The email of the account to get
 ///
 /// # Returns
 /// * `Custom<Value>` - The account
 #[get("/email/<email>")]
-async fn get_account_by_email(provider: &State<ServiceProvider>, email: String) -> Custom<Value> {
-    match provider.service.get_account_by_email(email).await {
-        Some(account) => Custom(Status::Ok, json!(account)),
-        None => Custom(Status::NotFound, json!({})),
+async fn get_account_by_email(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    email: String,
+) -> Result<Custom<Value>, AccountError> {
+    if provider.signups.is_enumeration_safe() {
+        provider
+            .tokens
+            .verify(bearer.token()?)?
+            .require_role(ADMIN_ROLE)?;
     }
+    let account = provider.service.get_account_by_email(email).await?;
+    Ok(Custom(Status::Ok, json!(account)))
 }
 
 /// API endpoint to create an account.
 ///
+/// A verification link is mailed to the email of the account. In
+/// enumeration safe mode every signup is answered with `202 Accepted`,
+/// and the owner of a taken email gets a mail instead.
+///
 /// # Arguments
 /// * `provider` - The service provider for account operations
-/// * `account` - The account to create
+/// * `account` - The account to create, the id is generated when omitted
 ///
 /// # Returns
-/// * `Custom<Value>` - The created account
+/// * `Created<Json<AccountDetails>>` - The created account and its location
+/// * `Status` - Accepted, in enumeration safe mode
 #[post("/", format = "application/json", data = "<account>")]
 async fn create_account(
     provider: &State<ServiceProvider>,
     account: Json<AccountModel>,
-) -> Custom<Value> {
-    if provider.service.create_account(account.into_inner()).await {
-        Custom(Status::Created, json!({}))
-    } else {
-        Custom(
-            Status::Conflict,
-            json!({ "error": "Account already exists" }),
-        )
+) -> Result<Either<Created<Json<AccountDetails>>, Status>, AccountError> {
+    let created = provider
+        .signups
+        .create(provider.service.as_ref(), account.into_inner())
+        .await?;
+    if let Some(account) = &created {
+        provider.email_verifications.start(account).await;
+    }
+
+    // Only reveal the account if taken emails are not hidden
+    match created {
+        Some(account) if !provider.signups.is_enumeration_safe() => {
+            let location = uri!("/api/v1/accounts", get_account_by_id(id = &account.id));
+            Ok(Either::Left(
+                Created::new(location.to_string()).body(Json(account)),
+            ))
+        }
+        _ => Ok(Either::Right(Status::Accepted)),
     }
 }
 
 /// API endpoint to update an account.
 ///
+/// Only the account itself may update it. A changed email has to be
+/// verified again, a verification link is mailed to it.
+///
 /// # Arguments
 /// * `provider` - The service provider for account operations
-/// * `account` - The account to update
+/// * `bearer` - The access token of the account
+/// * `if_match` - The expected version of the account
+/// * `account` - The account to update, any password is ignored
 ///
 /// # Returns
 /// * `Status` - The status of the operation
 #[put("/", format = "application/json", data = "<account>")]
-async fn update_account(provider: &State<ServiceProvider>, account: Json<AccountModel>) -> Status {
-    if provider.service.update_account(account.into_inner()).await {
-        Status::NoContent
-    } else {
-        Status::NotFound
+async fn update_account(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    if_match: IfMatch,
+    account: Json<AccountUpdateModel>,
+) -> Result<Status, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&account.id)?;
+    let previous = provider
+        .service
+        .get_account_by_id(account.id.clone())
+        .await
+        .ok();
+    provider
+        .service
+        .update_account(account.into_inner(), if_match.0)
+        .await?;
+    if let Some(previous) = previous {
+        provider
+            .email_verifications
+            .restart(provider.service.as_ref(), &previous)
+            .await;
     }
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to partially update an account.
+///
+/// Only the account itself may update it. A changed email has to be
+/// verified again, a verification link is mailed to it.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
+/// * `id` - The id of the account to patch
+/// * `if_match` - The expected version of the account
+/// * `patch` - The JSON merge patch of the name and email
+///
+/// # Returns
+/// * `Tagged<Custom<Value>>` - The patched account, with its new version as `ETag`
+#[patch("/id/<id>", format = "application/merge-patch+json", data = "<patch>")]
+async fn patch_account(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    id: String,
+    if_match: IfMatch,
+    patch: Json<AccountPatchModel>,
+) -> Result<Tagged<Custom<Value>>, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&id)?;
+    let previous = provider.service.get_account_by_id(id.clone()).await.ok();
+    let account = provider
+        .service
+        .patch_account(id, patch.into_inner(), if_match.0)
+        .await?;
+    if let Some(previous) = previous {
+        provider
+            .email_verifications
+            .restart(provider.service.as_ref(), &previous)
+            .await;
+    }
+    let etag = account.etag.clone();
+    Ok(Tagged(Custom(Status::Ok, json!(account)), etag))
+}
+
+/// API endpoint to change the password of an account.
+///
+/// Wrong current passwords count as failed logins.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `client` - The ip address failures are counted for
+/// * `id` - The id of the account
+/// * `change` - The current and the new password
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post("/id/<id>/password", format = "application/json", data = "<change>")]
+async fn change_password(
+    provider: &State<ServiceProvider>,
+    client: ClientInfo,
+    id: String,
+    change: Json<PasswordChangeModel>,
+) -> Result<Status, AccountError> {
+    let account = provider.service.get_account_by_id(id.clone()).await?;
+    let change = provider.service.change_password(id, change.into_inner());
+    check_password(provider, &client, &account, change).await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to ask for a password reset.
+///
+/// Mails a reset link if the email belongs to an account. The answer
+/// is the same either way, so emails of accounts cannot be probed.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `forgot` - The email of the account
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post("/password/forgot", format = "application/json", data = "<forgot>")]
+async fn forgot_password(
+    provider: &State<ServiceProvider>,
+    forgot: Json<ForgotPasswordModel>,
+) -> Result<Status, AccountError> {
+    provider
+        .password_resets
+        .request(provider.service.as_ref(), forgot.into_inner())
+        .await?;
+    Ok(Status::Accepted)
+}
+
+/// API endpoint to reset a password with a mailed token.
+///
+/// The token is used up, and every session of the account is
+/// revoked once the new password is set.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `reset` - The reset token and the new password
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post("/password/reset", format = "application/json", data = "<reset>")]
+async fn reset_password(
+    provider: &State<ServiceProvider>,
+    reset: Json<PasswordResetModel>,
+) -> Result<Status, AccountError> {
+    let account_id = provider
+        .password_resets
+        .reset(provider.service.as_ref(), reset.into_inner())
+        .await?;
+    provider
+        .refresh_tokens
+        .revoke_sessions(&account_id, None)
+        .await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to verify an email with a mailed token.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `verification` - The verification token
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post("/verify-email", format = "application/json", data = "<verification>")]
+async fn verify_email(
+    provider: &State<ServiceProvider>,
+    verification: Json<EmailVerificationModel>,
+) -> Result<Status, AccountError> {
+    provider
+        .email_verifications
+        .verify(provider.service.as_ref(), verification.into_inner())
+        .await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to ask for a new verification link.
+///
+/// Mails a verification link if the email belongs to an account that
+/// has not verified it. The response is the same either way.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `request` - The email of the account
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post(
+    "/verify-email/resend",
+    format = "application/json",
+    data = "<request>"
+)]
+async fn resend_verification(
+    provider: &State<ServiceProvider>,
+    request: Json<VerificationRequestModel>,
+) -> Result<Status, AccountError> {
+    provider
+        .email_verifications
+        .request(provider.service.as_ref(), request.into_inner())
+        .await?;
+    Ok(Status::Accepted)
 }
 
 /// API endpoint to delete an account by id.
 ///
+/// Only the account itself may delete it. Every session of the
+/// account is revoked with it.
+///
 /// # Arguments
 /// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
 /// * `account_id` - The id of the account to delete
+/// * `if_match` - The expected version of the account
 #[delete("/id/<account_id>")]
-async fn delete_account(provider: &State<ServiceProvider>, account_id: String) -> Status {
-    if provider
+async fn delete_account(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    account_id: String,
+    if_match: IfMatch,
+) -> Result<Status, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&account_id)?;
+    provider
         .service
-        .delete_account(account_id.to_string())
-        .await
-    {
-        Status::NoContent
-    } else {
-        Status::NotFound
-    }
+        .delete_account(account_id.clone(), if_match.0)
+        .await?;
+    provider
+        .refresh_tokens
+        .revoke_sessions(&account_id, None)
+        .await?;
+    Ok(Status::NoContent)
 }
 
 /// API endpoint to get validate an account by email and password.
 ///
+/// Every successful validation starts a new session. Accounts with
+/// an unverified email are rejected if verification is required.
+/// Repeated failures lock out the email and the client for a while.
+/// Accounts with two-factor authentication get a challenge instead,
+/// completed at `/validate/mfa`.
+///
 /// # Arguments
 /// * `provider` - The service provider for account operations
+/// * `client` - The user agent and ip address recorded with the session
 /// * `credentials` - The credentials to validate
+///
+/// # Returns
+/// * `Custom<Value>` - The account with its session, an access token and a refresh token,
+///   or the challenge with `mfa_required`, `mfa_token` and `expires_in`
 #[post("/validate", format = "application/json", data = "<credentials>")]
 async fn validate_account(
     provider: &State<ServiceProvider>,
+    client: ClientInfo,
     credentials: Json<CredentialsModel>,
-) -> Custom<Value> {
-    match provider
+) -> Result<Custom<Value>, AccountError> {
+    let email = credentials.email.clone();
+    let ip = client.ip.as_deref();
+    provider.lockouts.check(&email, ip).await?;
+    let account = match provider
         .service
         .validate_account(credentials.into_inner())
         .await
     {
-        Some(account) => Custom(Status::Ok, json!(account)),
-        None => Custom(Status::NotFound, json!({})),
+        Ok(account) => account,
+        Err(AccountError::InvalidCredentials) => {
+            provider.lockouts.record_failure(&email, ip).await;
+            return Err(AccountError::InvalidCredentials);
+        }
+        Err(e) => return Err(e),
+    };
+
+    // Suspended accounts are refused once the password proved the owner
+    account.check_active()?;
+
+    // Failures are only forgotten once the second factor is verified too
+    if account.mfa_enabled {
+        provider.email_verifications.check(&account)?;
+        let challenge = provider.two_factor.challenge(&account).await?;
+        return Ok(Custom(Status::Ok, json!(challenge)));
+    }
+    finish_login(provider, client, account).await
+}
+
+/// API endpoint to complete a login with a second factor.
+///
+/// The challenge is used up by every try, a wrong code needs the
+/// password again. Wrong codes count as failed logins.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `client` - The user agent and ip address recorded with the session
+/// * `login` - The challenge token with a code or a recovery code
+///
+/// # Returns
+/// * `Custom<Value>` - The account with its session, an access token and a refresh token
+#[post("/validate/mfa", format = "application/json", data = "<login>")]
+async fn validate_mfa(
+    provider: &State<ServiceProvider>,
+    client: ClientInfo,
+    login: Json<MfaLoginModel>,
+) -> Result<Custom<Value>, AccountError> {
+    login.validate()?;
+    let login = login.into_inner();
+    let challenged = provider.two_factor.take_challenge(&login.mfa_token).await?;
+    let ip = client.ip.as_deref();
+    provider.lockouts.check(&challenged.email, ip).await?;
+    match provider
+        .two_factor
+        .verify(
+            provider.service.as_ref(),
+            &challenged.account_id,
+            &login.factor,
+        )
+        .await
+    {
+        Ok(()) => {}
+        Err(AccountError::InvalidMfaCode) => {
+            provider
+                .lockouts
+                .record_failure(&challenged.email, ip)
+                .await;
+            return Err(AccountError::InvalidMfaCode);
+        }
+        Err(e) => return Err(e),
+    }
+
+    // Deleted accounts cannot complete their login, suspended ones are refused
+    let account = match provider
+        .service
+        .get_account_by_id(challenged.account_id)
+        .await
+    {
+        Ok(account) => account,
+        Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+        Err(e) => return Err(e),
+    };
+    account.check_active()?;
+    finish_login(provider, client, account).await
+}
+
+/// Finishes a login once every factor is verified.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `client` - The user agent and ip address recorded with the session
+/// * `account` - The account logging in
+///
+/// # Returns
+/// * `Custom<Value>` - The account with its session, an access token and a refresh token
+async fn finish_login(
+    provider: &ServiceProvider,
+    client: ClientInfo,
+    account: AccountDetails,
+) -> Result<Custom<Value>, AccountError> {
+    provider.lockouts.record_success(&account.email).await;
+    provider.email_verifications.check(&account)?;
+    let issued = provider
+        .refresh_tokens
+        .issue(&account, client.user_agent, client.ip)
+        .await?;
+    let token = provider.tokens.issue(&account, &issued.session_id)?;
+    Ok(Custom(
+        Status::Ok,
+        json!(LoginDetails {
+            account,
+            token,
+            session_id: issued.session_id,
+            refresh_token: issued.refresh_token
+        }),
+    ))
+}
+
+/// Checks the password of an account like a login does.
+///
+/// Locked out emails and clients are rejected and wrong passwords
+/// count as failed logins, so passwords cannot be guessed outside of
+/// logins either. Failures of accounts with two-factor authentication
+/// are only forgotten once both factors are verified.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `client` - The ip address failures are counted for
+/// * `account` - The account the password belongs to
+/// * `operation` - The operation checking the password
+///
+/// # Returns
+/// The result of the operation, or `LockedOut` if it was not tried
+async fn check_password<T>(
+    provider: &ServiceProvider,
+    client: &ClientInfo,
+    account: &AccountDetails,
+    operation: impl Future<Output = AccountResult<T>>,
+) -> AccountResult<T> {
+    let ip = client.ip.as_deref();
+    provider.lockouts.check(&account.email, ip).await?;
+    match operation.await {
+        Ok(result) => {
+            if !account.mfa_enabled {
+                provider.lockouts.record_success(&account.email).await;
+            }
+            Ok(result)
+        }
+        Err(AccountError::InvalidCredentials) => {
+            provider.lockouts.record_failure(&account.email, ip).await;
+            Err(AccountError::InvalidCredentials)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// API endpoint to start a TOTP enrollment.
+///
+/// Wrong passwords count as failed logins.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `client` - The ip address failures are counted for
+/// * `id` - The id of the account
+/// * `enrollment` - The password of the account
+///
+/// # Returns
+/// * `Custom<Value>` - The `secret` and the `otpauth_uri` for authenticator apps
+#[post(
+    "/id/<id>/mfa/totp",
+    format = "application/json",
+    data = "<enrollment>"
+)]
+async fn enroll_totp(
+    provider: &State<ServiceProvider>,
+    client: ClientInfo,
+    id: String,
+    enrollment: Json<MfaEnrollmentModel>,
+) -> Result<Custom<Value>, AccountError> {
+    let account = provider.service.get_account_by_id(id.clone()).await?;
+    let enrollment =
+        provider
+            .two_factor
+            .enroll(provider.service.as_ref(), id, enrollment.into_inner());
+    let enrollment = check_password(provider, &client, &account, enrollment).await?;
+    Ok(Custom(Status::Ok, json!(enrollment)))
+}
+
+/// Checks a second factor of an account like a login does.
+///
+/// Locked out emails and clients are rejected and wrong codes count
+/// as failed logins, so codes cannot be guessed outside of logins.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `client` - The ip address failures are counted for
+/// * `account` - The account the factor belongs to
+/// * `operation` - The operation checking the factor
+///
+/// # Returns
+/// The result of the operation, or `LockedOut` if it was not tried
+async fn check_factor<T>(
+    provider: &ServiceProvider,
+    client: &ClientInfo,
+    account: &AccountDetails,
+    operation: impl Future<Output = AccountResult<T>>,
+) -> AccountResult<T> {
+    let ip = client.ip.as_deref();
+    provider.lockouts.check(&account.email, ip).await?;
+    match operation.await {
+        Err(AccountError::InvalidMfaCode) => {
+            provider.lockouts.record_failure(&account.email, ip).await;
+            Err(AccountError::InvalidMfaCode)
+        }
+        result => result,
+    }
+}
+
+/// API endpoint to confirm a TOTP enrollment with a first code.
+///
+/// Only the account itself may confirm its enrollment, and wrong codes
+/// count as failed logins. Logins need a second factor from then on.
+/// The recovery codes are only shown once.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
+/// * `client` - The ip address failures are counted for
+/// * `id` - The id of the account
+/// * `confirmation` - The code of the authenticator app
+///
+/// # Returns
+/// * `Custom<Value>` - The single-use `recovery_codes`
+#[post(
+    "/id/<id>/mfa/totp/confirm",
+    format = "application/json",
+    data = "<confirmation>"
+)]
+async fn confirm_totp(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    client: ClientInfo,
+    id: String,
+    confirmation: Json<MfaCodeModel>,
+) -> Result<Custom<Value>, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&id)?;
+    let account = provider.service.get_account_by_id(id.clone()).await?;
+    let codes =
+        provider
+            .two_factor
+            .confirm(provider.service.as_ref(), id, confirmation.into_inner());
+    let codes = check_factor(provider, &client, &account, codes).await?;
+    Ok(Custom(Status::Ok, json!(codes)))
+}
+
+/// API endpoint to turn two-factor authentication off.
+///
+/// Only the account itself may turn it off, and wrong codes count as
+/// failed logins, so codes cannot be guessed here.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
+/// * `client` - The ip address failures are counted for
+/// * `id` - The id of the account
+/// * `factor` - A code or a recovery code
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post("/id/<id>/mfa/disable", format = "application/json", data = "<factor>")]
+async fn disable_mfa(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    client: ClientInfo,
+    id: String,
+    factor: Json<MfaCodeModel>,
+) -> Result<Status, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&id)?;
+    let account = provider.service.get_account_by_id(id.clone()).await?;
+    let disabled = provider
+        .two_factor
+        .disable(provider.service.as_ref(), id, factor.into_inner());
+    check_factor(provider, &client, &account, disabled).await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to unlock an account locked out by failed logins.
+///
+/// Only accounts with the admin role may unlock accounts. Failures
+/// counted for a client ip address are only forgotten if it is given.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the admin
+/// * `id` - The id of the account
+/// * `ip` - The client ip address to unlock too, if any
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[delete("/id/<id>/lockout?<ip>")]
+async fn unlock_account(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    id: String,
+    ip: Option<String>,
+) -> Result<Status, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_role(ADMIN_ROLE)?;
+
+    // Counters are kept under the address as clients are seen
+    let ip = ip.map(|ip| ip.parse::<IpAddr>()).transpose().map_err(|_| {
+        AccountError::Validation(vec![FieldError::new("ip", "must be an ip address")])
+    })?;
+    let account = provider.service.get_account_by_id(id).await?;
+    provider.lockouts.unlock(&account.email).await?;
+    if let Some(ip) = ip {
+        provider.lockouts.unlock_ip(&ip.to_string()).await?;
     }
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to exchange a refresh token for new tokens.
+///
+/// The refresh token is consumed. Presenting it again revokes every
+/// token rotated from the same login.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `refresh` - The refresh token to exchange
+///
+/// # Returns
+/// * `Custom<Value>` - The account with a new access token and refresh token
+#[post("/token/refresh", format = "application/json", data = "<refresh>")]
+async fn refresh_token(
+    provider: &State<ServiceProvider>,
+    refresh: Json<RefreshTokenModel>,
+) -> Result<Custom<Value>, AccountError> {
+    let rotated = provider
+        .refresh_tokens
+        .rotate(&refresh.refresh_token)
+        .await?;
+
+    // Deleted accounts cannot refresh their tokens, not even once their id
+    // is taken again, and suspended ones are refused
+    let account = match provider.service.get_account_by_id(rotated.account_id).await {
+        Ok(account) if account.instance_id == rotated.account_instance_id => account,
+        Ok(_) | Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+        Err(e) => return Err(e),
+    };
+    account.check_active()?;
+    let token = provider.tokens.issue(&account, &rotated.session_id)?;
+    Ok(Custom(
+        Status::Ok,
+        json!(LoginDetails {
+            account,
+            token,
+            session_id: rotated.session_id,
+            refresh_token: rotated.refresh_token
+        }),
+    ))
+}
+
+/// API endpoint to get the sessions of an account.
+///
+/// Only the account itself may list its sessions.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
+/// * `id` - The id of the account
+///
+/// # Returns
+/// * `Custom<Value>` - The live sessions as `items`, most recently used first
+#[get("/id/<id>/sessions")]
+async fn get_sessions(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    id: String,
+) -> Result<Custom<Value>, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&id)?;
+    provider.service.get_account_by_id(id.clone()).await?;
+    let sessions = provider.refresh_tokens.sessions(&id).await?;
+    Ok(Custom(Status::Ok, json!({ "items": sessions })))
+}
+
+/// API endpoint to revoke a session of an account.
+///
+/// Only the account itself may revoke its sessions.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
+/// * `id` - The id of the account
+/// * `session_id` - The id of the session to revoke
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[delete("/id/<id>/sessions/<session_id>")]
+async fn delete_session(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    id: String,
+    session_id: String,
+) -> Result<Status, AccountError> {
+    provider
+        .tokens
+        .verify(bearer.token()?)?
+        .require_account(&id)?;
+    provider
+        .refresh_tokens
+        .revoke_session(&id, &session_id)
+        .await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to revoke every other session of an account.
+///
+/// Only the account itself may revoke its sessions. The session the
+/// access token was issued to is kept.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `bearer` - The access token of the account
+/// * `id` - The id of the account
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[delete("/id/<id>/sessions")]
+async fn delete_sessions(
+    provider: &State<ServiceProvider>,
+    bearer: BearerToken,
+    id: String,
+) -> Result<Status, AccountError> {
+    let claims = provider.tokens.verify(bearer.token()?)?;
+    claims.require_account(&id)?;
+    provider.service.get_account_by_id(id.clone()).await?;
+    provider
+        .refresh_tokens
+        .revoke_sessions(&id, Some(&claims.sid))
+        .await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to log out.
+///
+/// Revokes the refresh token and every token rotated from the same
+/// login. Unknown tokens are accepted, so logging out is idempotent.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+/// * `refresh` - The refresh token to revoke
+///
+/// # Returns
+/// * `Status` - The status of the operation
+#[post("/logout", format = "application/json", data = "<refresh>")]
+async fn logout(
+    provider: &State<ServiceProvider>,
+    refresh: Json<RefreshTokenModel>,
+) -> Result<Status, AccountError> {
+    provider
+        .refresh_tokens
+        .revoke(&refresh.refresh_token)
+        .await?;
+    Ok(Status::NoContent)
+}
+
+/// API endpoint to get the keys verifying access tokens.
+///
+/// # Arguments
+/// * `provider` - The service provider for account operations
+///
+/// # Returns
+/// * `Custom<Value>` - The JSON web key set
+#[get("/.well-known/jwks.json")]
+fn jwks(provider: &State<ServiceProvider>) -> Custom<Value> {
+    Custom(Status::Ok, provider.tokens.jwks())
 }
 
 /// API endpoint to handle the OPTIONS request.
-/// 
+///
 /// This is a requirement for CORS. This method lets
 /// the client know CORS is acceptable.
 #[options("/<_..>")]
@@ -152,9 +928,364 @@ fn options() {
     /* Left blank. This will trigger the Cors fairing response. */
 }
 
+/// The data access objects of one account store.
+type StoreDaos = (
+    Box<dyn AccountDao>,
+    Box<dyn RefreshTokenDao>,
+    Arc<dyn ActionTokenDao>,
+    Box<dyn LoginAttemptsDao>,
+);
+
 /// The service provider for account operations.
+///
+/// Route handlers only see the `AccountService` trait, so any
+/// implementation (or decorator around one) can be provided.
+///
+/// # Fields
+/// * `service` - The account service
+/// * `tokens` - The access token issuer
+/// * `refresh_tokens` - The refresh tokens
+/// * `password_resets` - The password resets
+/// * `email_verifications` - The email verifications
+/// * `lockouts` - The login lockouts
+/// * `signups` - The signups
+/// * `two_factor` - The two-factor authentication
 struct ServiceProvider {
-    service: DaprAccountService,
+    service: Box<dyn AccountService>,
+    tokens: TokenIssuer,
+    refresh_tokens: RefreshTokens,
+    password_resets: PasswordResets,
+    email_verifications: EmailVerifications,
+    lockouts: Lockouts,
+    signups: Signups,
+    two_factor: TwoFactor,
+}
+
+/// The service provider implementation.
+impl ServiceProvider {
+    /// Creates a new service provider around an account service.
+    ///
+    /// Access tokens are signed and TOTP secrets encrypted with
+    /// ephemeral keys, refresh and mailed tokens, challenges and failed
+    /// logins are kept in memory and mails are logged.
+    ///
+    /// # Arguments
+    /// * `service` - The account service to provide
+    ///
+    /// # Returns
+    /// The new service provider
+    fn new(service: impl AccountService + 'static) -> Self {
+        let action_token_dao = Arc::new(InMemoryActionTokenDao::new());
+        let mailer = Arc::new(LogMailer::new(MailConfig::default().from));
+        ServiceProvider {
+            service: Box::new(service),
+            tokens: TokenIssuer::default(),
+            refresh_tokens: RefreshTokens::new(
+                Box::new(InMemoryRefreshTokenDao::new()),
+                TokenConfig::default().refresh_token_ttl_secs,
+            ),
+            password_resets: PasswordResets::new(
+                action_token_dao.clone(),
+                mailer.clone(),
+                PasswordResetConfig::default(),
+            ),
+            email_verifications: EmailVerifications::new(
+                action_token_dao.clone(),
+                mailer.clone(),
+                EmailVerificationConfig::default(),
+            ),
+            lockouts: Lockouts::new(
+                Box::new(InMemoryLoginAttemptsDao::new()),
+                LockoutConfig::default(),
+            ),
+            signups: Signups::new(mailer, SignupConfig::default()),
+            two_factor: TwoFactor::new(action_token_dao, MfaConfig::default())
+                .expect("ephemeral mfa encryption key"),
+        }
+    }
+
+    /// Replaces the access token issuer.
+    ///
+    /// # Arguments
+    /// * `tokens` - The access token issuer to use
+    ///
+    /// # Returns
+    /// The service provider using the issuer
+    fn with_token_issuer(self, tokens: TokenIssuer) -> Self {
+        ServiceProvider { tokens, ..self }
+    }
+
+    /// Replaces the refresh tokens.
+    ///
+    /// # Arguments
+    /// * `refresh_tokens` - The refresh tokens to use
+    ///
+    /// # Returns
+    /// The service provider using the refresh tokens
+    fn with_refresh_tokens(self, refresh_tokens: RefreshTokens) -> Self {
+        ServiceProvider {
+            refresh_tokens,
+            ..self
+        }
+    }
+
+    /// Replaces the password resets.
+    ///
+    /// # Arguments
+    /// * `password_resets` - The password resets to use
+    ///
+    /// # Returns
+    /// The service provider using the password resets
+    fn with_password_resets(self, password_resets: PasswordResets) -> Self {
+        ServiceProvider {
+            password_resets,
+            ..self
+        }
+    }
+
+    /// Replaces the email verifications.
+    ///
+    /// # Arguments
+    /// * `email_verifications` - The email verifications to use
+    ///
+    /// # Returns
+    /// The service provider using the email verifications
+    fn with_email_verifications(self, email_verifications: EmailVerifications) -> Self {
+        ServiceProvider {
+            email_verifications,
+            ..self
+        }
+    }
+
+    /// Replaces the login lockouts.
+    ///
+    /// # Arguments
+    /// * `lockouts` - The login lockouts to use
+    ///
+    /// # Returns
+    /// The service provider using the lockouts
+    fn with_lockouts(self, lockouts: Lockouts) -> Self {
+        ServiceProvider { lockouts, ..self }
+    }
+
+    /// Replaces the signups.
+    ///
+    /// # Arguments
+    /// * `signups` - The signups to use
+    ///
+    /// # Returns
+    /// The service provider using the signups
+    fn with_signups(self, signups: Signups) -> Self {
+        ServiceProvider { signups, ..self }
+    }
+
+    /// Replaces the two-factor authentication.
+    ///
+    /// # Arguments
+    /// * `two_factor` - The two-factor authentication to use
+    ///
+    /// # Returns
+    /// The service provider using the two-factor authentication
+    fn with_two_factor(self, two_factor: TwoFactor) -> Self {
+        ServiceProvider { two_factor, ..self }
+    }
+
+    /// Creates a new service provider from the api configuration.
+    ///
+    /// # Arguments
+    /// * `config` - The api configuration
+    ///
+    /// # Returns
+    /// The new service provider, or an error if the account store,
+    /// the token signing key or the mfa encryption key could not be set up
+    async fn from_config(config: &ApiConfig) -> AccountResult<Self> {
+        // Hash passwords off the async workers, with bounded concurrency, and
+        // create the dummy hash before the first login with an unknown email
+        let hasher = PasswordHasher::new(&config.password_hashing)?;
+        hasher.verify_dummy(String::new()).await?;
+
+        // Load the access token signing key
+        if !config.tokens.has_key() {
+            warn!("No access token signing key configured, using an ephemeral key");
+        }
+        let tokens = TokenIssuer::new(&config.tokens)?;
+        if config.mfa.encryption_key.is_none() {
+            warn!("No mfa encryption key configured, using an ephemeral key");
+        }
+
+        // Select the account store backend, tokens and failed logins are kept next to the accounts
+        let (account_dao, refresh_token_dao, action_token_dao, login_attempts_dao): StoreDaos =
+            match config.account_store {
+                AccountStore::Dapr => {
+                    let dao = DaprAccountDao::new(config.dapr_client.clone())?
+                        .with_password_hasher(hasher);
+                    if config.backfill_email_index {
+                        match dao.backfill_email_index().await {
+                            Ok(added) => println!("Added {} email index entries", added),
+                            Err(e) => error!("Email index backfill failed: {}", e),
+                        }
+                    }
+                    let refresh_token_dao = dao.refresh_token_dao();
+                    let action_token_dao = dao.action_token_dao();
+                    let login_attempts_dao = dao.login_attempts_dao();
+                    (
+                        Box::new(dao),
+                        Box::new(refresh_token_dao),
+                        Arc::new(action_token_dao),
+                        Box::new(login_attempts_dao),
+                    )
+                }
+                AccountStore::Memory => (
+                    Box::new(InMemoryAccountDao::new().with_password_hasher(hasher)),
+                    Box::new(InMemoryRefreshTokenDao::new()),
+                    Arc::new(InMemoryActionTokenDao::new()),
+                    Box::new(InMemoryLoginAttemptsDao::new()),
+                ),
+            };
+        let refresh_tokens =
+            RefreshTokens::new(refresh_token_dao, config.tokens.refresh_token_ttl_secs);
+
+        // Select the mail transport
+        let mail = &config.mail;
+        let mailer: Arc<dyn Mailer> = match mail.transport {
+            MailTransport::Log => Arc::new(LogMailer::new(mail.from.clone())),
+            MailTransport::File => Arc::new(FileMailer::new(mail.from.clone(), &mail.directory)),
+            MailTransport::Dapr => Arc::new(DaprBindingMailer::new(
+                sidecar_url(),
+                &mail.binding,
+                mail.from.clone(),
+                config.dapr_client.clone(),
+            )?),
+        };
+        let password_resets = PasswordResets::new(
+            action_token_dao.clone(),
+            mailer.clone(),
+            config.password_reset.clone(),
+        );
+        let email_verifications = EmailVerifications::new(
+            action_token_dao.clone(),
+            mailer.clone(),
+            config.email_verification.clone(),
+        );
+        let two_factor = TwoFactor::new(action_token_dao, config.mfa.clone())?;
+        let signups = Signups::new(mailer, config.signup.clone());
+
+        Ok(ServiceProvider::new(DaprAccountService::new(account_dao))
+            .with_token_issuer(tokens)
+            .with_refresh_tokens(refresh_tokens)
+            .with_password_resets(password_resets)
+            .with_email_verifications(email_verifications)
+            .with_lockouts(Lockouts::new(login_attempts_dao, config.lockout.clone()))
+            .with_signups(signups)
+            .with_two_factor(two_factor))
+    }
+}
+
+/// Maps account errors to problem+json responses.
+///
+/// # Status Codes
+/// * `NotFound` - 404 Not Found
+/// * `Conflict` - 409 Conflict
+/// * `InvalidCredentials`, `InvalidToken`, `InvalidMfaCode` - 401 Unauthorized
+/// * `Forbidden`, `Suspended`, `EmailNotVerified` - 403 Forbidden
+/// * `PreconditionFailed` - 412 Precondition Failed
+/// * `StoreUnavailable` - 503 Service Unavailable
+/// * `TooManyRequests`, `LockedOut` - 429 Too Many Requests, with a `Retry-After` header
+/// * `Validation` - 422 Unprocessable Entity
+/// * `Serialization`, `Internal` - 500 Internal Server Error
+impl<'r> Responder<'r, 'static> for AccountError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let (status, problem_type, title) = match self {
+            AccountError::NotFound => (Status::NotFound, "account-not-found", "Account not found"),
+            AccountError::Conflict(_) => (Status::Conflict, "account-conflict", "Account conflict"),
+            AccountError::InvalidCredentials => (
+                Status::Unauthorized,
+                "invalid-credentials",
+                "Invalid credentials",
+            ),
+            AccountError::InvalidToken => (Status::Unauthorized, "invalid-token", "Invalid token"),
+            AccountError::InvalidMfaCode => (
+                Status::Unauthorized,
+                "invalid-mfa-code",
+                "Invalid two-factor code",
+            ),
+            AccountError::Forbidden => (Status::Forbidden, "forbidden", "Forbidden"),
+            AccountError::Suspended => {
+                (Status::Forbidden, "account-suspended", "Account suspended")
+            }
+            AccountError::EmailNotVerified => (
+                Status::Forbidden,
+                "email-not-verified",
+                "Email not verified",
+            ),
+            AccountError::PreconditionFailed => (
+                Status::PreconditionFailed,
+                "precondition-failed",
+                "Account version mismatch",
+            ),
+            AccountError::StoreUnavailable(_) => (
+                Status::ServiceUnavailable,
+                "store-unavailable",
+                "Account store unavailable",
+            ),
+            AccountError::TooManyRequests => (
+                Status::TooManyRequests,
+                "too-many-requests",
+                "Too many requests",
+            ),
+            AccountError::LockedOut(_) => (
+                Status::TooManyRequests,
+                "locked-out",
+                "Too many failed logins",
+            ),
+            AccountError::Validation(_) => (
+                Status::UnprocessableEntity,
+                "validation-failed",
+                "Invalid request data",
+            ),
+            AccountError::Serialization(_) | AccountError::Internal(_) => (
+                Status::InternalServerError,
+                "internal-error",
+                "Internal error",
+            ),
+        };
+
+        // Keep server side failure details out of the response body
+        let detail = if status.code >= 500 {
+            error!(
+                "[{}] {} {}: {}",
+                RequestId::of(request).0,
+                request.method(),
+                request.uri(),
+                self
+            );
+            "The request could not be completed, please retry later".to_string()
+        } else {
+            self.to_string()
+        };
+
+        let problem = Problem::new(status, detail).with_type(problem_type, title);
+
+        // List every offending field of invalid requests
+        match self {
+            AccountError::Validation(errors) => problem
+                .with_extension("errors", json!(errors))
+                .respond_to(request),
+            // Ask busy clients to back off
+            AccountError::TooManyRequests => {
+                let mut response = problem.respond_to(request)?;
+                response.set_header(Header::new("Retry-After", "1"));
+                Ok(response)
+            }
+            // Tell locked out clients when to try again
+            AccountError::LockedOut(retry_after) => {
+                let mut response = problem.respond_to(request)?;
+                response.set_header(Header::new("Retry-After", retry_after.to_string()));
+                Ok(response)
+            }
+            _ => problem.respond_to(request),
+        }
+    }
 }
 
 /// The CORS fairing for the server.
@@ -182,29 +1313,25 @@ impl Fairing for Cors {
         ));
         response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
         response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            "ETag, Location, X-Request-Id",
+        ));
     }
 }
 
-/// Start the rocket server.
+/// Build the rocket server without an account service.
 ///
-/// This method replaces the main method in a normal rust application.
+/// The caller is responsible for managing a `ServiceProvider`.
 ///
 /// # Returns
 /// * `rocket::Rocket` - The rocket server
-#[launch]
-fn rocket() -> _ {
-    // Notify console of starting server
-    println!("Starting server...");
-
-    // The dapr account service for account operations
-    let service: ServiceProvider = ServiceProvider {
-        service: services::DaprAccountService::new(DaprAccountDao::new()),
-    };
-
-    // Start the server
+fn server() -> Rocket<Build> {
     rocket::build()
         .attach(Cors)
-        .manage(service)
+        .attach(RequestIdFairing)
+        .register("/", errors::catchers())
+        .mount("/", routes![jwks])
         .mount(
             "/api/v1/accounts",
             routes![
@@ -215,7 +1342,56 @@ fn rocket() -> _ {
                 create_account,
                 delete_account,
                 update_account,
-                validate_account
+                patch_account,
+                change_password,
+                forgot_password,
+                reset_password,
+                verify_email,
+                resend_verification,
+                unlock_account,
+                enroll_totp,
+                confirm_totp,
+                disable_mfa,
+                validate_account,
+                validate_mfa,
+                refresh_token,
+                logout,
+                get_sessions,
+                delete_session,
+                delete_sessions
             ],
         )
 }
+
+/// Start the rocket server.
+///
+/// This method replaces the main method in a normal rust application.
+///
+/// # Returns
+/// * `rocket::Rocket` - The rocket server
+#[launch]
+fn rocket() -> _ {
+    // Notify console of starting server
+    println!("Starting server...");
+
+    // Start the server
+    server()
+        // Build the account service from the configured store once the figment is final
+        .attach(AdHoc::try_on_ignite("Account Service", |rocket| async {
+            let config = match rocket.figment().extract::<ApiConfig>() {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Invalid account api configuration: {}", e);
+                    return Err(rocket);
+                }
+            };
+            println!("Using {:?} account store", config.account_store);
+            match ServiceProvider::from_config(&config).await {
+                Ok(provider) => Ok(rocket.manage(provider)),
+                Err(e) => {
+                    error!("Account service could not be set up: {}", e);
+                    Err(rocket)
+                }
+            }
+        }))
+}
@@ -0,0 +1,72 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    Data, Request, Response,
+};
+use uuid::Uuid;
+
+/// The header carrying the request id.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The request id.
+///
+/// Every request is tagged with an id so that a failure reported
+/// to a client can be matched with the server logs. A client
+/// supplied `X-Request-Id` header is reused, otherwise a new id
+/// is generated.
+///
+/// # Methods
+/// * `of` - Gets the request id of a request
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// The request id implementation.
+impl RequestId {
+    /// Gets the request id of a request.
+    ///
+    /// # Arguments
+    /// * `request` - The request
+    ///
+    /// # Returns
+    /// The request id, generated on first use
+    pub fn of(request: &Request<'_>) -> RequestId {
+        request
+            .local_cache(|| {
+                let id = request
+                    .headers()
+                    .get_one(REQUEST_ID_HEADER)
+                    .filter(|id| !id.is_empty() && id.len() <= 128)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+                RequestId(id)
+            })
+            .clone()
+    }
+}
+
+/// The request id fairing.
+///
+/// # Methods
+/// * `info` - The info for the fairing
+/// * `on_request` - Assigns the request id
+/// * `on_response` - Echoes the request id in the response headers
+pub struct RequestIdFairing;
+
+/// The request id fairing implementation.
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Id Fairing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        RequestId::of(request);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_header(Header::new(REQUEST_ID_HEADER, RequestId::of(request).0));
+    }
+}
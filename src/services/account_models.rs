@@ -1,6 +1,10 @@
-use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::{
+    json::serde_json::{self, Map, Value},
+    Deserialize, Serialize,
+};
 
-use crate::data::AccountEntity;
+use crate::data::{default_roles, AccountEntity, AccountStatus};
+use crate::errors::{AccountError, AccountResult, FieldError};
 
 /// The Account Model.
 ///
@@ -8,7 +12,7 @@ use crate::data::AccountEntity;
 /// the service layer and the data layer.
 ///
 /// # Fields
-/// * `id` - The id of the account
+/// * `id` - The id of the account, generated by the server when left empty on creation
 /// * `name` - The name of the account
 /// * `email` - The email of the account
 /// * `password` - The password of the account
@@ -22,6 +26,7 @@ use crate::data::AccountEntity;
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct AccountModel {
+    #[serde(default)]
     pub id: String,
     pub name: String,
     pub email: String,
@@ -51,6 +56,112 @@ impl AccountModel {
     }
 }
 
+/// The Account Update Model.
+///
+/// This model is used to transfer profile changes from the
+/// presentation layer to the service layer. It has no password,
+/// passwords are only changed through `PasswordChangeModel`, and
+/// no status, accounts are only suspended in the store.
+///
+/// # Fields
+/// * `id` - The id of the account to update
+/// * `name` - The name of the account
+/// * `email` - The email of the account
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountUpdateModel {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub email: String,
+}
+
+/// The account update model implementation.
+impl AccountUpdateModel {
+    /// Creates a new account update model from an account entity.
+    ///
+    /// # Arguments
+    /// * `entity` - The account entity to convert
+    ///
+    /// # Returns
+    /// The new account update model
+    pub fn from_entity(entity: &AccountEntity) -> Self {
+        AccountUpdateModel {
+            id: entity.id.clone(),
+            name: entity.name.clone(),
+            email: entity.email.clone(),
+        }
+    }
+}
+
+/// The Account Patch Model.
+///
+/// This model holds a JSON merge patch (RFC 7396) of the
+/// profile fields of an account.
+///
+/// # Fields
+/// * `0` - The merge patch document
+///
+/// # Methods
+/// * `fields` - Gets the patched fields
+/// * `apply_to` - Applies the patch to an account
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde", transparent)]
+pub struct AccountPatchModel(pub Value);
+
+/// The account patch model implementation.
+impl AccountPatchModel {
+    /// Gets the patched fields.
+    ///
+    /// # Returns
+    /// The members of the patch, or `None` if it is not an object
+    pub fn fields(&self) -> Option<&Map<String, Value>> {
+        self.0.as_object()
+    }
+
+    /// Applies the patch to an account.
+    ///
+    /// # Arguments
+    /// * `account` - The account to patch
+    ///
+    /// # Returns
+    /// The patched account, or `Validation` if the result is not an account
+    pub fn apply_to(&self, account: &AccountUpdateModel) -> AccountResult<AccountUpdateModel> {
+        let mut target = serde_json::to_value(account)?;
+        merge_patch(&mut target, &self.0);
+        serde_json::from_value(target)
+            .map_err(|e| AccountError::Validation(vec![FieldError::new("patch", e.to_string())]))
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7396) to a value.
+///
+/// Members of an object patch replace the members of the target,
+/// recursively for objects, and `null` members remove them. Any
+/// other patch replaces the target.
+///
+/// # Arguments
+/// * `target` - The value to patch
+/// * `patch` - The merge patch
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(members) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in members {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
 /// The Account Details.
 ///
 /// This model is used to transfer account data between
@@ -60,16 +171,38 @@ impl AccountModel {
 /// * `id` - The id of the account
 /// * `name` - The name of the account
 /// * `email` - The email of the account
+/// * `status` - The status of the account
+/// * `roles` - The roles of the account
+/// * `email_verified` - True once the owner proved access to the email
+/// * `email_verified_at` - The time the email was verified, in seconds since the epoch
+/// * `mfa_enabled` - True if logins need a second factor
+/// * `instance_id` - The random id given on creation, not sent to clients
+/// * `etag` - The version of the account, sent as the `ETag` header
 ///
 /// # Methods
 /// * `from_entity` - Creates a new account details from an account entity
 /// * `from_model` - Creates a new account details from an account model
+/// * `check_active` - Checks the account is not suspended
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct AccountDetails {
     pub id: String,
     pub name: String,
     pub email: String,
+    #[serde(default)]
+    pub status: AccountStatus,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(default)]
+    pub email_verified_at: Option<u64>,
+    #[serde(default)]
+    pub mfa_enabled: bool,
+    #[serde(skip)]
+    pub instance_id: String,
+    #[serde(skip)]
+    pub etag: Option<String>,
 }
 
 /// The account details implementation.
@@ -86,6 +219,13 @@ impl AccountDetails {
             id: entity.id.clone(),
             name: entity.name.clone(),
             email: entity.email.clone(),
+            status: entity.status,
+            roles: entity.roles.clone(),
+            email_verified: entity.email_verified,
+            email_verified_at: entity.email_verified_at,
+            mfa_enabled: entity.mfa.is_enabled(),
+            instance_id: entity.instance_id.clone(),
+            etag: entity.etag.clone(),
         }
     }
 
@@ -101,6 +241,24 @@ impl AccountDetails {
             id: model.id.clone(),
             name: model.name.clone(),
             email: model.email.clone(),
+            status: AccountStatus::Active,
+            roles: default_roles(),
+            email_verified: false,
+            email_verified_at: None,
+            mfa_enabled: false,
+            instance_id: String::new(),
+            etag: None,
+        }
+    }
+
+    /// Checks the account is not suspended.
+    ///
+    /// # Returns
+    /// Nothing, or `Suspended` if the account may not be used
+    pub fn check_active(&self) -> AccountResult<()> {
+        match self.status {
+            AccountStatus::Active => Ok(()),
+            AccountStatus::Suspended => Err(AccountError::Suspended),
         }
     }
 }
@@ -0,0 +1,98 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket::FromForm;
+
+use super::account_models::AccountDetails;
+use crate::data::{
+    AccountPage, AccountQuery, AccountStatus, SortField, SortOrder, DEFAULT_PAGE_LIMIT,
+};
+
+/// The account query model.
+///
+/// This model is used to transfer the query parameters of an
+/// account listing from the presentation layer to the service layer.
+///
+/// # Fields
+/// * `limit` - The maximum number of accounts in the page
+/// * `page_token` - The `next_page_token` of the previous page
+/// * `sort` - The field to sort by, prefixed with `-` for descending order
+/// * `name` - Only list accounts with this exact name
+/// * `email` - Only list the account with this email
+/// * `status` - Only list accounts with this status
+///
+/// # Methods
+/// * `to_query` - Converts the validated model to an account query
+#[derive(FromForm, Clone, Debug, Default)]
+pub struct AccountQueryModel {
+    pub limit: Option<usize>,
+    pub page_token: Option<String>,
+    pub sort: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub status: Option<String>,
+}
+
+/// The account query model implementation.
+impl AccountQueryModel {
+    /// Converts the model to an account query.
+    ///
+    /// Unknown sort fields and statuses fall back to their defaults,
+    /// so the model should be validated first.
+    ///
+    /// # Returns
+    /// The account query
+    pub fn to_query(&self) -> AccountQuery {
+        // Split the sort order from the sort field
+        let (sort, order) = match self.sort.as_deref() {
+            Some(sort) => match sort.strip_prefix('-') {
+                Some(field) => (SortField::parse(field), SortOrder::Desc),
+                None => (SortField::parse(sort), SortOrder::Asc),
+            },
+            None => (None, SortOrder::Asc),
+        };
+
+        AccountQuery {
+            limit: self.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+            page_token: self.page_token.clone(),
+            sort: sort.unwrap_or_default(),
+            order,
+            name: self.name.clone(),
+            email: self.email.clone(),
+            status: self.status.as_deref().and_then(AccountStatus::parse),
+        }
+    }
+}
+
+/// The account page details.
+///
+/// This model is used to transfer a page of accounts between
+/// the service layer and the presentation layer.
+///
+/// # Fields
+/// * `items` - The accounts in the page
+/// * `next_page_token` - The token of the next page, `null` on the last page
+///
+/// # Methods
+/// * `from_page` - Creates new account page details from a page of entities
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountPageDetails {
+    pub items: Vec<AccountDetails>,
+    pub next_page_token: Option<String>,
+}
+
+/// The account page details implementation.
+impl AccountPageDetails {
+    /// Creates new account page details from a page of entities.
+    ///
+    /// # Arguments
+    /// * `page` - The page of account entities to convert
+    ///
+    /// # Returns
+    /// The new account page details
+    pub fn from_page(page: &AccountPage) -> Self {
+        AccountPageDetails {
+            items: page.items.iter().map(AccountDetails::from_entity).collect(),
+            next_page_token: page.next_page_token.clone(),
+        }
+    }
+}
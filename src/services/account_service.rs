@@ -1,27 +1,46 @@
 use super::AccountDetails;
 use super::AccountModel;
+use super::AccountPageDetails;
+use super::AccountPatchModel;
+use super::AccountQueryModel;
+use super::AccountUpdateModel;
 use super::CredentialsModel;
+use super::PasswordChangeModel;
+use super::PasswordResetModel;
+use crate::data::MfaSettings;
+use crate::errors::AccountResult;
 use rocket::async_trait;
 
 /// The account service.
 ///
 /// This trait defines the interface for the account service.
+/// Implementations must be thread safe so they can be held in
+/// the rocket state as a trait object.
 ///
 /// # Methods
-/// * `get_accounts` - Gets all accounts
+/// * `get_accounts` - Gets a page of accounts
 /// * `get_account_by_id` - Gets an account by id
 /// * `get_account_by_email` - Gets an account by email
 /// * `validate_account` - Validates an account
 /// * `create_account` - Creates an account
 /// * `update_account` - Updates an account
+/// * `patch_account` - Partially updates an account
+/// * `change_password` - Changes the password of an account
+/// * `reset_password` - Sets a new password on an account
+/// * `verify_email` - Marks the email of an account as verified
+/// * `get_mfa` - Gets the two-factor settings of an account
+/// * `set_mfa` - Replaces the two-factor settings of an account
 /// * `delete_account` - Deletes an account
 #[async_trait]
-pub trait AccountService {
-    /// Gets all accounts.
+pub trait AccountService: Send + Sync {
+    /// Gets a page of accounts.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, sort order and page to get
     ///
     /// # Returns
-    /// The list of accounts
-    async fn get_accounts(&self) -> Vec<AccountDetails>;
+    /// The page of accounts, or `Validation` if the query is invalid
+    async fn get_accounts(&self, query: AccountQueryModel) -> AccountResult<AccountPageDetails>;
 
     /// Gets an account by id.
     ///
@@ -29,8 +48,8 @@ pub trait AccountService {
     /// * `id` - The id of the account
     ///
     /// # Returns
-    /// The account details
-    async fn get_account_by_id(&self, id: String) -> Option<AccountDetails>;
+    /// The account details, or `NotFound` if no account has the id
+    async fn get_account_by_id(&self, id: String) -> AccountResult<AccountDetails>;
 
     /// Gets an account by email.
     ///
@@ -38,8 +57,8 @@ pub trait AccountService {
     /// * `email` - The email of the account
     ///
     /// # Returns
-    /// The account details
-    async fn get_account_by_email(&self, email: String) -> Option<AccountDetails>;
+    /// The account details, or `NotFound` if no account has the email
+    async fn get_account_by_email(&self, email: String) -> AccountResult<AccountDetails>;
 
     /// Validates an account.
     ///
@@ -47,33 +66,128 @@ pub trait AccountService {
     /// * `credentials` - The credentials of the account
     ///
     /// # Returns
-    /// The account details
-    async fn validate_account(&self, credentials: CredentialsModel) -> Option<AccountDetails>;
+    /// The account details, or `InvalidCredentials` if the credentials are wrong
+    async fn validate_account(
+        &self,
+        credentials: CredentialsModel,
+    ) -> AccountResult<AccountDetails>;
 
     /// Creates an account.
     ///
     /// # Arguments
-    /// * `account` - The account to create
+    /// * `account` - The account to create, an empty id is generated
     ///
     /// # Returns
-    /// `true` if the account was created, otherwise `false`
-    async fn create_account(&self, account: AccountModel) -> bool;
+    /// The created account details, or `Conflict` if the id or email is already in use
+    async fn create_account(&self, account: AccountModel) -> AccountResult<AccountDetails>;
 
     /// Updates an account.
     ///
+    /// The password is left untouched, see `change_password`.
+    ///
     /// # Arguments
     /// * `account` - The account to update
+    /// * `etag` - The expected version of the account, if any
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account is not at the expected version
+    async fn update_account(
+        &self,
+        account: AccountUpdateModel,
+        etag: Option<String>,
+    ) -> AccountResult<()>;
+
+    /// Partially updates an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `patch` - The merge patch of the profile fields
+    /// * `etag` - The expected version of the account, if any
+    ///
+    /// # Returns
+    /// The patched account details, `NotFound` if the account does not exist,
+    /// `Validation` if the patch or the patched account is invalid, or
+    /// `PreconditionFailed` if the account is not at the expected version
+    async fn patch_account(
+        &self,
+        id: String,
+        patch: AccountPatchModel,
+        etag: Option<String>,
+    ) -> AccountResult<AccountDetails>;
+
+    /// Changes the password of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `change` - The current and the new password
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, `InvalidCredentials`
+    /// if the current password is wrong, or `Validation` if the new one is invalid
+    async fn change_password(&self, id: String, change: PasswordChangeModel) -> AccountResult<()>;
+
+    /// Sets a new password on an account.
+    ///
+    /// The reset token must already be checked by the caller.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `reset` - The reset token and the new password
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `Validation` if the new password is invalid
+    async fn reset_password(&self, id: String, reset: PasswordResetModel) -> AccountResult<()>;
+
+    /// Marks the email of an account as verified.
+    ///
+    /// The verification token must already be checked by the caller.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `email` - The email the token was sent to
+    ///
+    /// # Returns
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account has another email by now
+    async fn verify_email(&self, id: String, email: String) -> AccountResult<()>;
+
+    /// Gets the two-factor settings of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    ///
+    /// # Returns
+    /// The settings with the version of the account, or `NotFound` if
+    /// the account does not exist
+    async fn get_mfa(&self, id: String) -> AccountResult<(MfaSettings, Option<String>)>;
+
+    /// Replaces the two-factor settings of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `mfa` - The two-factor settings to store
+    /// * `etag` - The version the settings were read at
     ///
     /// # Returns
-    /// `true` if the account was updated, otherwise `false`
-    async fn update_account(&self, account: AccountModel) -> bool;
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account was modified concurrently
+    async fn set_mfa(
+        &self,
+        id: String,
+        mfa: MfaSettings,
+        etag: Option<String>,
+    ) -> AccountResult<()>;
 
     /// Deletes an account.
     ///
     /// # Arguments
     /// * `id` - The id of the account to delete
+    /// * `etag` - The expected version of the account, if any
     ///
     /// # Returns
-    /// `true` if the account was deleted, otherwise `false`
-    async fn delete_account(&self, id: String) -> bool;
+    /// Nothing, `NotFound` if the account does not exist, or
+    /// `PreconditionFailed` if the account is not at the expected version
+    async fn delete_account(&self, id: String, etag: Option<String>) -> AccountResult<()>;
 }
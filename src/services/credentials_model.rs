@@ -14,3 +14,119 @@ pub struct CredentialsModel {
     pub email: String,
     pub password: String,
 }
+
+/// The password change model.
+///
+/// This model is used to transfer a password change between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `current_password` - The current password of the account
+/// * `new_password` - The password to set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordChangeModel {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// The forgotten password model.
+///
+/// This model is used to request a password reset between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `email` - The email of the account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ForgotPasswordModel {
+    pub email: String,
+}
+
+/// The password reset model.
+///
+/// This model is used to transfer a password reset between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `token` - The password reset token that was mailed to the account
+/// * `new_password` - The password to set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordResetModel {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// The email verification model.
+///
+/// This model is used to transfer an email verification between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `token` - The verification token that was mailed to the account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct EmailVerificationModel {
+    pub token: String,
+}
+
+/// The verification mail request model.
+///
+/// This model is used to ask for a new verification mail between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `email` - The email of the account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct VerificationRequestModel {
+    pub email: String,
+}
+
+/// The two-factor enrollment model.
+///
+/// This model is used to start a TOTP enrollment between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `password` - The password of the account, proving the owner enrolls
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct MfaEnrollmentModel {
+    pub password: String,
+}
+
+/// The second factor model.
+///
+/// This model is used to transfer a second factor between
+/// the presentation layer and the service layer. Exactly one
+/// of the fields is given.
+///
+/// # Fields
+/// * `code` - The current code of the authenticator app
+/// * `recovery_code` - An unused recovery code
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct MfaCodeModel {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub recovery_code: Option<String>,
+}
+
+/// The two-factor login model.
+///
+/// This model is used to complete a login between
+/// the presentation layer and the service layer.
+///
+/// # Fields
+/// * `mfa_token` - The challenge token returned by the password login
+/// * `factor` - The code or recovery code
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct MfaLoginModel {
+    pub mfa_token: String,
+    #[serde(flatten)]
+    pub factor: MfaCodeModel,
+}
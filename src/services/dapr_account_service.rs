@@ -1,31 +1,43 @@
-use super::account_models::{AccountDetails, AccountModel};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::account_models::{AccountDetails, AccountModel, AccountPatchModel, AccountUpdateModel};
+use super::account_query_model::{AccountPageDetails, AccountQueryModel};
 use super::account_service::AccountService;
-use super::credentials_model::CredentialsModel;
-use crate::data::{AccountDao, AccountEntity, DaprAccountDao};
+use super::credentials_model::{CredentialsModel, PasswordChangeModel, PasswordResetModel};
+use super::validation::Validate;
+use crate::data::{AccountDao, AccountEntity, MfaSettings};
+use crate::errors::{AccountError, AccountResult};
 use rocket::async_trait;
+use uuid::Uuid;
 
 /// The Dapr Account Service.
 ///
-/// This service is used to access the account data.
+/// This service is used to access the account data. It works with
+/// any account data access object, not only the dapr one.
 ///
 /// # Fields
 /// * `account_dao` - The account data access object
 ///
 /// # Methods
 /// * `new` - Creates a new account service
-/// * `to_account_details` - Converts an account entity to an account details
-/// * `get_accounts` - Gets all accounts
+/// * `get_accounts` - Gets a page of accounts
 /// * `get_account_by_id` - Gets an account by id
 /// * `get_account_by_email` - Gets an account by email
 /// * `create_account` - Creates an account
 /// * `update_account` - Updates an account
+/// * `patch_account` - Partially updates an account
+/// * `change_password` - Changes the password of an account
+/// * `reset_password` - Sets a new password on an account
+/// * `verify_email` - Marks the email of an account as verified
+/// * `get_mfa` - Gets the two-factor settings of an account
+/// * `set_mfa` - Replaces the two-factor settings of an account
 /// * `delete_account` - Deletes an account
 /// * `validate_account` - Validates an account
 ///
 /// # Traits
 /// * `AccountService` - The account service trait
 pub struct DaprAccountService {
-    account_dao: DaprAccountDao,
+    account_dao: Box<dyn AccountDao>,
 }
 
 /// The Dapr Account Service implementation.
@@ -37,40 +49,28 @@ impl DaprAccountService {
     ///
     /// # Returns
     /// The new account service
-    pub fn new(account_dao: DaprAccountDao) -> Self {
+    pub fn new(account_dao: Box<dyn AccountDao>) -> Self {
         DaprAccountService { account_dao }
     }
-
-    /// Converts an account entity to an account details.
-    ///
-    /// # Arguments
-    /// * `entity` - The account entity to convert
-    ///
-    /// # Returns
-    /// The account details
-    fn to_account_details(&self, entity: &Option<AccountEntity>) -> Option<AccountDetails> {
-        match entity {
-            Some(found_entity) => Some(AccountDetails::from_entity(&found_entity)),
-            None => None,
-        }
-    }
 }
 
 /// The Account Service implementation.
 #[async_trait]
 impl AccountService for DaprAccountService {
-    /// Gets all accounts.
+    /// Gets a page of accounts.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, sort order and page to get
     ///
     /// # Returns
-    /// The list of accounts
-    async fn get_accounts(&self) -> Vec<AccountDetails> {
-        // Get all accounts and map to account details
-        self.account_dao
-            .get_accounts()
-            .await
-            .iter()
-            .map(|entity| AccountDetails::from_entity(entity))
-            .collect()
+    /// The page of accounts
+    async fn get_accounts(&self, query: AccountQueryModel) -> AccountResult<AccountPageDetails> {
+        // Reject invalid query parameters
+        query.validate()?;
+
+        // Get the page and map to account details
+        let page = self.account_dao.get_accounts(query.to_query()).await?;
+        Ok(AccountPageDetails::from_page(&page))
     }
 
     /// Gets an account by id.
@@ -80,10 +80,10 @@ impl AccountService for DaprAccountService {
     ///
     /// # Returns
     /// The account details
-    async fn get_account_by_id(&self, id: String) -> Option<AccountDetails> {
+    async fn get_account_by_id(&self, id: String) -> AccountResult<AccountDetails> {
         // Get the account and map to account details
-        let entity: Option<AccountEntity> = self.account_dao.get_account_by_id(id).await;
-        self.to_account_details(&entity)
+        let entity: AccountEntity = self.account_dao.get_account_by_id(id).await?;
+        Ok(AccountDetails::from_entity(&entity))
     }
 
     /// Gets an account by email.
@@ -93,10 +93,10 @@ impl AccountService for DaprAccountService {
     ///
     /// # Returns
     /// The account details
-    async fn get_account_by_email(&self, email: String) -> Option<AccountDetails> {
+    async fn get_account_by_email(&self, email: String) -> AccountResult<AccountDetails> {
         // Get the account and map to account details
-        let entity: Option<AccountEntity> = self.account_dao.get_account_by_email(email).await;
-        self.to_account_details(&entity)
+        let entity: AccountEntity = self.account_dao.get_account_by_email(email).await?;
+        Ok(AccountDetails::from_entity(&entity))
     }
 
     /// Validates an account.
@@ -106,52 +106,202 @@ impl AccountService for DaprAccountService {
     ///
     /// # Returns
     /// The account details
-    async fn validate_account(&self, credentials: CredentialsModel) -> Option<AccountDetails> {
+    async fn validate_account(
+        &self,
+        credentials: CredentialsModel,
+    ) -> AccountResult<AccountDetails> {
+        // Reject malformed credentials
+        credentials.validate()?;
+
         // Get the account with the given credentials and map to account details
-        let entity: Option<AccountEntity> = self
+        let entity: AccountEntity = self
             .account_dao
             .validate_account(credentials.email, credentials.password)
-            .await;
-        self.to_account_details(&entity)
+            .await?;
+        Ok(AccountDetails::from_entity(&entity))
     }
 
     /// Creates an account.
     ///
     /// # Arguments
-    /// * `account` - The account to create
+    /// * `account` - The account to create, an empty id is generated
     ///
     /// # Returns
-    /// True if the account was created, false otherwise
-    async fn create_account(&self, account: AccountModel) -> bool {
+    /// The created account details
+    async fn create_account(&self, account: AccountModel) -> AccountResult<AccountDetails> {
+        // Reject invalid account data
+        account.validate()?;
+
+        // Generate a time ordered id unless the client chose one
+        let account = AccountModel {
+            id: if account.id.is_empty() {
+                Uuid::now_v7().to_string()
+            } else {
+                account.id
+            },
+            ..account
+        };
+
         // Create the account
-        self.account_dao
-            .create_account(AccountEntity::from_model(&account))
-            .await
+        let entity = AccountEntity::from_model(&account);
+        self.account_dao.create_account(entity.clone()).await?;
+        Ok(AccountDetails::from_entity(&entity))
     }
 
     /// Updates an account.
     ///
     /// # Arguments
     /// * `account` - The account to update
+    /// * `etag` - The expected version of the account, if any
     ///
     /// # Returns
-    /// True if the account was updated, false otherwise
-    async fn update_account(&self, account: AccountModel) -> bool {
-        // Update the account
+    /// Nothing if the account was updated
+    async fn update_account(
+        &self,
+        account: AccountUpdateModel,
+        etag: Option<String>,
+    ) -> AccountResult<()> {
+        // Reject invalid account data, the id is required to find the account
+        account.validate()?;
+
+        // Update the account at the expected version
         self.account_dao
-            .update_account(AccountEntity::from_model(&account))
+            .update_account(AccountEntity {
+                etag,
+                ..AccountEntity::from_update(&account)
+            })
             .await
     }
 
+    /// Partially updates an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `patch` - The merge patch of the profile fields
+    /// * `etag` - The expected version of the account, if any
+    ///
+    /// # Returns
+    /// The patched account details
+    async fn patch_account(
+        &self,
+        id: String,
+        patch: AccountPatchModel,
+        etag: Option<String>,
+    ) -> AccountResult<AccountDetails> {
+        // Reject patches of anything but the profile fields
+        patch.validate()?;
+
+        // Apply the patch to the current account and validate the result
+        let current = self.account_dao.get_account_by_id(id).await?;
+        let account = patch.apply_to(&AccountUpdateModel::from_entity(&current))?;
+        account.validate()?;
+
+        // Update the version the patch was applied to, unless another is expected
+        self.account_dao
+            .update_account(AccountEntity {
+                etag: etag.or(current.etag),
+                ..AccountEntity::from_update(&account)
+            })
+            .await?;
+
+        // Get the patched account with its new version
+        let entity = self.account_dao.get_account_by_id(account.id).await?;
+        Ok(AccountDetails::from_entity(&entity))
+    }
+
+    /// Changes the password of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `change` - The current and the new password
+    ///
+    /// # Returns
+    /// Nothing if the password was changed
+    async fn change_password(&self, id: String, change: PasswordChangeModel) -> AccountResult<()> {
+        // Reject a malformed current password or a new one breaking the policy
+        change.validate()?;
+
+        // Change the password if the current one matches
+        self.account_dao
+            .change_password(id, change.current_password, change.new_password)
+            .await
+    }
+
+    /// Sets a new password on an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `reset` - The reset token and the new password
+    ///
+    /// # Returns
+    /// Nothing if the password was set
+    async fn reset_password(&self, id: String, reset: PasswordResetModel) -> AccountResult<()> {
+        // Reject a new password breaking the policy
+        reset.validate()?;
+
+        // Set the password without the current one
+        self.account_dao
+            .reset_password(id, reset.new_password)
+            .await
+    }
+
+    /// Marks the email of an account as verified.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `email` - The email the token was sent to
+    ///
+    /// # Returns
+    /// Nothing if the email is verified
+    async fn verify_email(&self, id: String, email: String) -> AccountResult<()> {
+        // Record when the email was verified
+        let verified_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| AccountError::Internal(e.to_string()))?
+            .as_secs();
+        self.account_dao.verify_email(id, email, verified_at).await
+    }
+
+    /// Gets the two-factor settings of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    ///
+    /// # Returns
+    /// The settings with the version of the account
+    async fn get_mfa(&self, id: String) -> AccountResult<(MfaSettings, Option<String>)> {
+        let entity = self.account_dao.get_account_by_id(id).await?;
+        Ok((entity.mfa, entity.etag))
+    }
+
+    /// Replaces the two-factor settings of an account.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the account
+    /// * `mfa` - The two-factor settings to store
+    /// * `etag` - The version the settings were read at
+    ///
+    /// # Returns
+    /// Nothing if the settings were stored
+    async fn set_mfa(
+        &self,
+        id: String,
+        mfa: MfaSettings,
+        etag: Option<String>,
+    ) -> AccountResult<()> {
+        self.account_dao.set_mfa(id, mfa, etag).await
+    }
+
     /// Deletes an account.
     ///
     /// # Arguments
     /// * `id` - The id of the account
+    /// * `etag` - The expected version of the account, if any
     ///
     /// # Returns
-    /// True if the account was deleted, false otherwise
-    async fn delete_account(&self, id: String) -> bool {
-        // Delete the account
-        self.account_dao.delete_account(id).await
+    /// Nothing if the account was deleted
+    async fn delete_account(&self, id: String, etag: Option<String>) -> AccountResult<()> {
+        // Delete the account at the expected version
+        self.account_dao.delete_account(id, etag).await
     }
 }
@@ -0,0 +1,236 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::data::{normalize_email, LoginAttemptsDao};
+use crate::errors::{AccountError, AccountResult};
+use rocket::serde::Deserialize;
+
+/// The login lockout configuration.
+///
+/// Once `account_threshold` logins for an email, or `ip_threshold`
+/// logins from a client, failed in a row, further logins are locked
+/// out for `base_lockout_secs`. Every further failure doubles the
+/// lockout, up to `max_lockout_secs`.
+///
+/// # Fields
+/// * `enabled` - Count failed logins and lock out repeated failures
+/// * `account_threshold` - The failed logins for one email before it is locked out
+/// * `ip_threshold` - The failed logins from one client before it is locked out
+/// * `base_lockout_secs` - The first lockout
+/// * `max_lockout_secs` - The longest lockout
+/// * `window_secs` - How long failures are counted after the last one, at least the longest lockout
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct LockoutConfig {
+    pub enabled: bool,
+    pub account_threshold: u32,
+    pub ip_threshold: u32,
+    pub base_lockout_secs: u64,
+    pub max_lockout_secs: u64,
+    pub window_secs: u64,
+}
+
+/// The default login lockout configuration.
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            enabled: true,
+            account_threshold: 5,
+            ip_threshold: 20,
+            base_lockout_secs: 30,
+            max_lockout_secs: 3600,
+            window_secs: 900,
+        }
+    }
+}
+
+/// The login lockout configuration implementation.
+impl LockoutConfig {
+    /// Gets the lockout after a number of failed logins.
+    ///
+    /// # Arguments
+    /// * `failures` - The failed logins in a row
+    /// * `threshold` - The failed logins before a lockout
+    ///
+    /// # Returns
+    /// The lockout in seconds, `0` below the threshold
+    pub fn lockout_secs(&self, failures: u32, threshold: u32) -> u64 {
+        if threshold == 0 || failures < threshold {
+            return 0;
+        }
+        let doublings = (failures - threshold).min(63);
+        self.base_lockout_secs
+            .saturating_mul(1 << doublings)
+            .min(self.max_lockout_secs)
+    }
+}
+
+/// The login lockouts.
+///
+/// Failed logins are counted per email and per client ip address, so
+/// both guessing the password of one account and trying leaked
+/// credentials of many accounts from one client are slowed down.
+/// Unknown emails are counted too, so lockouts do not reveal which
+/// emails have an account.
+///
+/// # Fields
+/// * `dao` - The login attempts data access object
+/// * `config` - The thresholds and lockouts
+///
+/// # Methods
+/// * `new` - Creates new login lockouts
+/// * `subjects` - Gets the counted subjects of a login
+/// * `check` - Checks a login is not locked out
+/// * `record_failure` - Counts a failed login
+/// * `record_success` - Forgets the failed logins of an email after a login
+/// * `unlock` - Forgets the failed logins of an email
+pub struct Lockouts {
+    dao: Box<dyn LoginAttemptsDao>,
+    config: LockoutConfig,
+}
+
+/// Gets the current time.
+///
+/// # Returns
+/// The seconds since the epoch, or an internal error if the clock is before it
+fn unix_time() -> AccountResult<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| AccountError::Internal(e.to_string()))?
+        .as_secs())
+}
+
+/// The login lockouts implementation.
+impl Lockouts {
+    /// Creates new login lockouts.
+    ///
+    /// # Arguments
+    /// * `dao` - The login attempts data access object
+    /// * `config` - The thresholds and lockouts
+    ///
+    /// # Returns
+    /// The new login lockouts
+    pub fn new(dao: Box<dyn LoginAttemptsDao>, config: LockoutConfig) -> Self {
+        Lockouts { dao, config }
+    }
+
+    /// Gets the counted subjects of a login.
+    ///
+    /// # Arguments
+    /// * `email` - The email of the login
+    /// * `ip` - The client ip address, if known
+    ///
+    /// # Returns
+    /// The subjects with their thresholds
+    fn subjects(&self, email: &str, ip: Option<&str>) -> Vec<(String, u32)> {
+        let mut subjects = vec![(
+            format!("email:{}", normalize_email(email)),
+            self.config.account_threshold,
+        )];
+        if let Some(ip) = ip {
+            subjects.push((format!("ip:{}", ip), self.config.ip_threshold));
+        }
+        subjects
+    }
+
+    /// Checks a login is not locked out.
+    ///
+    /// # Arguments
+    /// * `email` - The email of the login
+    /// * `ip` - The client ip address, if known
+    ///
+    /// # Returns
+    /// Nothing, or `LockedOut` with the seconds until the longest lockout ends
+    pub async fn check(&self, email: &str, ip: Option<&str>) -> AccountResult<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        // Find the lockout ending last
+        let now = unix_time()?;
+        let mut retry_after = 0;
+        for (id, threshold) in self.subjects(email, ip) {
+            let attempts = match self.dao.get_login_attempts(id, now).await {
+                Ok(attempts) => attempts,
+                Err(AccountError::NotFound) => continue,
+                Err(e) => return Err(e),
+            };
+            let locked_until =
+                attempts.last_failure_at + self.config.lockout_secs(attempts.failures, threshold);
+            retry_after = retry_after.max(locked_until.saturating_sub(now));
+        }
+        match retry_after {
+            0 => Ok(()),
+            retry_after => Err(AccountError::LockedOut(retry_after)),
+        }
+    }
+
+    /// Counts a failed login.
+    ///
+    /// The login failed already, so failures to count it are only logged.
+    ///
+    /// # Arguments
+    /// * `email` - The email of the login
+    /// * `ip` - The client ip address, if known
+    pub async fn record_failure(&self, email: &str, ip: Option<&str>) {
+        if !self.config.enabled {
+            return;
+        }
+
+        // Keep counters until their longest lockout ends
+        let window_secs = self.config.window_secs.max(self.config.max_lockout_secs);
+        let now = match unix_time() {
+            Ok(now) => now,
+            Err(e) => return error!("Failed login could not be counted: {}", e),
+        };
+        for (id, _) in self.subjects(email, ip) {
+            if let Err(e) = self.dao.record_login_failure(id, now, window_secs).await {
+                error!("Failed login could not be counted: {}", e);
+            }
+        }
+    }
+
+    /// Forgets the failed logins of an email after a login.
+    ///
+    /// The login succeeded already, so failures to forget are only logged.
+    ///
+    /// # Arguments
+    /// * `email` - The email that logged in
+    pub async fn record_success(&self, email: &str) {
+        if let Err(e) = self.unlock(email).await {
+            error!("Failed logins could not be cleared: {}", e);
+        }
+    }
+
+    /// Forgets the failed logins of an email.
+    ///
+    /// Failures counted for client ip addresses are kept, so a client
+    /// cannot reset its own count with a known password.
+    ///
+    /// # Arguments
+    /// * `email` - The email to unlock
+    ///
+    /// # Returns
+    /// Nothing, also if the email was not locked out
+    pub async fn unlock(&self, email: &str) -> AccountResult<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        self.dao
+            .clear_login_attempts(format!("email:{}", normalize_email(email)))
+            .await
+    }
+
+    /// Forgets the failed logins from a client.
+    ///
+    /// # Arguments
+    /// * `ip` - The client ip address to unlock
+    ///
+    /// # Returns
+    /// Nothing, also if the client was not locked out
+    pub async fn unlock_ip(&self, ip: &str) -> AccountResult<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        self.dao.clear_login_attempts(format!("ip:{}", ip)).await
+    }
+}
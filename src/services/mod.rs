@@ -1,12 +1,26 @@
 // Expose the following modules to the rest of the application
 mod account_models;
+mod account_query_model;
 mod account_service;
 mod credentials_model;
 mod dapr_account_service;
+mod lockouts;
+mod signups;
+mod validation;
 
 // Public exports
 pub use account_models::AccountDetails;
 pub use account_models::AccountModel;
+pub use account_models::AccountPatchModel;
+pub use account_models::AccountUpdateModel;
+pub use account_query_model::{AccountPageDetails, AccountQueryModel};
 pub use account_service::AccountService;
-pub use credentials_model::CredentialsModel;
+pub use credentials_model::{
+    CredentialsModel, EmailVerificationModel, ForgotPasswordModel, MfaCodeModel,
+    MfaEnrollmentModel, MfaLoginModel, PasswordChangeModel, PasswordResetModel,
+    VerificationRequestModel,
+};
 pub use dapr_account_service::DaprAccountService;
+pub use lockouts::{LockoutConfig, Lockouts};
+pub use signups::{SignupConfig, Signups};
+pub use validation::Validate;
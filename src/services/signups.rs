@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use super::account_models::{AccountDetails, AccountModel};
+use super::account_service::AccountService;
+use crate::errors::{AccountError, AccountResult};
+use crate::mail::{send_in_background, MailMessage, Mailer};
+use rocket::serde::Deserialize;
+
+/// The signup configuration.
+///
+/// # Fields
+/// * `enumeration_safe` - Answer signups alike whether or not the email
+///   has an account, and mail the owner of a taken email instead
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct SignupConfig {
+    pub enumeration_safe: bool,
+}
+
+/// The signups.
+///
+/// By default a signup with a taken email is rejected with `Conflict`,
+/// which tells the caller the email has an account. In enumeration
+/// safe mode the caller is not told, the owner of the email gets a
+/// mail about the attempt instead.
+///
+/// # Fields
+/// * `mailer` - The mailer delivering notices to owners of taken emails
+/// * `config` - The signup mode
+///
+/// # Methods
+/// * `new` - Creates new signups
+/// * `is_enumeration_safe` - Checks if signups are answered alike
+/// * `create` - Creates an account
+/// * `notify_owner` - Mails the owner of a taken email about a signup
+pub struct Signups {
+    mailer: Arc<dyn Mailer>,
+    config: SignupConfig,
+}
+
+/// The signups implementation.
+impl Signups {
+    /// Creates new signups.
+    ///
+    /// # Arguments
+    /// * `mailer` - The mailer delivering notices to owners of taken emails
+    /// * `config` - The signup mode
+    ///
+    /// # Returns
+    /// The new signups
+    pub fn new(mailer: Arc<dyn Mailer>, config: SignupConfig) -> Self {
+        Signups { mailer, config }
+    }
+
+    /// Checks if signups are answered alike for new and taken emails.
+    ///
+    /// # Returns
+    /// True in enumeration safe mode
+    pub fn is_enumeration_safe(&self) -> bool {
+        self.config.enumeration_safe
+    }
+
+    /// Creates an account.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `account` - The account to create, an empty id is generated
+    ///
+    /// # Returns
+    /// The created account details, `None` if the email was taken in
+    /// enumeration safe mode, or `Conflict` if the id or email is in use
+    pub async fn create(
+        &self,
+        service: &dyn AccountService,
+        account: AccountModel,
+    ) -> AccountResult<Option<AccountDetails>> {
+        let email = account.email.clone();
+        match service.create_account(account).await {
+            Ok(account) => Ok(Some(account)),
+            // Tell the owner of a taken email instead of the caller
+            Err(AccountError::Conflict(reason)) if self.config.enumeration_safe => {
+                match service.get_account_by_email(email).await {
+                    Ok(owner) => {
+                        self.notify_owner(&owner);
+                        Ok(None)
+                    }
+                    Err(AccountError::NotFound) => Err(AccountError::Conflict(reason)),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Mails the owner of a taken email about a signup.
+    ///
+    /// # Arguments
+    /// * `owner` - The account that has the email
+    fn notify_owner(&self, owner: &AccountDetails) {
+        let message = MailMessage {
+            to: owner.email.clone(),
+            subject: "Sign up attempt with your email".to_string(),
+            body: "Someone tried to create an account with this email, \
+                   but it already belongs to an account.\n\n\
+                   If this was you, log in instead, or reset your password if you forgot it.\n\
+                   Otherwise you can ignore this mail."
+                .to_string(),
+        };
+        send_in_background(self.mailer.clone(), message);
+    }
+}
@@ -0,0 +1,413 @@
+use super::account_models::{AccountModel, AccountPatchModel, AccountUpdateModel};
+use super::account_query_model::AccountQueryModel;
+use super::credentials_model::{
+    CredentialsModel, EmailVerificationModel, ForgotPasswordModel, MfaCodeModel,
+    MfaEnrollmentModel, MfaLoginModel, PasswordChangeModel, PasswordResetModel,
+    VerificationRequestModel,
+};
+use crate::data::{AccountStatus, SortField, MAX_PAGE_LIMIT};
+use crate::errors::{AccountError, AccountResult, FieldError};
+use rocket::serde::json::Value;
+
+/// The maximum length of an account id.
+pub const MAX_ID_LENGTH: usize = 64;
+
+/// The maximum length of an account name, in characters.
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// The maximum length of an email address, see RFC 5321.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// The minimum length of a password, in characters.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The maximum length of a password, in bytes. Bcrypt ignores anything longer.
+pub const MAX_PASSWORD_BYTES: usize = 72;
+
+/// A validatable model.
+///
+/// Models are validated in the service layer before they reach
+/// the data layer, so invalid data is never stored.
+///
+/// # Methods
+/// * `field_errors` - Collects every invalid field of the model
+/// * `validate` - Validates the model
+pub trait Validate {
+    /// Collects every invalid field of the model.
+    ///
+    /// # Arguments
+    /// * `errors` - The list to add field errors to
+    fn field_errors(&self, errors: &mut Vec<FieldError>);
+
+    /// Validates the model.
+    ///
+    /// # Returns
+    /// Nothing, or `Validation` listing every offending field
+    fn validate(&self) -> AccountResult<()> {
+        let mut errors = vec![];
+        self.field_errors(&mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AccountError::Validation(errors))
+        }
+    }
+}
+
+/// Validates an account id.
+///
+/// Ids are used as state store keys and in urls, so only
+/// ascii letters, digits, `-` and `_` are allowed.
+///
+/// # Arguments
+/// * `field` - The field name
+/// * `id` - The id to validate
+/// * `errors` - The list to add field errors to
+pub fn validate_id(field: &str, id: &str, errors: &mut Vec<FieldError>) {
+    if id.is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+    } else if id.len() > MAX_ID_LENGTH {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at most {} characters", MAX_ID_LENGTH),
+        ));
+    } else if !id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        errors.push(FieldError::new(
+            field,
+            "may only contain letters, digits, '-' and '_'",
+        ));
+    }
+}
+
+/// Validates an account name.
+///
+/// # Arguments
+/// * `field` - The field name
+/// * `name` - The name to validate
+/// * `errors` - The list to add field errors to
+pub fn validate_name(field: &str, name: &str, errors: &mut Vec<FieldError>) {
+    let length = name.chars().count();
+    if name.trim().is_empty() {
+        errors.push(FieldError::new(field, "must not be blank"));
+    } else if length > MAX_NAME_LENGTH {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at most {} characters", MAX_NAME_LENGTH),
+        ));
+    } else if name.trim() != name {
+        errors.push(FieldError::new(
+            field,
+            "must not start or end with whitespace",
+        ));
+    } else if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || "'-._".contains(c))
+    {
+        errors.push(FieldError::new(
+            field,
+            "may only contain letters, digits, spaces and ' - . _",
+        ));
+    }
+}
+
+/// Validates an email address.
+///
+/// This accepts the common `local@domain.tld` form: a dot-atom
+/// local part and a domain of at least two dns labels.
+///
+/// # Arguments
+/// * `field` - The field name
+/// * `email` - The email to validate
+/// * `errors` - The list to add field errors to
+pub fn validate_email(field: &str, email: &str, errors: &mut Vec<FieldError>) {
+    if email.is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+    } else if email.len() > MAX_EMAIL_LENGTH {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at most {} characters", MAX_EMAIL_LENGTH),
+        ));
+    } else if !is_valid_email(email) {
+        errors.push(FieldError::new(field, "must be a valid email address"));
+    }
+}
+
+/// Validates a new password.
+///
+/// # Arguments
+/// * `field` - The field name
+/// * `password` - The password to validate
+/// * `errors` - The list to add field errors to
+pub fn validate_password(field: &str, password: &str, errors: &mut Vec<FieldError>) {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at least {} characters", MIN_PASSWORD_LENGTH),
+        ));
+    } else if password.len() > MAX_PASSWORD_BYTES {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at most {} bytes", MAX_PASSWORD_BYTES),
+        ));
+    }
+}
+
+/// Checks the syntax of an email address.
+///
+/// # Arguments
+/// * `email` - The email to check
+///
+/// # Returns
+/// True if the email is syntactically valid
+fn is_valid_email(email: &str) -> bool {
+    // Split into exactly one local part and one domain
+    let (local, domain) = match email.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    if domain.contains('@') {
+        return false;
+    }
+
+    // The local part is a dot-atom
+    let local_valid = !local.is_empty()
+        && local.len() <= 64
+        && local.split('.').all(|atom| {
+            !atom.is_empty()
+                && atom
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+/=?^_`{|}~-".contains(c))
+        });
+
+    // The domain is at least two dns labels
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    local_valid && domain_valid
+}
+
+/// Account model validation.
+///
+/// An empty id is accepted since the server generates one on creation.
+impl Validate for AccountModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if !self.id.is_empty() {
+            validate_id("id", &self.id, errors);
+        }
+        validate_name("name", &self.name, errors);
+        validate_email("email", &self.email, errors);
+        validate_password("password", &self.password, errors);
+    }
+}
+
+/// Account update model validation.
+///
+/// The id is required to find the account to update.
+impl Validate for AccountUpdateModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        validate_id("id", &self.id, errors);
+        validate_name("name", &self.name, errors);
+        validate_email("email", &self.email, errors);
+    }
+}
+
+/// Account patch model validation.
+///
+/// Only the name and email may be patched, and neither of
+/// them removed. The patched account is validated again as a whole.
+impl Validate for AccountPatchModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        let Some(fields) = self.fields() else {
+            errors.push(FieldError::new("patch", "must be a JSON object"));
+            return;
+        };
+        for (field, value) in fields {
+            match (field.as_str(), value) {
+                ("name" | "email", Value::Null) => {
+                    errors.push(FieldError::new(field, "cannot be removed"))
+                }
+                ("name", Value::String(name)) => validate_name(field, name, errors),
+                ("email", Value::String(email)) => validate_email(field, email, errors),
+                ("name" | "email", _) => errors.push(FieldError::new(field, "must be a string")),
+                _ => errors.push(FieldError::new(field, "cannot be patched")),
+            }
+        }
+    }
+}
+
+/// Credentials model validation.
+///
+/// Only the shape of the credentials is checked, password
+/// policy is left to account creation.
+impl Validate for CredentialsModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        validate_email("email", &self.email, errors);
+        if self.password.is_empty() {
+            errors.push(FieldError::new("password", "must not be empty"));
+        } else if self.password.len() > MAX_PASSWORD_BYTES {
+            errors.push(FieldError::new(
+                "password",
+                format!("must be at most {} bytes", MAX_PASSWORD_BYTES),
+            ));
+        }
+    }
+}
+
+/// Password change model validation.
+///
+/// The current password is only checked for shape, the
+/// new one must satisfy the password policy.
+impl Validate for PasswordChangeModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if self.current_password.is_empty() {
+            errors.push(FieldError::new("current_password", "must not be empty"));
+        } else if self.current_password.len() > MAX_PASSWORD_BYTES {
+            errors.push(FieldError::new(
+                "current_password",
+                format!("must be at most {} bytes", MAX_PASSWORD_BYTES),
+            ));
+        }
+        validate_password("new_password", &self.new_password, errors);
+    }
+}
+
+/// Forgotten password model validation.
+impl Validate for ForgotPasswordModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        validate_email("email", &self.email, errors);
+    }
+}
+
+/// Password reset model validation.
+///
+/// The token is only checked for presence, the new
+/// password must satisfy the password policy.
+impl Validate for PasswordResetModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if self.token.is_empty() {
+            errors.push(FieldError::new("token", "must not be empty"));
+        }
+        validate_password("new_password", &self.new_password, errors);
+    }
+}
+
+/// Email verification model validation.
+impl Validate for EmailVerificationModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if self.token.is_empty() {
+            errors.push(FieldError::new("token", "must not be empty"));
+        }
+    }
+}
+
+/// Verification mail request model validation.
+impl Validate for VerificationRequestModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        validate_email("email", &self.email, errors);
+    }
+}
+
+/// Two-factor enrollment model validation.
+impl Validate for MfaEnrollmentModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if self.password.is_empty() {
+            errors.push(FieldError::new("password", "must not be empty"));
+        }
+    }
+}
+
+/// Second factor model validation.
+///
+/// Codes of authenticator apps are six digits, recovery codes are
+/// checked against the stored ones only.
+impl Validate for MfaCodeModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        match (&self.code, &self.recovery_code) {
+            (Some(code), None) => {
+                if code.len() != 6 || !code.bytes().all(|b| b.is_ascii_digit()) {
+                    errors.push(FieldError::new("code", "must be 6 digits"));
+                }
+            }
+            (None, Some(recovery_code)) => {
+                if recovery_code.trim().is_empty() {
+                    errors.push(FieldError::new("recovery_code", "must not be empty"));
+                }
+            }
+            (None, None) => errors.push(FieldError::new(
+                "code",
+                "must be given, or a recovery_code instead",
+            )),
+            (Some(_), Some(_)) => errors.push(FieldError::new(
+                "recovery_code",
+                "must not be given together with a code",
+            )),
+        }
+    }
+}
+
+/// Two-factor login model validation.
+impl Validate for MfaLoginModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if self.mfa_token.is_empty() {
+            errors.push(FieldError::new("mfa_token", "must not be empty"));
+        }
+        self.factor.field_errors(errors);
+    }
+}
+
+/// Account query model validation.
+///
+/// Filters are only checked for length, any value that is too long
+/// cannot match a valid account anyway.
+impl Validate for AccountQueryModel {
+    fn field_errors(&self, errors: &mut Vec<FieldError>) {
+        if let Some(limit) = self.limit {
+            if limit == 0 || limit > MAX_PAGE_LIMIT {
+                errors.push(FieldError::new(
+                    "limit",
+                    format!("must be between 1 and {}", MAX_PAGE_LIMIT),
+                ));
+            }
+        }
+        if let Some(sort) = &self.sort {
+            let field = sort.strip_prefix('-').unwrap_or(sort);
+            if SortField::parse(field).is_none() {
+                errors.push(FieldError::new(
+                    "sort",
+                    "must be one of id, name, email, optionally prefixed with '-'",
+                ));
+            }
+        }
+        if let Some(name) = &self.name {
+            if name.chars().count() > MAX_NAME_LENGTH {
+                errors.push(FieldError::new(
+                    "name",
+                    format!("must be at most {} characters", MAX_NAME_LENGTH),
+                ));
+            }
+        }
+        if let Some(email) = &self.email {
+            if email.len() > MAX_EMAIL_LENGTH {
+                errors.push(FieldError::new(
+                    "email",
+                    format!("must be at most {} characters", MAX_EMAIL_LENGTH),
+                ));
+            }
+        }
+        if let Some(status) = &self.status {
+            if AccountStatus::parse(status).is_none() {
+                errors.push(FieldError::new("status", "must be active or suspended"));
+            }
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+
+use crate::data::{
+    default_roles, AccountDao, AccountEntity, AccountQuery, AccountStatus, MfaSettings, SortField,
+    SortOrder,
+};
+use crate::errors::AccountError;
+use proptest::prelude::*;
+use proptest::test_runner::{Config as ProptestConfig, TestRunner};
+
+/// The account ids used by random operations.
+const IDS: [&str; 3] = ["acc_a", "acc_b", "acc_c"];
+
+/// The emails used by random operations, two differ only in case.
+const EMAILS: [&str; 4] = ["x@test.com", "X@Test.com", "y@test.com", "z@test.com"];
+
+/// Runs a future to completion on a fresh runtime.
+///
+/// # Arguments
+/// * `future` - The future to run
+///
+/// # Returns
+/// The output of the future
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    rocket::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("test runtime")
+        .block_on(future)
+}
+
+/// Creates a new account entity.
+///
+/// # Arguments
+/// * `id` - The id of the account
+/// * `name` - The name of the account
+/// * `email` - The email of the account
+///
+/// # Returns
+/// The active account entity, with the password `password`
+pub fn account(id: &str, name: &str, email: &str) -> AccountEntity {
+    AccountEntity {
+        id: id.to_string(),
+        name: name.to_string(),
+        email: email.to_string(),
+        password: "password".to_string(),
+        status: AccountStatus::Active,
+        roles: default_roles(),
+        email_verified: false,
+        email_verified_at: None,
+        mfa: MfaSettings::default(),
+        instance_id: format!("{}_instance", id),
+        etag: None,
+    }
+}
+
+/// Checks created accounts can be found by id and email.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_create_and_get<D: AccountDao>(dao: &D) {
+    dao.create_account(account("acc_1", "One", "One@Test.com"))
+        .await
+        .unwrap();
+
+    // Found by id, with a version and without the plain password
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(stored.name, "One");
+    assert_eq!(stored.email, "One@Test.com");
+    assert_eq!(stored.status, AccountStatus::Active);
+    assert_ne!(stored.password, "password");
+    assert!(stored.etag.is_some());
+
+    // Found by email regardless of case
+    let stored = dao
+        .get_account_by_email("one@test.com".to_string())
+        .await
+        .unwrap();
+    assert_eq!(stored.id, "acc_1");
+}
+
+/// Checks missing accounts are not found.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_get_missing<D: AccountDao>(dao: &D) {
+    assert!(matches!(
+        dao.get_account_by_id("missing".to_string()).await,
+        Err(AccountError::NotFound)
+    ));
+    assert!(matches!(
+        dao.get_account_by_email("missing@test.com".to_string())
+            .await,
+        Err(AccountError::NotFound)
+    ));
+}
+
+/// Checks ids and emails cannot be taken twice.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_create_conflicts<D: AccountDao>(dao: &D) {
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+
+    // Same id, new email
+    assert!(matches!(
+        dao.create_account(account("acc_1", "Two", "two@test.com"))
+            .await,
+        Err(AccountError::Conflict(_))
+    ));
+
+    // New id, same email in another case
+    assert!(matches!(
+        dao.create_account(account("acc_2", "Two", "ONE@test.com"))
+            .await,
+        Err(AccountError::Conflict(_))
+    ));
+
+    // Nothing of the rejected accounts was stored
+    assert!(matches!(
+        dao.get_account_by_id("acc_2".to_string()).await,
+        Err(AccountError::NotFound)
+    ));
+    assert!(matches!(
+        dao.get_account_by_email("two@test.com".to_string()).await,
+        Err(AccountError::NotFound)
+    ));
+}
+
+/// Checks credentials are validated against the stored password.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_validate_account<D: AccountDao>(dao: &D) {
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+
+    let valid = dao
+        .validate_account("one@test.com".to_string(), "password".to_string())
+        .await
+        .unwrap();
+    assert_eq!(valid.id, "acc_1");
+    assert!(matches!(
+        dao.validate_account("one@test.com".to_string(), "wrong password".to_string())
+            .await,
+        Err(AccountError::InvalidCredentials)
+    ));
+    assert!(matches!(
+        dao.validate_account("two@test.com".to_string(), "password".to_string())
+            .await,
+        Err(AccountError::InvalidCredentials)
+    ));
+}
+
+/// Checks updates respect versions and email ownership.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_update_account<D: AccountDao>(dao: &D) {
+    // Missing accounts cannot be updated
+    assert!(matches!(
+        dao.update_account(account("acc_1", "One", "one@test.com"))
+            .await,
+        Err(AccountError::NotFound)
+    ));
+
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+    dao.create_account(account("acc_2", "Two", "two@test.com"))
+        .await
+        .unwrap();
+    let stale = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+
+    // Moving to a new email releases the old one
+    dao.update_account(AccountEntity {
+        etag: stale.etag.clone(),
+        ..account("acc_1", "Uno", "uno@test.com")
+    })
+    .await
+    .unwrap();
+    let stored = dao
+        .get_account_by_email("uno@test.com".to_string())
+        .await
+        .unwrap();
+    assert_eq!((stored.id.as_str(), stored.name.as_str()), ("acc_1", "Uno"));
+    assert_ne!(stored.etag, stale.etag);
+    assert!(matches!(
+        dao.get_account_by_email("one@test.com".to_string()).await,
+        Err(AccountError::NotFound)
+    ));
+
+    // A stale version is rejected
+    assert!(matches!(
+        dao.update_account(AccountEntity {
+            etag: stale.etag,
+            ..account("acc_1", "One", "one@test.com")
+        })
+        .await,
+        Err(AccountError::PreconditionFailed)
+    ));
+
+    // The email of another account cannot be taken
+    assert!(matches!(
+        dao.update_account(account("acc_1", "Uno", "TWO@test.com"))
+            .await,
+        Err(AccountError::Conflict(_))
+    ));
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(stored.email, "uno@test.com");
+
+    // The password, status and instance id are kept, whatever the update carries
+    dao.update_account(AccountEntity {
+        password: stored.password.clone(),
+        status: AccountStatus::Suspended,
+        instance_id: "other".to_string(),
+        ..account("acc_1", "Uno", "uno@test.com")
+    })
+    .await
+    .unwrap();
+    let updated = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(updated.password, stored.password);
+    assert_eq!(updated.status, AccountStatus::Active);
+    assert_eq!(updated.instance_id, "acc_1_instance");
+    dao.validate_account("uno@test.com".to_string(), "password".to_string())
+        .await
+        .unwrap();
+}
+
+/// Checks passwords are only changed with the current password.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_change_password<D: AccountDao>(dao: &D) {
+    let change = |current: &str, new: &str| {
+        dao.change_password("acc_1".to_string(), current.to_string(), new.to_string())
+    };
+
+    // Missing accounts have no password to change
+    assert!(matches!(
+        change("password", "new password").await,
+        Err(AccountError::NotFound)
+    ));
+
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+    let before = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+
+    // A wrong current password changes nothing
+    assert!(matches!(
+        change("wrong", "new password").await,
+        Err(AccountError::InvalidCredentials)
+    ));
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(stored.etag, before.etag);
+
+    // The current password sets a new one, in a new version
+    change("password", "new password").await.unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_ne!(stored.etag, before.etag);
+    assert!(matches!(
+        dao.validate_account("one@test.com".to_string(), "password".to_string())
+            .await,
+        Err(AccountError::InvalidCredentials)
+    ));
+    dao.validate_account("one@test.com".to_string(), "new password".to_string())
+        .await
+        .unwrap();
+}
+
+/// Checks passwords can be reset without the current one.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_reset_password<D: AccountDao>(dao: &D) {
+    // Missing accounts have no password to reset
+    assert!(matches!(
+        dao.reset_password("acc_1".to_string(), "new password".to_string())
+            .await,
+        Err(AccountError::NotFound)
+    ));
+
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+    let before = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+
+    // The new password replaces the old one, in a new version
+    dao.reset_password("acc_1".to_string(), "new password".to_string())
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_ne!(stored.etag, before.etag);
+    assert_eq!(stored.name, before.name);
+    assert!(matches!(
+        dao.validate_account("one@test.com".to_string(), "password".to_string())
+            .await,
+        Err(AccountError::InvalidCredentials)
+    ));
+    dao.validate_account("one@test.com".to_string(), "new password".to_string())
+        .await
+        .unwrap();
+}
+
+/// Checks emails are verified until they change.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_verify_email<D: AccountDao>(dao: &D) {
+    // Missing accounts have no email to verify
+    assert!(matches!(
+        dao.verify_email("acc_1".to_string(), "one@test.com".to_string(), 100)
+            .await,
+        Err(AccountError::NotFound)
+    ));
+
+    // New accounts are unverified, only their current email can be verified
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert!(!stored.email_verified);
+    assert_eq!(stored.email_verified_at, None);
+    assert!(matches!(
+        dao.verify_email("acc_1".to_string(), "other@test.com".to_string(), 100)
+            .await,
+        Err(AccountError::PreconditionFailed)
+    ));
+
+    // Verifying again keeps the first verification time
+    dao.verify_email("acc_1".to_string(), "ONE@test.com".to_string(), 100)
+        .await
+        .unwrap();
+    dao.verify_email("acc_1".to_string(), "one@test.com".to_string(), 200)
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert!(stored.email_verified);
+    assert_eq!(stored.email_verified_at, Some(100));
+
+    // Profile updates keep the verification, email changes drop it
+    dao.update_account(account("acc_1", "Renamed", "One@Test.com"))
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert!(stored.email_verified);
+    assert_eq!(stored.email_verified_at, Some(100));
+    dao.update_account(account("acc_1", "Renamed", "new@test.com"))
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert!(!stored.email_verified);
+    assert_eq!(stored.email_verified_at, None);
+}
+
+/// Checks two-factor settings respect versions and survive profile updates.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_set_mfa<D: AccountDao>(dao: &D) {
+    let mfa = MfaSettings {
+        totp_secret: Some("secret".to_string()),
+        pending_totp_secret: None,
+        pending_totp_expires_at: 0,
+        last_totp_step: 7,
+        recovery_codes: vec!["code".to_string()],
+    };
+
+    // Missing accounts have no settings
+    assert!(matches!(
+        dao.set_mfa("acc_1".to_string(), mfa.clone(), None).await,
+        Err(AccountError::NotFound)
+    ));
+
+    // New accounts have no second factor
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+    let stale = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(stale.mfa, MfaSettings::default());
+    assert!(!stale.mfa.is_enabled());
+
+    // Settings are stored at a new version
+    dao.set_mfa("acc_1".to_string(), mfa.clone(), stale.etag.clone())
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(stored.mfa, mfa);
+    assert!(stored.mfa.is_enabled());
+    assert_ne!(stored.etag, stale.etag);
+
+    // A stale version is rejected
+    assert!(matches!(
+        dao.set_mfa("acc_1".to_string(), MfaSettings::default(), stale.etag)
+            .await,
+        Err(AccountError::PreconditionFailed)
+    ));
+
+    // Profile updates keep the settings, whatever the update carries
+    dao.update_account(account("acc_1", "Renamed", "new@test.com"))
+        .await
+        .unwrap();
+    let stored = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    assert_eq!(stored.mfa, mfa);
+}
+
+/// Checks deletes respect versions and release the email.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_delete_account<D: AccountDao>(dao: &D) {
+    // Missing accounts cannot be deleted
+    assert!(matches!(
+        dao.delete_account("acc_1".to_string(), None).await,
+        Err(AccountError::NotFound)
+    ));
+
+    dao.create_account(account("acc_1", "One", "one@test.com"))
+        .await
+        .unwrap();
+    let stale = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    dao.update_account(account("acc_1", "Uno", "one@test.com"))
+        .await
+        .unwrap();
+
+    // A stale version is rejected
+    assert!(matches!(
+        dao.delete_account("acc_1".to_string(), stale.etag).await,
+        Err(AccountError::PreconditionFailed)
+    ));
+
+    // The current version is deleted and its email released
+    let current = dao.get_account_by_id("acc_1".to_string()).await.unwrap();
+    dao.delete_account("acc_1".to_string(), current.etag)
+        .await
+        .unwrap();
+    assert!(matches!(
+        dao.get_account_by_id("acc_1".to_string()).await,
+        Err(AccountError::NotFound)
+    ));
+    dao.create_account(account("acc_2", "Two", "one@test.com"))
+        .await
+        .unwrap();
+}
+
+/// Checks listings are paged, sorted and filtered.
+///
+/// Pages may hold fewer accounts than their limit, so every listing
+/// is followed to its last page.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+pub async fn check_get_accounts<D: AccountDao>(dao: &D) {
+    for (id, name) in [("acc_1", "Dave"), ("acc_2", "Alice"), ("acc_3", "Carol")] {
+        dao.create_account(account(id, name, &format!("{}@test.com", name)))
+            .await
+            .unwrap();
+    }
+    dao.create_account(AccountEntity {
+        status: AccountStatus::Suspended,
+        ..account("acc_4", "Bob", "bob@test.com")
+    })
+    .await
+    .unwrap();
+
+    // Sorted by name, descending, two at a time
+    let query = AccountQuery {
+        limit: 2,
+        sort: SortField::Name,
+        order: SortOrder::Desc,
+        ..AccountQuery::default()
+    };
+    assert_eq!(
+        list_ids(dao, query).await,
+        vec!["acc_1", "acc_3", "acc_4", "acc_2"]
+    );
+
+    // Filtered by name, email and status
+    let query = AccountQuery {
+        name: Some("Carol".to_string()),
+        ..AccountQuery::default()
+    };
+    assert_eq!(list_ids(dao, query).await, vec!["acc_3"]);
+    let query = AccountQuery {
+        email: Some("ALICE@test.com".to_string()),
+        ..AccountQuery::default()
+    };
+    assert_eq!(list_ids(dao, query).await, vec!["acc_2"]);
+    let query = AccountQuery {
+        status: Some(AccountStatus::Suspended),
+        ..AccountQuery::default()
+    };
+    assert_eq!(list_ids(dao, query).await, vec!["acc_4"]);
+}
+
+/// Lists the ids of every account matching a query, following its pages.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+/// * `query` - The query of the first page
+///
+/// # Returns
+/// The ids in listing order
+async fn list_ids<D: AccountDao>(dao: &D, mut query: AccountQuery) -> Vec<String> {
+    let mut ids = vec![];
+    loop {
+        let page = dao.get_accounts(query.clone()).await.unwrap();
+        assert!(page.items.len() <= query.limit);
+        ids.extend(page.items.into_iter().map(|account| account.id));
+        match page.next_page_token {
+            Some(token) => query.page_token = Some(token),
+            None => return ids,
+        }
+    }
+}
+
+/// A random account dao operation.
+///
+/// # Variants
+/// * `Create` - Creates an account with an id and email
+/// * `Update` - Updates the email of an account
+/// * `Delete` - Deletes an account
+#[derive(Clone, Debug)]
+enum Operation {
+    Create(usize, usize),
+    Update(usize, usize),
+    Delete(usize),
+}
+
+/// Generates random account dao operations.
+///
+/// # Returns
+/// The operation strategy
+fn operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        (0..IDS.len(), 0..EMAILS.len()).prop_map(|(id, email)| Operation::Create(id, email)),
+        (0..IDS.len(), 0..EMAILS.len()).prop_map(|(id, email)| Operation::Update(id, email)),
+        (0..IDS.len()).prop_map(Operation::Delete),
+    ]
+}
+
+/// Applies operations to a dao and checks it against a reference model.
+///
+/// The model maps account ids to emails. After every operation the
+/// outcome and every lookup must agree with the model.
+///
+/// # Arguments
+/// * `dao` - The dao under test
+/// * `operations` - The operations to apply
+async fn check_against_model<D: AccountDao>(dao: &D, operations: &[Operation]) {
+    let mut model: BTreeMap<&str, &str> = BTreeMap::new();
+    let owner = |model: &BTreeMap<&str, &str>, email: &str| {
+        model
+            .iter()
+            .find(|(_, owned)| owned.eq_ignore_ascii_case(email))
+            .map(|(id, _)| id.to_string())
+    };
+
+    for operation in operations {
+        match *operation {
+            Operation::Create(id, email) => {
+                let (id, email) = (IDS[id], EMAILS[email]);
+                let result = dao.create_account(account(id, "Name", email)).await;
+                if model.contains_key(id) || owner(&model, email).is_some() {
+                    assert!(
+                        matches!(result, Err(AccountError::Conflict(_))),
+                        "{:?}",
+                        result
+                    );
+                } else {
+                    assert!(result.is_ok(), "{:?}", result);
+                    model.insert(id, email);
+                }
+            }
+            Operation::Update(id, email) => {
+                let (id, email) = (IDS[id], EMAILS[email]);
+                let result = dao.update_account(account(id, "Name", email)).await;
+                if !model.contains_key(id) {
+                    assert!(
+                        matches!(result, Err(AccountError::NotFound)),
+                        "{:?}",
+                        result
+                    );
+                } else if owner(&model, email).is_some_and(|owner| owner != id) {
+                    assert!(
+                        matches!(result, Err(AccountError::Conflict(_))),
+                        "{:?}",
+                        result
+                    );
+                } else {
+                    assert!(result.is_ok(), "{:?}", result);
+                    model.insert(id, email);
+                }
+            }
+            Operation::Delete(id) => {
+                let id = IDS[id];
+                let result = dao.delete_account(id.to_string(), None).await;
+                if model.remove(id).is_some() {
+                    assert!(result.is_ok(), "{:?}", result);
+                } else {
+                    assert!(
+                        matches!(result, Err(AccountError::NotFound)),
+                        "{:?}",
+                        result
+                    );
+                }
+            }
+        }
+
+        // Every id resolves to its modelled email
+        for id in IDS {
+            match (dao.get_account_by_id(id.to_string()).await, model.get(id)) {
+                (Ok(stored), Some(email)) => assert_eq!(&stored.email, email),
+                (Err(AccountError::NotFound), None) => {}
+                (result, expected) => panic!("{}: got {:?}, expected {:?}", id, result, expected),
+            }
+        }
+
+        // Every email resolves to its modelled owner
+        for email in EMAILS {
+            let found = match dao.get_account_by_email(email.to_string()).await {
+                Ok(stored) => Some(stored.id),
+                Err(AccountError::NotFound) => None,
+                Err(e) => panic!("{}: {:?}", email, e),
+            };
+            assert_eq!(found, owner(&model, email), "{}", email);
+        }
+    }
+
+    // The listing holds exactly the modelled accounts
+    let listed: BTreeSet<String> = list_ids(dao, AccountQuery::default())
+        .await
+        .into_iter()
+        .collect();
+    let expected: BTreeSet<String> = model.keys().map(|id| id.to_string()).collect();
+    assert_eq!(listed, expected);
+}
+
+/// Checks random operation sequences against a reference model.
+///
+/// Every case runs on a fresh dao. Password hashing makes each
+/// operation slow, so few short sequences are generated.
+///
+/// # Arguments
+/// * `fixture` - Creates a fresh dao, with anything it needs kept alive
+pub fn check_random_operations<G, D: AccountDao>(fixture: impl Fn() -> (G, D)) {
+    let mut runner = TestRunner::new(ProptestConfig {
+        cases: 8,
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    });
+    runner
+        .run(
+            &proptest::collection::vec(operation(), 1..8),
+            |operations| {
+                let (_guard, dao) = fixture();
+                block_on(check_against_model(&dao, &operations));
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
+/// Generates the account dao conformance tests for a dao fixture.
+///
+/// The fixture is a function returning a guard, kept alive for the
+/// test, and a fresh, empty dao.
+///
+/// # Example
+/// ```ignore
+/// mod in_memory {
+///     account_dao_conformance_tests!(|| ((), InMemoryAccountDao::new()));
+/// }
+/// ```
+macro_rules! account_dao_conformance_tests {
+    ($fixture:expr) => {
+        account_dao_conformance_tests!(
+            $fixture;
+            check_create_and_get,
+            check_get_missing,
+            check_create_conflicts,
+            check_validate_account,
+            check_update_account,
+            check_change_password,
+            check_reset_password,
+            check_verify_email,
+            check_set_mfa,
+            check_delete_account,
+            check_get_accounts
+        );
+
+        #[test]
+        fn check_random_operations() {
+            $crate::test_support::dao_conformance::check_random_operations($fixture);
+        }
+    };
+    ($fixture:expr; $($check:ident),*) => {
+        $(
+            #[test]
+            fn $check() {
+                let (_guard, dao) = ($fixture)();
+                $crate::test_support::dao_conformance::block_on(
+                    $crate::test_support::dao_conformance::$check(&dao),
+                );
+            }
+        )*
+    };
+}
+
+pub(crate) use account_dao_conformance_tests;
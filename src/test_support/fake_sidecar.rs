@@ -0,0 +1,754 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::data::{DaprAccountDao, DaprClientConfig};
+use rocket::{
+    config::{LogLevel, Shutdown as ShutdownConfig},
+    fairing::AdHoc,
+    http::{Header, Status},
+    response::{self, Responder},
+    serde::json::serde_json::{self, json, Value},
+    Config, Request, Response, Shutdown, State,
+};
+
+/// The state store name served by the fake sidecar.
+pub const FAKE_STORE_NAME: &str = "account-db";
+
+/// A fault the fake sidecar injects into a response.
+///
+/// # Variants
+/// * `Delay` - Wait before answering, to trigger client timeouts
+/// * `Status` - Answer with this status and a dapr error body
+/// * `MalformedJson` - Answer with a success status and a broken json body
+/// * `LostReply` - Handle the request, then answer with this status as if the reply was lost
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Delay(Duration),
+    Status(u16),
+    MalformedJson,
+    LostReply(u16),
+}
+
+/// A stored state value.
+///
+/// # Fields
+/// * `value` - The stored json value
+/// * `etag` - The version of the value
+#[derive(Clone, Debug)]
+struct StoredValue {
+    value: Value,
+    etag: String,
+}
+
+/// The state of the fake sidecar, shared with its request handlers.
+///
+/// # Fields
+/// * `store_name` - The only state store name that is accepted
+/// * `entries` - The stored values, keyed by state key
+/// * `versions` - The source of etags
+/// * `faults` - Faults injected into the next requests, in order
+/// * `persistent_fault` - A fault injected into every request
+/// * `lost_reply` - The status replacing the reply of the request being handled
+/// * `requests` - The number of requests received
+/// * `bindings` - The output binding requests received, with the binding name
+struct SidecarState {
+    store_name: String,
+    entries: Mutex<HashMap<String, StoredValue>>,
+    versions: AtomicUsize,
+    faults: Mutex<VecDeque<Fault>>,
+    persistent_fault: Mutex<Option<Fault>>,
+    lost_reply: Mutex<Option<u16>>,
+    requests: AtomicUsize,
+    bindings: Mutex<Vec<(String, Value)>>,
+}
+
+/// The sidecar state implementation.
+impl SidecarState {
+    /// Gets a new etag.
+    ///
+    /// # Returns
+    /// The new etag, unique within this sidecar
+    fn next_etag(&self) -> String {
+        (self.versions.fetch_add(1, AtomicOrdering::SeqCst) + 1).to_string()
+    }
+
+    /// Counts a request and injects the next fault, if any.
+    ///
+    /// Delays are served here and lost replies replaced once the request
+    /// is handled, other faults are returned as the reply.
+    ///
+    /// # Returns
+    /// The reply replacing the real one, if the request fails
+    async fn fault(&self) -> Option<Reply> {
+        self.requests.fetch_add(1, AtomicOrdering::SeqCst);
+
+        // Take a one-off fault before the persistent one
+        let fault = self
+            .faults
+            .lock()
+            .unwrap()
+            .pop_front()
+            .or(*self.persistent_fault.lock().unwrap());
+        match fault {
+            Some(Fault::Delay(delay)) => rocket::tokio::time::sleep(delay).await,
+            Some(Fault::Status(code)) => {
+                return Some(Reply::error(
+                    Status::new(code),
+                    "ERR_INJECTED",
+                    "injected fault",
+                ))
+            }
+            Some(Fault::MalformedJson) => {
+                return Some(Reply::Raw(Status::Ok, "{\"results\": [".to_string()))
+            }
+            Some(Fault::LostReply(code)) => *self.lost_reply.lock().unwrap() = Some(code),
+            None => {}
+        }
+        None
+    }
+
+    /// Counts a state request and injects the next fault, if any.
+    ///
+    /// # Arguments
+    /// * `store` - The state store name of the request
+    ///
+    /// # Returns
+    /// The reply replacing the real one, if the request fails
+    async fn intercept(&self, store: &str) -> Option<Reply> {
+        if let Some(reply) = self.fault().await {
+            return Some(reply);
+        }
+
+        // Reject unknown state stores like dapr does
+        if store != self.store_name {
+            return Some(Reply::error(
+                Status::BadRequest,
+                "ERR_STATE_STORE_NOT_FOUND",
+                &format!("state store {} is not found", store),
+            ));
+        }
+        None
+    }
+
+    /// Applies a write to the entries if its etag matches.
+    ///
+    /// With an etag the key must still have that version. Without one,
+    /// first-write upserts only succeed if the key does not exist yet.
+    ///
+    /// # Arguments
+    /// * `entries` - The entries to change
+    /// * `write` - The dapr state request, `value` is `None` for deletes
+    ///
+    /// # Returns
+    /// True if the write was applied
+    fn apply(&self, entries: &mut HashMap<String, StoredValue>, write: &Write) -> bool {
+        let current = entries.get(&write.key).map(|stored| stored.etag.as_str());
+        let allowed = match (&write.etag, current) {
+            (Some(etag), Some(current)) => etag == current,
+            (Some(_), None) => false,
+            (None, Some(_)) => !(write.first_write && write.value.is_some()),
+            (None, None) => true,
+        };
+        if !allowed {
+            return false;
+        }
+
+        match &write.value {
+            Some(value) => {
+                let stored = StoredValue {
+                    value: value.clone(),
+                    etag: self.next_etag(),
+                };
+                entries.insert(write.key.clone(), stored);
+            }
+            None => {
+                entries.remove(&write.key);
+            }
+        }
+        true
+    }
+}
+
+/// A parsed dapr state write.
+///
+/// # Fields
+/// * `key` - The state key
+/// * `value` - The value to store, `None` to delete the key
+/// * `etag` - The expected version of the key
+/// * `first_write` - True if first-write concurrency was requested
+struct Write {
+    key: String,
+    value: Option<Value>,
+    etag: Option<String>,
+    first_write: bool,
+}
+
+/// The write implementation.
+impl Write {
+    /// Parses a dapr state request.
+    ///
+    /// # Arguments
+    /// * `request` - The state request, with `key`, `value`, `etag` and `options`
+    /// * `delete` - True if the request deletes the key
+    ///
+    /// # Returns
+    /// The write, or `None` if the request has no key
+    fn parse(request: &Value, delete: bool) -> Option<Self> {
+        Some(Write {
+            key: request["key"].as_str()?.to_string(),
+            value: if delete {
+                None
+            } else {
+                Some(request["value"].clone())
+            },
+            etag: request["etag"].as_str().map(str::to_string),
+            first_write: request["options"]["concurrency"] == "first-write",
+        })
+    }
+}
+
+/// A reply of the fake sidecar.
+///
+/// # Variants
+/// * `Value` - A stored value with its etag
+/// * `NoContent` - An empty success reply
+/// * `Json` - A json reply with a status
+/// * `Raw` - A raw body with a status
+enum Reply {
+    Value(Value, String),
+    NoContent,
+    Json(Status, Value),
+    Raw(Status, String),
+}
+
+/// The reply implementation.
+impl Reply {
+    /// Creates a dapr error reply.
+    ///
+    /// # Arguments
+    /// * `status` - The status of the reply
+    /// * `code` - The dapr error code
+    /// * `message` - The error message
+    ///
+    /// # Returns
+    /// The error reply
+    fn error(status: Status, code: &str, message: &str) -> Self {
+        Reply::Json(status, json!({ "errorCode": code, "message": message }))
+    }
+}
+
+/// Sends the reply as dapr would.
+impl<'r> Responder<'r, 'static> for Reply {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        let (status, body, etag) = match self {
+            Reply::Value(value, etag) => (Status::Ok, value.to_string(), Some(etag)),
+            Reply::NoContent => return Response::build().status(Status::NoContent).ok(),
+            Reply::Json(status, value) => (status, value.to_string(), None),
+            Reply::Raw(status, body) => (status, body, None),
+        };
+        let mut response = Response::build();
+        response
+            .status(status)
+            .header(Header::new("Content-Type", "application/json"))
+            .sized_body(body.len(), std::io::Cursor::new(body));
+        if let Some(etag) = etag {
+            response.header(Header::new("ETag", etag));
+        }
+        response.ok()
+    }
+}
+
+/// Gets a state value.
+#[get("/v1.0/state/<store>/<key>")]
+async fn get_state(state: &State<Arc<SidecarState>>, store: &str, key: &str) -> Reply {
+    if let Some(reply) = state.intercept(store).await {
+        return reply;
+    }
+    match state.entries.lock().unwrap().get(key) {
+        Some(stored) => Reply::Value(stored.value.clone(), stored.etag.clone()),
+        None => Reply::NoContent,
+    }
+}
+
+/// Saves state values, each guarded by its own etag.
+#[post("/v1.0/state/<store>", data = "<body>")]
+async fn save_state(state: &State<Arc<SidecarState>>, store: &str, body: String) -> Reply {
+    if let Some(reply) = state.intercept(store).await {
+        return reply;
+    }
+    let requests: Vec<Value> = match serde_json::from_str(&body) {
+        Ok(requests) => requests,
+        Err(e) => return Reply::error(Status::BadRequest, "ERR_MALFORMED_REQUEST", &e.to_string()),
+    };
+    let mut entries = state.entries.lock().unwrap();
+    for request in requests {
+        let Some(write) = Write::parse(&request, false) else {
+            return Reply::error(Status::BadRequest, "ERR_MALFORMED_REQUEST", "missing key");
+        };
+        if !state.apply(&mut entries, &write) {
+            return Reply::error(Status::Conflict, "ERR_STATE_SAVE", "possible etag mismatch");
+        }
+    }
+    Reply::NoContent
+}
+
+/// Deletes a state value, guarded by the `If-Match` etag.
+#[delete("/v1.0/state/<store>/<key>")]
+async fn delete_state(
+    state: &State<Arc<SidecarState>>,
+    store: &str,
+    key: &str,
+    if_match: IfMatchHeader,
+) -> Reply {
+    if let Some(reply) = state.intercept(store).await {
+        return reply;
+    }
+    let write = Write {
+        key: key.to_string(),
+        value: None,
+        etag: if_match.0,
+        first_write: true,
+    };
+    if state.apply(&mut state.entries.lock().unwrap(), &write) {
+        Reply::NoContent
+    } else {
+        Reply::error(
+            Status::Conflict,
+            "ERR_STATE_DELETE",
+            "possible etag mismatch",
+        )
+    }
+}
+
+/// Applies upserts and deletes atomically.
+#[post("/v1.0/state/<store>/transaction", data = "<body>")]
+async fn transaction(state: &State<Arc<SidecarState>>, store: &str, body: String) -> Reply {
+    if let Some(reply) = state.intercept(store).await {
+        return reply;
+    }
+    let body: Value = match serde_json::from_str(&body) {
+        Ok(body) => body,
+        Err(e) => return Reply::error(Status::BadRequest, "ERR_MALFORMED_REQUEST", &e.to_string()),
+    };
+
+    // Apply every operation to a copy, keep it only if all succeed
+    let mut entries = state.entries.lock().unwrap();
+    let mut staged = entries.clone();
+    for operation in body["operations"].as_array().cloned().unwrap_or_default() {
+        let delete = match operation["operation"].as_str() {
+            Some("upsert") => false,
+            Some("delete") => true,
+            _ => {
+                return Reply::error(
+                    Status::BadRequest,
+                    "ERR_NOT_SUPPORTED_STATE_OPERATION",
+                    "unknown operation",
+                )
+            }
+        };
+        let Some(write) = Write::parse(&operation["request"], delete) else {
+            return Reply::error(Status::BadRequest, "ERR_MALFORMED_REQUEST", "missing key");
+        };
+        if !state.apply(&mut staged, &write) {
+            return Reply::error(
+                Status::InternalServerError,
+                "ERR_STATE_TRANSACTION",
+                "possible etag mismatch",
+            );
+        }
+    }
+    *entries = staged;
+    Reply::NoContent
+}
+
+/// Queries state values with the alpha query api.
+///
+/// Supports `EQ`, `IN`, `AND` and `OR` filters on top level fields,
+/// sorting and offset based page tokens.
+#[post("/v1.0-alpha1/state/<store>/query", data = "<body>")]
+async fn query(state: &State<Arc<SidecarState>>, store: &str, body: String) -> Reply {
+    if let Some(reply) = state.intercept(store).await {
+        return reply;
+    }
+    let body: Value = match serde_json::from_str(&body) {
+        Ok(body) => body,
+        Err(e) => return Reply::error(Status::BadRequest, "ERR_MALFORMED_REQUEST", &e.to_string()),
+    };
+
+    // Filter the values
+    let mut results: Vec<(String, StoredValue)> = state
+        .entries
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, stored)| matches_filter(&body["filter"], &stored.value))
+        .map(|(key, stored)| (key.clone(), stored.clone()))
+        .collect();
+
+    // Sort by every sort key in turn, ties ordered by key
+    let sort = body["sort"].as_array().cloned().unwrap_or_default();
+    results.sort_by(|(a_key, a), (b_key, b)| {
+        sort.iter()
+            .map(|sort| {
+                let field = sort["key"].as_str().unwrap_or_default();
+                let ordering = compare_values(&a.value[field], &b.value[field]);
+                if sort["order"] == "DESC" {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a_key.cmp(b_key))
+    });
+
+    // Cut out the page
+    let offset = body["page"]["token"]
+        .as_str()
+        .and_then(|token| token.parse::<usize>().ok())
+        .unwrap_or(0);
+    let limit = body["page"]["limit"]
+        .as_u64()
+        .map(|limit| limit as usize)
+        .unwrap_or(results.len());
+    let end = offset.saturating_add(limit);
+    let token = (end < results.len()).then(|| end.to_string());
+    let results: Vec<Value> = results
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(key, stored)| json!({ "key": key, "data": stored.value, "etag": stored.etag }))
+        .collect();
+
+    let mut reply = json!({ "results": results });
+    if let Some(token) = token {
+        reply["token"] = json!(token);
+    }
+    Reply::Json(Status::Ok, reply)
+}
+
+/// Checks if a value matches a dapr query filter.
+///
+/// # Arguments
+/// * `filter` - The filter, `{}` matches everything
+/// * `value` - The stored value
+///
+/// # Returns
+/// True if the value matches
+fn matches_filter(filter: &Value, value: &Value) -> bool {
+    let Some((operator, operand)) = filter.as_object().and_then(|filter| filter.iter().next())
+    else {
+        return true;
+    };
+    let field = |operand: &Value| {
+        operand
+            .as_object()
+            .and_then(|operand| operand.iter().next())
+            .map(|(field, expected)| (value[field.as_str()].clone(), expected.clone()))
+    };
+    match operator.as_str() {
+        "EQ" => field(operand).is_some_and(|(actual, expected)| actual == expected),
+        "IN" => field(operand).is_some_and(|(actual, expected)| {
+            expected
+                .as_array()
+                .is_some_and(|expected| expected.contains(&actual))
+        }),
+        "AND" => operand
+            .as_array()
+            .is_some_and(|filters| filters.iter().all(|filter| matches_filter(filter, value))),
+        "OR" => operand
+            .as_array()
+            .is_some_and(|filters| filters.iter().any(|filter| matches_filter(filter, value))),
+        _ => false,
+    }
+}
+
+/// Compares two stored field values for sorting.
+///
+/// Missing fields sort after present ones, like nulls in postgres.
+///
+/// # Arguments
+/// * `a` - The first value
+/// * `b` - The second value
+///
+/// # Returns
+/// The ordering of the values
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Greater,
+        (_, Value::Null) => Ordering::Less,
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (a, b) => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+/// Records an output binding request.
+#[post("/v1.0/bindings/<name>", data = "<body>")]
+async fn invoke_binding(state: &State<Arc<SidecarState>>, name: &str, body: String) -> Reply {
+    if let Some(reply) = state.fault().await {
+        return reply;
+    }
+    let Ok(request) = serde_json::from_str::<Value>(&body) else {
+        return Reply::error(
+            Status::BadRequest,
+            "ERR_MALFORMED_REQUEST",
+            "malformed request",
+        );
+    };
+    state
+        .bindings
+        .lock()
+        .unwrap()
+        .push((name.to_string(), request));
+    Reply::NoContent
+}
+
+/// The `If-Match` header of a delete request.
+///
+/// # Fields
+/// * `0` - The expected etag, if any
+struct IfMatchHeader(Option<String>);
+
+/// The if match request guard.
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for IfMatchHeader {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, ()> {
+        let etag = request.headers().get_one("If-Match").map(str::to_string);
+        rocket::request::Outcome::Success(IfMatchHeader(etag))
+    }
+}
+
+/// An in-process stand-in for the dapr sidecar.
+///
+/// Serves the dapr state and output binding endpoints used by the api on
+/// an ephemeral local port, so the dao can be tested without dapr
+/// and every test can use its own sidecar. The server stops when
+/// the sidecar is dropped.
+///
+/// # Fields
+/// * `url` - The base url of the sidecar
+/// * `state` - The state shared with the request handlers
+/// * `shutdown` - The shutdown handle of the server
+///
+/// # Methods
+/// * `start` - Starts a new fake sidecar
+/// * `url` - Gets the base url of the sidecar
+/// * `dao` - Creates a dapr account dao using the sidecar
+/// * `dao_with_config` - Creates a dapr account dao using the sidecar and a client configuration
+/// * `inject` - Injects a fault into the next request
+/// * `inject_always` - Injects a fault into every request
+/// * `clear_faults` - Removes all injected faults
+/// * `request_count` - Gets the number of requests received
+/// * `keys` - Gets the stored state keys
+/// * `binding_requests` - Gets the output binding requests received
+pub struct FakeSidecar {
+    url: String,
+    state: Arc<SidecarState>,
+    shutdown: Shutdown,
+}
+
+/// The fake sidecar implementation.
+impl FakeSidecar {
+    /// Starts a new fake sidecar.
+    ///
+    /// # Returns
+    /// The running fake sidecar
+    pub fn start() -> Self {
+        let state = Arc::new(SidecarState {
+            store_name: FAKE_STORE_NAME.to_string(),
+            entries: Mutex::new(HashMap::new()),
+            versions: AtomicUsize::new(0),
+            faults: Mutex::new(VecDeque::new()),
+            persistent_fault: Mutex::new(None),
+            lost_reply: Mutex::new(None),
+            requests: AtomicUsize::new(0),
+            bindings: Mutex::new(vec![]),
+        });
+
+        // Bind an ephemeral port, quietly and without signal handlers
+        let shutdown = ShutdownConfig {
+            ctrlc: false,
+            #[cfg(unix)]
+            signals: std::collections::HashSet::new(),
+            ..Default::default()
+        };
+        let config = Config {
+            address: Ipv4Addr::LOCALHOST.into(),
+            port: 0,
+            workers: 2,
+            log_level: LogLevel::Off,
+            shutdown,
+            ..Config::debug_default()
+        };
+
+        // Report the bound port once the server is up
+        let (sender, receiver) = mpsc::channel();
+        let rocket = rocket::custom(config)
+            .manage(state.clone())
+            .mount(
+                "/",
+                routes![
+                    get_state,
+                    save_state,
+                    delete_state,
+                    transaction,
+                    query,
+                    invoke_binding
+                ],
+            )
+            .attach(AdHoc::on_response("Lost Replies", |request, response| {
+                Box::pin(async move {
+                    // Replace the reply of a handled request with the injected error
+                    let state = request.rocket().state::<Arc<SidecarState>>();
+                    let lost = state.and_then(|state| state.lost_reply.lock().unwrap().take());
+                    if let Some(code) = lost {
+                        let body = json!({ "errorCode": "ERR_INJECTED", "message": "lost reply" })
+                            .to_string();
+                        response.set_status(Status::new(code));
+                        response.set_header(Header::new("Content-Type", "application/json"));
+                        response.remove_header("ETag");
+                        response.set_sized_body(body.len(), std::io::Cursor::new(body));
+                    }
+                })
+            }))
+            .attach(AdHoc::on_liftoff("Fake Sidecar", move |rocket| {
+                Box::pin(async move {
+                    let _ = sender.send((rocket.config().port, rocket.shutdown()));
+                })
+            }));
+
+        // Serve on a dedicated runtime
+        thread::spawn(move || {
+            let _ = rocket::execute(rocket.launch());
+        });
+        let (port, shutdown) = receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("fake sidecar started");
+
+        FakeSidecar {
+            url: format!("http://127.0.0.1:{}", port),
+            state,
+            shutdown,
+        }
+    }
+
+    /// Gets the base url of the sidecar.
+    ///
+    /// # Returns
+    /// The base url, e.g. `http://127.0.0.1:41234`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Creates a dapr account dao using the sidecar.
+    ///
+    /// # Returns
+    /// The new dapr account dao
+    pub fn dao(&self) -> DaprAccountDao {
+        self.dao_with_config(DaprClientConfig::default())
+    }
+
+    /// Creates a dapr account dao using the sidecar and a client configuration.
+    ///
+    /// # Arguments
+    /// * `config` - The dapr client configuration
+    ///
+    /// # Returns
+    /// The new dapr account dao
+    pub fn dao_with_config(&self, config: DaprClientConfig) -> DaprAccountDao {
+        DaprAccountDao::with_sidecar(self.url(), FAKE_STORE_NAME, config).expect("dapr account dao")
+    }
+
+    /// Injects a fault into the next request.
+    ///
+    /// Faults injected this way are used up in order.
+    ///
+    /// # Arguments
+    /// * `fault` - The fault to inject
+    pub fn inject(&self, fault: Fault) {
+        self.state.faults.lock().unwrap().push_back(fault);
+    }
+
+    /// Injects a fault into every request until faults are cleared.
+    ///
+    /// # Arguments
+    /// * `fault` - The fault to inject
+    pub fn inject_always(&self, fault: Fault) {
+        *self.state.persistent_fault.lock().unwrap() = Some(fault);
+    }
+
+    /// Removes all injected faults.
+    pub fn clear_faults(&self) {
+        self.state.faults.lock().unwrap().clear();
+        *self.state.persistent_fault.lock().unwrap() = None;
+    }
+
+    /// Gets the number of requests received.
+    ///
+    /// # Returns
+    /// The number of requests, including failed ones
+    pub fn request_count(&self) -> usize {
+        self.state.requests.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Gets the stored state keys.
+    ///
+    /// # Returns
+    /// The keys, sorted
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.state.entries.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Gets a stored value.
+    ///
+    /// # Arguments
+    /// * `key` - The state key
+    ///
+    /// # Returns
+    /// The value, or `None` if the key does not exist
+    pub fn value(&self, key: &str) -> Option<Value> {
+        let entries = self.state.entries.lock().unwrap();
+        entries.get(key).map(|stored| stored.value.clone())
+    }
+
+    /// Edits a stored value, as an operator would in the store.
+    ///
+    /// # Arguments
+    /// * `key` - The state key, which must exist
+    /// * `edit` - The change to the value
+    pub fn edit(&self, key: &str, edit: impl FnOnce(&mut Value)) {
+        let mut entries = self.state.entries.lock().unwrap();
+        let stored = entries.get_mut(key).expect("stored key");
+        edit(&mut stored.value);
+        stored.etag = self.state.next_etag();
+    }
+
+    /// Gets the output binding requests received.
+    ///
+    /// # Returns
+    /// The binding names and request bodies, in the order they were received
+    pub fn binding_requests(&self) -> Vec<(String, Value)> {
+        self.state.bindings.lock().unwrap().clone()
+    }
+}
+
+/// Stops the server when the sidecar is dropped.
+impl Drop for FakeSidecar {
+    fn drop(&mut self) {
+        self.shutdown.clone().notify();
+    }
+}
@@ -0,0 +1,9 @@
+// Exports the test support modules
+pub mod dao_conformance;
+mod fake_sidecar;
+mod recording_mailer;
+
+// Public exports
+pub(crate) use dao_conformance::account_dao_conformance_tests;
+pub use fake_sidecar::{FakeSidecar, Fault};
+pub use recording_mailer::RecordingMailer;
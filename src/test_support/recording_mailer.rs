@@ -0,0 +1,81 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::AccountResult;
+use crate::mail::{MailMessage, Mailer};
+use rocket::async_trait;
+
+/// A mailer that keeps every mail for inspection.
+///
+/// # Fields
+/// * `messages` - The mails sent, in order
+///
+/// # Methods
+/// * `new` - Creates a new recording mailer
+/// * `messages` - Gets the mails sent so far
+/// * `wait_for` - Waits until a number of mails was sent
+///
+/// # Traits
+/// * `Mailer` - The mailer trait
+#[derive(Default)]
+pub struct RecordingMailer {
+    messages: Mutex<Vec<MailMessage>>,
+}
+
+/// The recording mailer implementation.
+impl RecordingMailer {
+    /// Creates a new recording mailer.
+    ///
+    /// # Returns
+    /// The new recording mailer, without mails
+    pub fn new() -> Self {
+        RecordingMailer::default()
+    }
+
+    /// Gets the mails sent so far.
+    ///
+    /// # Returns
+    /// The mails, in the order they were sent
+    pub fn messages(&self) -> Vec<MailMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Waits until a number of mails was sent.
+    ///
+    /// Mails are sent in the background, so they may arrive after
+    /// the response that triggered them.
+    ///
+    /// # Arguments
+    /// * `count` - The number of mails to wait for
+    ///
+    /// # Returns
+    /// The mails, panicking if fewer arrive within five seconds
+    pub fn wait_for(&self, count: usize) -> Vec<MailMessage> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let messages = self.messages();
+            if messages.len() >= count {
+                return messages;
+            }
+            assert!(Instant::now() < deadline, "expected {} mails", count);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// The recording mailer implementation.
+#[async_trait]
+impl Mailer for RecordingMailer {
+    /// Keeps a mail.
+    ///
+    /// # Arguments
+    /// * `message` - The mail to keep
+    ///
+    /// # Returns
+    /// Nothing
+    async fn send(&self, message: MailMessage) -> AccountResult<()> {
+        self.messages.lock().unwrap().push(message);
+        Ok(())
+    }
+}
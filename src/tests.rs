@@ -1,14 +1,71 @@
-use super::rocket;
-use crate::services::{AccountDetails, AccountModel, CredentialsModel};
-use rocket::http::ContentType;
-use rocket::serde::json::json;
-use rocket::{http::Status, local::blocking::Client};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{rocket, server, ServiceProvider};
+use crate::data::{
+    default_roles, AccountDao, AccountEntity, AccountStatus, ActionPurpose, ActionTokenDao,
+    ActionTokenEntity, DaprClientConfig, InMemoryAccountDao, InMemoryActionTokenDao,
+    InMemoryLoginAttemptsDao, InMemoryRefreshTokenDao, LoginAttemptsDao, MfaSettings,
+    PasswordHasher, PasswordHashingConfig, RefreshFamilyEntity, RefreshTokenDao,
+    RefreshTokenEntity, ADMIN_ROLE,
+};
+use crate::errors::{AccountError, AccountResult};
+use crate::mail::{DaprBindingMailer, FileMailer, MailMessage, Mailer};
+use crate::services::{
+    AccountDetails, AccountModel, AccountPageDetails, AccountPatchModel, AccountQueryModel,
+    AccountService, AccountUpdateModel, CredentialsModel, DaprAccountService, LockoutConfig,
+    Lockouts, PasswordChangeModel, PasswordResetModel, SignupConfig, Signups,
+};
+use crate::test_support::{
+    dao_conformance::{account, block_on},
+    FakeSidecar, Fault, RecordingMailer,
+};
+use crate::tokens::{
+    totp::{base32_encode, code_at, provisioning_uri, time_step, verify_code},
+    EmailVerificationConfig, EmailVerifications, PasswordResetConfig, PasswordResets,
+    RefreshTokens, TokenConfig,
+};
+use rocket::async_trait;
+use rocket::http::{ContentType, Header};
+use rocket::serde::json::{json, Value};
+use rocket::{
+    http::Status,
+    local::blocking::{Client, LocalRequest},
+    Config,
+};
+
+/// Create a client backed by the in-memory account store.
+///
+/// # Returns
+/// A client for a fresh rocket instance with no accounts
+fn client() -> Client {
+    let figment = Config::figment().merge(("account_store", "memory"));
+    Client::tracked(rocket().configure(figment)).expect("valid rocket instance")
+}
+
+/// Create a client backed by the dapr account store of a fake sidecar.
+///
+/// # Arguments
+/// * `sidecar` - The fake sidecar holding the accounts and refresh tokens
+///
+/// # Returns
+/// A client for a fresh rocket instance using the sidecar
+fn dapr_client(sidecar: &FakeSidecar) -> Client {
+    let dao = sidecar.dao();
+    let refresh_tokens = RefreshTokens::new(
+        Box::new(dao.refresh_token_dao()),
+        TokenConfig::default().refresh_token_ttl_secs,
+    );
+    let service = DaprAccountService::new(Box::new(dao));
+    let rocket = server().manage(ServiceProvider::new(service).with_refresh_tokens(refresh_tokens));
+    Client::tracked(rocket).expect("valid rocket instance")
+}
 
 /// Test the get accounts endpoint.
 #[test]
 fn test_get_all() {
     // Create client
-    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let client = client();
 
     // Make request
     let response = client.get("/api/v1/accounts").dispatch();
@@ -17,7 +74,7 @@ fn test_get_all() {
     assert_eq!(response.status(), Status::Ok);
 
     // Make sure parsing does not throw an error
-    response.into_json::<Vec<AccountDetails>>().unwrap();
+    response.into_json::<AccountPageDetails>().unwrap();
 }
 
 /// Test the create account endpoint
@@ -27,14 +84,15 @@ fn test_get_all() {
 #[test]
 fn test_create_and_delete() {
     // Create client
-    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let client = client();
 
     // Get the size of the accounts before creation
     let before_size = client
         .get("/api/v1/accounts")
         .dispatch()
-        .into_json::<Vec<AccountDetails>>()
+        .into_json::<AccountPageDetails>()
         .unwrap()
+        .items
         .len();
 
     // Create account
@@ -59,13 +117,17 @@ fn test_create_and_delete() {
     let after_size = client
         .get("/api/v1/accounts")
         .dispatch()
-        .into_json::<Vec<AccountDetails>>()
+        .into_json::<AccountPageDetails>()
         .unwrap()
+        .items
         .len();
     assert_eq!(after_size, before_size + 1);
 
     // Delete account
-    let response = client.delete("/api/v1/accounts/id/test_1").dispatch();
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
 
     // Assert response is no content
     assert_eq!(response.status(), Status::NoContent);
@@ -74,8 +136,9 @@ fn test_create_and_delete() {
     let after_size = client
         .get("/api/v1/accounts")
         .dispatch()
-        .into_json::<Vec<AccountDetails>>()
+        .into_json::<AccountPageDetails>()
         .unwrap()
+        .items
         .len();
     assert_eq!(after_size, before_size);
 }
@@ -87,7 +150,7 @@ fn test_create_and_delete() {
 #[test]
 fn test_get_by_id() {
     // Create client
-    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let client = client();
 
     // Create account
     let account = AccountModel {
@@ -117,7 +180,10 @@ fn test_get_by_id() {
     response.into_json::<AccountDetails>().unwrap();
 
     // Delete account
-    let response = client.delete("/api/v1/accounts/id/test_1").dispatch();
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
 
     // Assert response is no content
     assert_eq!(response.status(), Status::NoContent);
@@ -130,7 +196,7 @@ fn test_get_by_id() {
 #[test]
 fn test_get_by_email() {
     // Create client
-    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let client = client();
 
     // Create account
     let account = AccountModel {
@@ -151,7 +217,9 @@ fn test_get_by_email() {
     assert_eq!(response.status(), Status::Created);
 
     // Get account by email
-    let response = client.get("/api/v1/accounts/email/test1@gmail.com").dispatch();
+    let response = client
+        .get("/api/v1/accounts/email/test1@gmail.com")
+        .dispatch();
 
     // Assert response is ok
     assert_eq!(response.status(), Status::Ok);
@@ -160,7 +228,10 @@ fn test_get_by_email() {
     response.into_json::<AccountDetails>().unwrap();
 
     // Delete account
-    let response = client.delete("/api/v1/accounts/id/test_1").dispatch();
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
 
     // Assert response is no content
     assert_eq!(response.status(), Status::NoContent);
@@ -173,7 +244,7 @@ fn test_get_by_email() {
 #[test]
 fn test_update() {
     // Create client
-    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let client = client();
 
     // Create account
     let mut account = AccountModel {
@@ -196,9 +267,47 @@ fn test_update() {
     // Update the account model
     account.email = "updated@gmail.com".to_string();
 
+    // Assert only the account itself can update, patch or delete it
+    fn authorize<'c>(request: LocalRequest<'c>, token: Option<&str>) -> LocalRequest<'c> {
+        match token {
+            Some(token) => request.header(bearer(token)),
+            None => request,
+        }
+    }
+    for token in [None, Some("forged")] {
+        let response = authorize(client.put("/api/v1/accounts"), token)
+            .json(&account)
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = authorize(client.patch("/api/v1/accounts/id/test_1"), token)
+            .header(ContentType::new("application", "merge-patch+json"))
+            .body(json!({ "email": "updated@gmail.com" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = authorize(client.delete("/api/v1/accounts/id/test_1"), token).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_2"))
+        .json(&account)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_2"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(
+        response.into_json::<Value>().unwrap()["email"],
+        "test1@gmail.com"
+    );
+
     // Put the updated account
     let response = client
         .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
         .header(ContentType::JSON)
         .body(json!(&account).to_string())
         .dispatch();
@@ -219,61 +328,3785 @@ fn test_update() {
     assert_eq!(account_details.email, "updated@gmail.com".to_string());
 
     // Delete account
-    let response = client.delete("/api/v1/accounts/id/test_1").dispatch();
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
 
     // Assert response is no content
     assert_eq!(response.status(), Status::NoContent);
 }
 
-/// Test the validate account endpoint.
-///
-/// # Note
-/// This will test creation, validation, and deletion.
+/// Test that updates keep the password and the change password endpoint.
 #[test]
-fn test_validate_account() {
+fn test_change_password() {
     // Create client
-    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let client = client();
 
-    // Create account
+    // Post the new account
     let account = AccountModel {
         id: "test_1".to_string(),
         name: "Test 1".to_string(),
         email: "test1@gmail.com".to_string(),
         password: "password".to_string(),
     };
+    let response = client.post("/api/v1/accounts").json(&account).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let login = |password: &str| {
+        client
+            .post("/api/v1/accounts/validate")
+            .json(&json!({ "email": "test1@gmail.com", "password": password }))
+            .dispatch()
+            .status()
+    };
 
-    // Post the new account
+    // Assert an update without a password, or echoing a hash, keeps the password
     let response = client
-        .post("/api/v1/accounts")
-        .header(ContentType::JSON)
-        .body(json!(&account).to_string())
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .json(&json!({ "id": "test_1", "name": "Test One", "email": "test1@gmail.com" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test One",
+            "email": "test1@gmail.com",
+            "password": "$2b$10$not.the.stored.hash",
+        }))
         .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(login("password"), Status::Ok);
 
-    // Assert response is ok
+    // Assert a wrong current password is rejected
+    let change = |current: &str, new: &str| {
+        client
+            .post("/api/v1/accounts/id/test_1/password")
+            .json(&json!({ "current_password": current, "new_password": new }))
+            .dispatch()
+            .status()
+    };
+    assert_eq!(change("wrong", "new password"), Status::Unauthorized);
+
+    // Assert a new password breaking the policy is rejected
+    assert_eq!(change("password", "short"), Status::UnprocessableEntity);
+
+    // Assert the password is changed with the current one
+    assert_eq!(change("password", "new password"), Status::NoContent);
+    assert_eq!(login("password"), Status::Unauthorized);
+    assert_eq!(login("new password"), Status::Ok);
+
+    // Assert the password of a missing account cannot be changed
+    let response = client
+        .post("/api/v1/accounts/id/test_2/password")
+        .json(&json!({ "current_password": "password", "new_password": "new password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Assert wrong current passwords count as failed logins
+    for _ in 0..5 {
+        assert_eq!(change("wrong", "other password"), Status::Unauthorized);
+    }
+    assert_eq!(change("wrong", "other password"), Status::TooManyRequests);
+    assert_eq!(
+        change("new password", "other password"),
+        Status::TooManyRequests
+    );
+    assert_eq!(login("new password"), Status::TooManyRequests);
+}
+
+/// Check the patch account endpoint.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+fn check_patch_account(client: &Client) {
+    // Post two accounts
+    for i in 1..=2 {
+        let account = AccountModel {
+            id: format!("test_{}", i),
+            name: format!("Test {}", i),
+            email: format!("test{}@gmail.com", i),
+            password: "password".to_string(),
+        };
+        let response = client.post("/api/v1/accounts").json(&account).dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+    let merge_patch = ContentType::new("application", "merge-patch+json");
+    let patch = |id: &str, patch: Value| {
+        client
+            .patch(format!("/api/v1/accounts/id/{}", id))
+            .header(owner(client, id))
+            .header(merge_patch.clone())
+            .body(patch.to_string())
+            .dispatch()
+    };
+
+    // Assert only the given fields change, with a new version
+    let before = client.get("/api/v1/accounts/id/test_1").dispatch();
+    let before_etag = before.headers().get_one("ETag").unwrap().to_string();
+    let response = patch("test_1", json!({ "name": "Renamed" }));
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    assert_ne!(etag, before_etag);
+    let account = response.into_json::<AccountDetails>().unwrap();
+    assert_eq!(account.name, "Renamed");
+    assert_eq!(account.email, "test1@gmail.com");
+    assert_eq!(account.status, AccountStatus::Active);
+
+    // Assert the password is kept
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Assert the email of another account cannot be taken
+    let response = patch("test_1", json!({ "email": "TEST2@gmail.com" }));
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Assert a new email moves the email index
+    let response = patch("test_1", json!({ "email": "moved@gmail.com" }));
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .get("/api/v1/accounts/email/moved@gmail.com")
+        .dispatch();
+    let account = response.into_json::<AccountDetails>().unwrap();
+    assert_eq!(account.id, "test_1");
+    let response = client
+        .get("/api/v1/accounts/email/test1@gmail.com")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Assert non-profile fields, removals and invalid values are rejected
+    let response = patch(
+        "test_1",
+        json!({
+            "id": "other",
+            "password": "new password",
+            "status": "suspended",
+            "name": null,
+            "email": "invalid"
+        }),
+    );
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let problem = response.into_json::<Value>().unwrap();
+    let fields: Vec<&str> = problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["id", "password", "status", "name", "email"]);
+    let response = patch("test_1", json!(["name"]));
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    // Assert a stale version is rejected
+    let response = client
+        .patch("/api/v1/accounts/id/test_1")
+        .header(owner(client, "test_1"))
+        .header(merge_patch.clone())
+        .header(Header::new("If-Match", before_etag))
+        .body(json!({ "name": "Stale" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    // Assert missing accounts and other media types are not found
+    let response = patch("test_3", json!({ "name": "Missing" }));
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client
+        .patch("/api/v1/accounts/id/test_1")
+        .header(owner(client, "test_1"))
+        .json(&json!({ "name": "Plain" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(
+        response.into_json::<AccountDetails>().unwrap().name,
+        "Renamed"
+    );
+}
+
+/// Test the patch account endpoint in memory.
+#[test]
+fn test_patch_account() {
+    check_patch_account(&client());
+}
+
+/// Test the patch account endpoint with the dapr store.
+#[test]
+fn test_patch_account_dapr() {
+    let sidecar = FakeSidecar::start();
+    check_patch_account(&dapr_client(&sidecar));
+}
+
+/// Create a client issuing tokens with the given configuration.
+///
+/// # Arguments
+/// * `tokens` - The `tokens` configuration
+///
+/// # Returns
+/// A client for a fresh rocket instance with no accounts, if it ignites
+fn token_client(tokens: Value) -> Option<Client> {
+    let figment = Config::figment()
+        .merge(("account_store", "memory"))
+        .merge(("tokens", tokens));
+    match Client::tracked(rocket().configure(figment)) {
+        Ok(client) => Some(client),
+        // Inspect the error, rocket panics on unhandled launch errors
+        Err(e) => {
+            let _ = e.kind();
+            None
+        }
+    }
+}
+
+/// Log in to a new account and verify the access token against the published keys.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+/// * `algorithm` - The expected signing algorithm
+/// * `issuer` - The expected issuer
+/// * `audience` - The expected audience
+///
+/// # Returns
+/// The verified claims and the published key set
+fn login_and_verify(
+    client: &Client,
+    algorithm: jsonwebtoken::Algorithm,
+    issuer: &str,
+    audience: &str,
+) -> (Value, Value) {
+    // Create the account and log in
+    let account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client.post("/api/v1/accounts").json(&account).dispatch();
     assert_eq!(response.status(), Status::Created);
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let login = response.into_json::<Value>().unwrap();
+    assert_eq!(login["id"], "test_1");
+    assert_eq!(login["token_type"], "Bearer");
+    let token = login["access_token"].as_str().unwrap();
 
-    // Create credentails model
-    let credentials = CredentialsModel {
+    // Find the signing key by the key id of the token
+    let response = client.get("/.well-known/jwks.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let jwks = response.into_json::<Value>().unwrap();
+    let header = jsonwebtoken::decode_header(token).unwrap();
+    assert_eq!(header.alg, algorithm);
+    let jwk = jwks["keys"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|jwk| jwk["kid"].as_str() == header.kid.as_deref())
+        .unwrap();
+    let jwk: jsonwebtoken::jwk::Jwk = rocket::serde::json::from_value(jwk.clone()).unwrap();
+    let key = jsonwebtoken::DecodingKey::from_jwk(&jwk).unwrap();
+
+    // Verify the token like another service would
+    let mut validation = jsonwebtoken::Validation::new(algorithm);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)
+        .unwrap()
+        .claims;
+    let lifetime = claims["exp"].as_u64().unwrap() - claims["iat"].as_u64().unwrap();
+    assert_eq!(Some(lifetime), login["expires_in"].as_u64());
+
+    // Assert a tampered token is rejected
+    let (signed, signature) = token.rsplit_once('.').unwrap();
+    let forged = format!("{}.{}", signed, signature.chars().rev().collect::<String>());
+    assert!(jsonwebtoken::decode::<Value>(&forged, &key, &validation).is_err());
+
+    // Assert the issuer verifies its own tokens the same way
+    let tokens = &client.rocket().state::<ServiceProvider>().unwrap().tokens;
+    assert_eq!(tokens.verify(token).unwrap().sub, "test_1");
+    assert_eq!(tokens.verify(&forged), Err(AccountError::InvalidToken));
+
+    (claims, jwks)
+}
+
+/// Test that logins issue access tokens signed with an ephemeral key by default.
+#[test]
+fn test_validate_issues_access_token() {
+    // Create client
+    let client = client();
+
+    // Assert the token is verifiable and names the account and its roles
+    let (claims, jwks) = login_and_verify(
+        &client,
+        jsonwebtoken::Algorithm::EdDSA,
+        "account-api",
+        "auction-games",
+    );
+    assert_eq!(claims["sub"], "test_1");
+    assert_eq!(claims["roles"], json!(["user"]));
+    assert_eq!(claims["email_verified"], false);
+    assert_eq!(jwks["keys"][0]["kty"], "OKP");
+    assert_eq!(jwks["keys"][0]["use"], "sig");
+    assert!(jwks["keys"][0].get("d").is_none());
+
+    // Assert failed logins get no token
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "wrong" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.into_json::<Value>().unwrap()["access_token"].is_null());
+}
+
+/// Test access tokens signed with configured keys.
+#[test]
+fn test_access_token_configured_keys() {
+    let key_file = |name: &str| {
+        format!(
+            "{}/src/test_support/keys/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    };
+
+    // Assert configured claims and an RS256 key
+    let client = token_client(json!({
+        "issuer": "https://accounts.test",
+        "audience": "bidding",
+        "access_token_ttl_secs": 60,
+        "algorithm": "RS256",
+        "private_key_file": key_file("rsa.pem"),
+    }))
+    .expect("valid rocket instance");
+    let (claims, jwks) = login_and_verify(
+        &client,
+        jsonwebtoken::Algorithm::RS256,
+        "https://accounts.test",
+        "bidding",
+    );
+    assert_eq!(
+        claims["exp"].as_u64().unwrap() - claims["iat"].as_u64().unwrap(),
+        60
+    );
+    assert_eq!(jwks["keys"][0]["kty"], "RSA");
+
+    // Assert the same key in PKCS#1 has the same key id
+    let client = token_client(json!({
+        "algorithm": "RS256",
+        "private_key_file": key_file("rsa_pkcs1.pem"),
+    }))
+    .expect("valid rocket instance");
+    let response = client.get("/.well-known/jwks.json").dispatch();
+    let pkcs1 = response.into_json::<Value>().unwrap();
+    assert_eq!(pkcs1["keys"][0]["kid"], jwks["keys"][0]["kid"]);
+
+    // Assert an inline Ed25519 key is used by every instance
+    let pem = std::fs::read_to_string(key_file("ed25519.pem")).unwrap();
+    let kids: Vec<Value> = (0..2)
+        .map(|_| {
+            let client =
+                token_client(json!({ "private_key": pem })).expect("valid rocket instance");
+            let response = client.get("/.well-known/jwks.json").dispatch();
+            response.into_json::<Value>().unwrap()["keys"][0]["kid"].clone()
+        })
+        .collect();
+    assert_eq!(kids[0], kids[1]);
+    let client = token_client(json!({ "private_key": pem })).expect("valid rocket instance");
+    login_and_verify(
+        &client,
+        jsonwebtoken::Algorithm::EdDSA,
+        "account-api",
+        "auction-games",
+    );
+
+    // Assert missing or mismatched keys stop the server from starting
+    assert!(token_client(json!({ "algorithm": "RS256" })).is_none());
+    assert!(token_client(json!({ "private_key": pem, "algorithm": "RS256" })).is_none());
+    assert!(token_client(json!({ "private_key_file": key_file("missing.pem") })).is_none());
+}
+
+/// Log in and get the refresh token.
+///
+/// # Arguments
+/// * `client` - A client with the account `test1@gmail.com`
+///
+/// # Returns
+/// The refresh token of the login
+fn login_refresh_token(client: &Client) -> String {
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let login = response.into_json::<Value>().unwrap();
+    login["refresh_token"].as_str().unwrap().to_string()
+}
+
+/// Exchange a refresh token.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `token` - The refresh token to exchange
+///
+/// # Returns
+/// The status and body of the response
+fn refresh(client: &Client, token: &str) -> (Status, Value) {
+    let response = client
+        .post("/api/v1/accounts/token/refresh")
+        .json(&json!({ "refresh_token": token }))
+        .dispatch();
+    (response.status(), response.into_json::<Value>().unwrap())
+}
+
+/// Check refresh token rotation, reuse detection and logout.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+fn check_refresh_tokens(client: &Client) {
+    // Create the account and log in
+    let account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
         email: "test1@gmail.com".to_string(),
         password: "password".to_string(),
     };
+    let response = client.post("/api/v1/accounts").json(&account).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let first = login_refresh_token(client);
 
-    // Validate by email and password
+    // Assert refreshing rotates the refresh token and issues an access token
+    let (status, body) = refresh(client, &first);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["id"], "test_1");
+    assert_eq!(body["token_type"], "Bearer");
+    assert!(body["access_token"].is_string());
+    assert!(body.get("password").is_none());
+    let second = body["refresh_token"].as_str().unwrap().to_string();
+    assert_ne!(second, first);
+    let (status, body) = refresh(client, &second);
+    assert_eq!(status, Status::Ok);
+    let third = body["refresh_token"].as_str().unwrap().to_string();
+
+    // Assert reusing a consumed token revokes the whole family
+    let (status, body) = refresh(client, &first);
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(body["type"], "urn:account-api:problem:invalid-token");
+    let (status, _) = refresh(client, &third);
+    assert_eq!(status, Status::Unauthorized);
+
+    // Assert other logins are not affected
+    let other = login_refresh_token(client);
+    let (status, body) = refresh(client, &other);
+    assert_eq!(status, Status::Ok);
+    let other = body["refresh_token"].as_str().unwrap().to_string();
+
+    // Assert logging out revokes the token, and can be repeated
+    for _ in 0..2 {
+        let response = client
+            .post("/api/v1/accounts/logout")
+            .json(&json!({ "refresh_token": other }))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+    }
+    let (status, _) = refresh(client, &other);
+    assert_eq!(status, Status::Unauthorized);
+
+    // Assert unknown tokens are rejected
+    let (status, _) = refresh(client, "unknown");
+    assert_eq!(status, Status::Unauthorized);
+
+    // Assert deleted accounts cannot refresh, not even once their id is taken again
+    let last = login_refresh_token(client);
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(client, "test_1"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    let (status, _) = refresh(client, &last);
+    assert_eq!(status, Status::Unauthorized);
+    let response = client.post("/api/v1/accounts").json(&account).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let (status, _) = refresh(client, &last);
+    assert_eq!(status, Status::Unauthorized);
+}
+
+/// Test refresh tokens in memory.
+#[test]
+fn test_refresh_tokens() {
+    check_refresh_tokens(&client());
+}
+
+/// Test refresh tokens with the dapr store.
+#[test]
+fn test_refresh_tokens_dapr() {
+    let sidecar = FakeSidecar::start();
+    let client = dapr_client(&sidecar);
+    check_refresh_tokens(&client);
+
+    // Assert only hashes of the tokens are stored, and never listed as accounts
+    let token = login_refresh_token(&client);
+    let keys = sidecar.keys();
+    assert!(keys.iter().any(|key| key.starts_with("refresh:")));
+    assert!(keys.iter().any(|key| key.starts_with("refresh_family:")));
+    assert!(keys.iter().all(|key| !key.contains(&token)));
+    let response = client.get("/api/v1/accounts").dispatch();
+    let page = response.into_json::<AccountPageDetails>().unwrap();
+    assert_eq!(page.items.len(), 1);
+
+    // Assert tokens stay with the account they were issued to, not with its id
+    sidecar.edit("test_1", |account| {
+        account["instance_id"] = json!("recreated")
+    });
+    let (status, _) = refresh(&client, &token);
+    assert_eq!(status, Status::Unauthorized);
+}
+
+/// Test that expired refresh tokens are rejected.
+#[test]
+fn test_refresh_token_expiry() {
+    let client =
+        token_client(json!({ "refresh_token_ttl_secs": 0 })).expect("valid rocket instance");
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password",
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let token = login_refresh_token(&client);
+    let (status, _) = refresh(&client, &token);
+    assert_eq!(status, Status::Unauthorized);
+}
+
+/// Log in from a client.
+///
+/// # Arguments
+/// * `client` - A client with the account `test1@gmail.com`
+/// * `user_agent` - The user agent of the login
+/// * `ip` - The client ip address of the login
+///
+/// # Returns
+/// The login response
+fn login_from(client: &Client, user_agent: &str, ip: &str) -> Value {
     let response = client
         .post("/api/v1/accounts/validate")
-        .header(ContentType::JSON)
-        .body(json!(&credentials).to_string())
+        .header(Header::new("User-Agent", user_agent.to_string()))
+        .remote(format!("{}:50000", ip).parse().unwrap())
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
         .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json::<Value>().unwrap()
+}
 
-    // Assert response is ok
+/// Get the ids of the sessions of an account.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `id` - The id of the account
+/// * `login` - A login response of the account
+///
+/// # Returns
+/// The session ids, most recently used first
+fn session_ids(client: &Client, id: &str, login: &Value) -> Vec<Value> {
+    let response = client
+        .get(format!("/api/v1/accounts/id/{}/sessions", id))
+        .header(bearer(login["access_token"].as_str().unwrap()))
+        .dispatch();
     assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Value>().unwrap();
+    sessions["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|session| session["id"].clone())
+        .collect()
+}
 
-    // Attempt to parse the response
-    response.into_json::<AccountDetails>().unwrap();
+/// Check listing and revoking sessions.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+fn check_sessions(client: &Client) {
+    // Create the account and log in from two clients
+    for (id, email) in [("test_1", "test1@gmail.com"), ("test_2", "test2@gmail.com")] {
+        let response = client
+            .post("/api/v1/accounts")
+            .json(&json!({ "id": id, "name": id, "email": email, "password": "password" }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+    let laptop = login_from(client, "Firefox", "10.0.0.1");
+    let phone = login_from(client, "Safari", "10.0.0.2");
 
-    // Delete account
-    let response = client.delete("/api/v1/accounts/id/test_1").dispatch();
+    // Assert the sessions record their clients, and tokens name their session
+    let response = client
+        .get("/api/v1/accounts/id/test_1/sessions")
+        .header(bearer(laptop["access_token"].as_str().unwrap()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Value>().unwrap()["items"].clone();
+    assert_eq!(sessions.as_array().unwrap().len(), 2);
+    let session = sessions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|session| session["id"] == laptop["session_id"])
+        .unwrap();
+    assert_eq!(session["user_agent"], "Firefox");
+    assert_eq!(session["ip"], "10.0.0.1");
+    assert!(session["created_at"].as_u64().unwrap() > 0);
+    assert!(session["expires_at"].as_u64() > session["last_used_at"].as_u64());
+    let claims = laptop["access_token"].as_str().unwrap().split('.').nth(1);
+    let claims: Value = rocket::serde::json::from_slice(
+        &base64::engine::Engine::decode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            claims.unwrap(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims["sid"], laptop["session_id"]);
 
-    // Assert response is no content
+    // Assert refreshing keeps the session
+    let (status, body) = refresh(client, laptop["refresh_token"].as_str().unwrap());
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["session_id"], laptop["session_id"]);
+    let laptop_token = body["refresh_token"].as_str().unwrap().to_string();
+
+    // Assert anonymous callers and other accounts cannot see or revoke the sessions
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test2@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let other = response.into_json::<Value>().unwrap();
+    let other_token = other["access_token"].as_str().unwrap();
+    let phone_session = format!(
+        "/api/v1/accounts/id/test_1/sessions/{}",
+        phone["session_id"].as_str().unwrap()
+    );
+    for (uri, delete) in [
+        ("/api/v1/accounts/id/test_1/sessions".to_string(), false),
+        ("/api/v1/accounts/id/test_1/sessions".to_string(), true),
+        (phone_session, true),
+    ] {
+        let request = |token: Option<&str>| {
+            let request = if delete {
+                client.delete(uri.clone())
+            } else {
+                client.get(uri.clone())
+            };
+            match token {
+                Some(token) => request.header(bearer(token)),
+                None => request,
+            }
+        };
+        assert_eq!(request(None).dispatch().status(), Status::Unauthorized);
+        assert_eq!(
+            request(Some("forged")).dispatch().status(),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            request(Some(other_token)).dispatch().status(),
+            Status::Forbidden
+        );
+    }
+    assert_eq!(
+        session_ids(client, "test_2", &other),
+        vec![other["session_id"].clone()]
+    );
+    let response = client
+        .delete(format!(
+            "/api/v1/accounts/id/test_2/sessions/{}",
+            phone["session_id"].as_str().unwrap()
+        ))
+        .header(bearer(other_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(session_ids(client, "test_1", &laptop).len(), 2);
+
+    // Assert revoking every other session keeps the one of the caller
+    let tablet = login_from(client, "Chrome", "10.0.0.3");
+    let response = client
+        .delete("/api/v1/accounts/id/test_1/sessions")
+        .header(bearer(laptop["access_token"].as_str().unwrap()))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        session_ids(client, "test_1", &laptop),
+        vec![laptop["session_id"].clone()]
+    );
+    for revoked in [&phone, &tablet] {
+        let (status, _) = refresh(client, revoked["refresh_token"].as_str().unwrap());
+        assert_eq!(status, Status::Unauthorized);
+    }
+
+    // Assert revoking one session invalidates its refresh token
+    let response = client
+        .delete(format!(
+            "/api/v1/accounts/id/test_1/sessions/{}",
+            laptop["session_id"].as_str().unwrap()
+        ))
+        .header(bearer(laptop["access_token"].as_str().unwrap()))
+        .dispatch();
     assert_eq!(response.status(), Status::NoContent);
+    assert!(session_ids(client, "test_1", &laptop).is_empty());
+    let (status, _) = refresh(client, &laptop_token);
+    assert_eq!(status, Status::Unauthorized);
+    let response = client
+        .delete("/api/v1/accounts/id/test_1/sessions/unknown")
+        .header(bearer(laptop["access_token"].as_str().unwrap()))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test sessions in memory.
+#[test]
+fn test_sessions() {
+    check_sessions(&client());
+}
+
+/// Test sessions with the dapr store.
+#[test]
+fn test_sessions_dapr() {
+    let sidecar = FakeSidecar::start();
+    check_sessions(&dapr_client(&sidecar));
+}
+
+/// Check that a refresh token dao only rotates the versions that were read.
+///
+/// # Arguments
+/// * `dao` - An empty refresh token dao
+fn check_refresh_token_dao(dao: impl RefreshTokenDao) {
+    let token = |id: &str| RefreshTokenEntity {
+        id: id.to_string(),
+        family_id: "family_1".to_string(),
+        account_id: "test_1".to_string(),
+        expires_at: u64::MAX / 2,
+        consumed: false,
+        etag: None,
+    };
+    let family = RefreshFamilyEntity {
+        id: "family_1".to_string(),
+        account_id: "test_1".to_string(),
+        account_instance_id: "instance_1".to_string(),
+        created_at: 1,
+        last_used_at: 1,
+        expires_at: u64::MAX / 2,
+        user_agent: None,
+        ip: None,
+        revoked: false,
+        etag: None,
+    };
+    block_on(async {
+        // Assert a family can only be created once
+        dao.create_refresh_family(family.clone(), token("token_1"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            dao.create_refresh_family(family.clone(), token("token_2"))
+                .await,
+            Err(AccountError::Conflict(_))
+        ));
+
+        // Assert only one of two rotations of the same token wins
+        let read = dao.get_refresh_token("token_1".to_string()).await.unwrap();
+        let read_family = dao
+            .get_refresh_family("family_1".to_string())
+            .await
+            .unwrap();
+        dao.rotate_refresh_token(read.clone(), read_family.clone(), token("token_2"))
+            .await
+            .unwrap();
+        assert_eq!(
+            dao.rotate_refresh_token(read, read_family, token("token_3"))
+                .await,
+            Err(AccountError::PreconditionFailed)
+        );
+        assert!(
+            dao.get_refresh_token("token_1".to_string())
+                .await
+                .unwrap()
+                .consumed
+        );
+        assert_eq!(
+            dao.get_refresh_token("token_3".to_string()).await,
+            Err(AccountError::NotFound)
+        );
+
+        // Assert a revoked family cannot be rotated
+        let read = dao.get_refresh_token("token_2".to_string()).await.unwrap();
+        let read_family = dao
+            .get_refresh_family("family_1".to_string())
+            .await
+            .unwrap();
+        dao.revoke_refresh_family("family_1".to_string())
+            .await
+            .unwrap();
+        dao.revoke_refresh_family("family_1".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            dao.rotate_refresh_token(read, read_family, token("token_3"))
+                .await,
+            Err(AccountError::PreconditionFailed)
+        );
+        assert!(
+            dao.get_refresh_family("family_1".to_string())
+                .await
+                .unwrap()
+                .revoked
+        );
+        assert_eq!(
+            dao.revoke_refresh_family("family_2".to_string()).await,
+            Err(AccountError::NotFound)
+        );
+    });
+}
+
+/// Test the in-memory refresh token dao.
+#[test]
+fn test_refresh_token_dao_in_memory() {
+    check_refresh_token_dao(InMemoryRefreshTokenDao::new());
+}
+
+/// Test the dapr refresh token dao.
+#[test]
+fn test_refresh_token_dao_dapr() {
+    let sidecar = FakeSidecar::start();
+    check_refresh_token_dao(sidecar.dao().refresh_token_dao());
+
+    // Assert new families drop the revoked and expired ones from the session index
+    let dao = sidecar.dao().refresh_token_dao();
+    let family = |id: &str, expires_at: u64| {
+        (
+            RefreshFamilyEntity {
+                id: id.to_string(),
+                account_id: "test_1".to_string(),
+                account_instance_id: "instance_1".to_string(),
+                created_at: 1,
+                last_used_at: 1,
+                expires_at,
+                user_agent: None,
+                ip: None,
+                revoked: false,
+                etag: None,
+            },
+            RefreshTokenEntity {
+                id: format!("{}_token", id),
+                family_id: id.to_string(),
+                account_id: "test_1".to_string(),
+                expires_at,
+                consumed: false,
+                etag: None,
+            },
+        )
+    };
+    let index = || sidecar.value("sessions:test_1").unwrap()["family_ids"].clone();
+    assert_eq!(index(), json!(["family_1"]));
+    let (expired, token) = family("family_2", 1);
+    block_on(dao.create_refresh_family(expired, token)).unwrap();
+    assert_eq!(index(), json!(["family_2"]));
+    let (live, token) = family("family_3", u64::MAX / 2);
+    block_on(dao.create_refresh_family(live, token)).unwrap();
+    assert_eq!(index(), json!(["family_3"]));
+}
+
+/// Create a client that records the mails of the configured flows.
+///
+/// Flows without a configuration keep logging their mails.
+///
+/// # Arguments
+/// * `sidecar` - The fake sidecar to keep accounts and tokens in, or `None` for memory
+/// * `password_reset` - The password reset configuration, if reset mails are recorded
+/// * `email_verification` - The email verification configuration, if its mails are recorded
+///
+/// # Returns
+/// A client for a fresh rocket instance and the mailer it sends with
+fn mail_client(
+    sidecar: Option<&FakeSidecar>,
+    password_reset: Option<PasswordResetConfig>,
+    email_verification: Option<EmailVerificationConfig>,
+) -> (Client, Arc<RecordingMailer>) {
+    let mailer = Arc::new(RecordingMailer::new());
+    let (account_dao, refresh_token_dao, action_token_dao): (
+        Box<dyn AccountDao>,
+        Box<dyn RefreshTokenDao>,
+        Arc<dyn ActionTokenDao>,
+    ) = match sidecar {
+        Some(sidecar) => {
+            let dao = sidecar.dao();
+            let refresh_token_dao = dao.refresh_token_dao();
+            let action_token_dao = dao.action_token_dao();
+            (
+                Box::new(dao),
+                Box::new(refresh_token_dao),
+                Arc::new(action_token_dao),
+            )
+        }
+        None => (
+            Box::new(InMemoryAccountDao::new()),
+            Box::new(InMemoryRefreshTokenDao::new()),
+            Arc::new(InMemoryActionTokenDao::new()),
+        ),
+    };
+    let mut provider = ServiceProvider::new(DaprAccountService::new(account_dao))
+        .with_refresh_tokens(RefreshTokens::new(
+            refresh_token_dao,
+            TokenConfig::default().refresh_token_ttl_secs,
+        ));
+    if let Some(config) = password_reset {
+        provider = provider.with_password_resets(PasswordResets::new(
+            action_token_dao.clone(),
+            mailer.clone(),
+            config,
+        ));
+    }
+    if let Some(config) = email_verification {
+        provider = provider.with_email_verifications(EmailVerifications::new(
+            action_token_dao,
+            mailer.clone(),
+            config,
+        ));
+    }
+    let client = Client::tracked(server().manage(provider)).expect("valid rocket instance");
+    (client, mailer)
+}
+
+/// Ask for a password reset.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `email` - The email to send the reset link to
+///
+/// # Returns
+/// The status of the response
+fn forgot_password(client: &Client, email: &str) -> Status {
+    client
+        .post("/api/v1/accounts/password/forgot")
+        .json(&json!({ "email": email }))
+        .dispatch()
+        .status()
+}
+
+/// Reset a password.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `token` - The password reset token
+/// * `new_password` - The password to set
+///
+/// # Returns
+/// The status of the response
+fn reset_password(client: &Client, token: &str, new_password: &str) -> Status {
+    client
+        .post("/api/v1/accounts/password/reset")
+        .json(&json!({ "token": token, "new_password": new_password }))
+        .dispatch()
+        .status()
+}
+
+/// Get the token from the link of a mail.
+///
+/// # Arguments
+/// * `message` - The password reset or verification mail
+///
+/// # Returns
+/// The token of the link
+fn link_token(message: &MailMessage) -> String {
+    let (_, token) = message.body.split_once("token=").expect("reset link");
+    token.split_whitespace().next().unwrap().to_string()
+}
+
+/// Check the password reset flow.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+/// * `mailer` - The mailer of the client
+fn check_password_reset(client: &Client, mailer: &RecordingMailer) {
+    // Create the account and log in
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let refresh_token = login_refresh_token(client);
+
+    // Assert unknown and known emails are answered alike, malformed ones are rejected
+    assert_eq!(
+        forgot_password(client, "unknown@gmail.com"),
+        Status::Accepted
+    );
+    assert_eq!(
+        forgot_password(client, "not an email"),
+        Status::UnprocessableEntity
+    );
+    assert_eq!(forgot_password(client, "Test1@Gmail.com"), Status::Accepted);
+
+    // Assert only the account owner gets a mail, with a link to the reset page
+    let messages = mailer.wait_for(1);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].to, "test1@gmail.com");
+    assert!(messages[0]
+        .body
+        .contains("http://localhost:3000/reset-password?token="));
+    let token = link_token(&messages[0]);
+
+    // Assert a password breaking the policy keeps the token, unknown tokens are rejected
+    assert_eq!(
+        reset_password(client, &token, "short"),
+        Status::UnprocessableEntity
+    );
+    assert_eq!(
+        reset_password(client, "unknown", "new password"),
+        Status::Unauthorized
+    );
+
+    // Assert the token resets the password once
+    assert_eq!(
+        reset_password(client, &token, "new password"),
+        Status::NoContent
+    );
+    assert_eq!(
+        reset_password(client, &token, "other password"),
+        Status::Unauthorized
+    );
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "new password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Assert the sessions from before the reset are revoked
+    let (status, _) = refresh(client, &refresh_token);
+    assert_eq!(status, Status::Unauthorized);
+
+    // Assert tokens of deleted accounts are rejected
+    assert_eq!(forgot_password(client, "test1@gmail.com"), Status::Accepted);
+    let token = link_token(&mailer.wait_for(2)[1]);
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(client, "test_1"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        reset_password(client, &token, "new password"),
+        Status::Unauthorized
+    );
+    assert_eq!(mailer.messages().len(), 2);
+}
+
+/// Test the status is kept out of clients' hands and suspended accounts are refused.
+#[test]
+fn test_suspended_account() {
+    let sidecar = FakeSidecar::start();
+    let (client, mailer) = mail_client(Some(&sidecar), Some(PasswordResetConfig::default()), None);
+
+    // Assert signups cannot choose their status
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password",
+            "status": "suspended"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let account = response.into_json::<AccountDetails>().unwrap();
+    assert_eq!(account.status, AccountStatus::Active);
+    let refresh_token = login_refresh_token(&client);
+    assert_eq!(
+        forgot_password(&client, "test1@gmail.com"),
+        Status::Accepted
+    );
+    let token = link_token(&mailer.wait_for(1)[0]);
+
+    // Assert a profile update keeps the status set in the store
+    sidecar.edit("test_1", |account| account["status"] = json!("suspended"));
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .json(&json!({ "id": "test_1", "name": "Test One", "email": "test1@gmail.com" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    let account = response.into_json::<AccountDetails>().unwrap();
+    assert_eq!(
+        (account.name.as_str(), account.status),
+        ("Test One", AccountStatus::Suspended)
+    );
+
+    // Assert the account cannot log in, refresh its tokens or reset its password
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let problem = response.into_json::<Value>().unwrap();
+    assert_eq!(problem["type"], "urn:account-api:problem:account-suspended");
+    assert!(problem["access_token"].is_null());
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "wrong" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let (status, _) = refresh(&client, &refresh_token);
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(
+        reset_password(&client, &token, "new password"),
+        Status::Forbidden
+    );
+
+    // Assert an account active again can log in with its old password
+    sidecar.edit("test_1", |account| account["status"] = json!("active"));
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+/// Test password resets in memory.
+#[test]
+fn test_password_reset() {
+    let (client, mailer) = mail_client(None, Some(PasswordResetConfig::default()), None);
+    check_password_reset(&client, &mailer);
+}
+
+/// Test password resets with the dapr store.
+#[test]
+fn test_password_reset_dapr() {
+    let sidecar = FakeSidecar::start();
+    let (client, mailer) = mail_client(Some(&sidecar), Some(PasswordResetConfig::default()), None);
+    check_password_reset(&client, &mailer);
+
+    // Assert used reset tokens are removed from the store
+    assert!(sidecar.keys().iter().all(|key| !key.starts_with("action:")));
+}
+
+/// Test expired password reset tokens are rejected.
+#[test]
+fn test_password_reset_expiry() {
+    let config = PasswordResetConfig {
+        token_ttl_secs: 0,
+        link: "https://example.com/reset#token={token}".to_string(),
+    };
+    let (client, mailer) = mail_client(None, Some(config), None);
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    // Assert the configured link is used, and the token has expired on arrival
+    assert_eq!(
+        forgot_password(&client, "test1@gmail.com"),
+        Status::Accepted
+    );
+    let message = &mailer.wait_for(1)[0];
+    assert!(message.body.contains("https://example.com/reset#token="));
+    assert_eq!(
+        reset_password(&client, &link_token(message), "new password"),
+        Status::Unauthorized
+    );
+}
+
+/// Verify an email.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `token` - The verification token
+///
+/// # Returns
+/// The status of the response
+fn verify_email(client: &Client, token: &str) -> Status {
+    client
+        .post("/api/v1/accounts/verify-email")
+        .json(&json!({ "token": token }))
+        .dispatch()
+        .status()
+}
+
+/// Ask for a new verification link.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `email` - The email to send the verification link to
+///
+/// # Returns
+/// The status of the response
+fn resend_verification(client: &Client, email: &str) -> Status {
+    client
+        .post("/api/v1/accounts/verify-email/resend")
+        .json(&json!({ "email": email }))
+        .dispatch()
+        .status()
+}
+
+/// Get the email verification of an account.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `id` - The id of the account
+///
+/// # Returns
+/// Whether the email is verified, and when
+fn email_verification(client: &Client, id: &str) -> (bool, Value) {
+    let account = client
+        .get(format!("/api/v1/accounts/id/{}", id))
+        .dispatch()
+        .into_json::<Value>()
+        .unwrap();
+    (
+        account["email_verified"].as_bool().unwrap(),
+        account["email_verified_at"].clone(),
+    )
+}
+
+/// Check the email verification flow.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+/// * `mailer` - The mailer of the client
+fn check_email_verification(client: &Client, mailer: &RecordingMailer) {
+    // Assert new accounts are unverified and get a link to the verification page
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let messages = mailer.wait_for(1);
+    assert_eq!(messages[0].to, "test1@gmail.com");
+    assert_eq!(messages[0].subject, "Verify your email");
+    assert!(messages[0]
+        .body
+        .contains("http://localhost:3000/verify-email?token="));
+    let stale_token = link_token(&messages[0]);
+    assert_eq!(email_verification(client, "test_1"), (false, Value::Null));
+
+    // Assert a changed email gets its own link, and old links no longer verify
+    let merge_patch = Header::new("Content-Type", "application/merge-patch+json");
+    let response = client
+        .patch("/api/v1/accounts/id/test_1")
+        .header(owner(client, "test_1"))
+        .header(merge_patch.clone())
+        .body(json!({ "email": "test2@gmail.com" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let messages = mailer.wait_for(2);
+    assert_eq!(messages[1].to, "test2@gmail.com");
+    let token = link_token(&messages[1]);
+    assert_eq!(verify_email(client, &stale_token), Status::Unauthorized);
+
+    // Assert unknown tokens are rejected, malformed requests too
+    assert_eq!(verify_email(client, "unknown"), Status::Unauthorized);
+    assert_eq!(verify_email(client, ""), Status::UnprocessableEntity);
+
+    // Assert the token verifies the email once
+    assert_eq!(verify_email(client, &token), Status::NoContent);
+    assert_eq!(verify_email(client, &token), Status::Unauthorized);
+    let (verified, verified_at) = email_verification(client, "test_1");
+    assert!(verified);
+    assert!(verified_at.as_u64().unwrap() > 0);
+
+    // Assert other changes keep the verification
+    let response = client
+        .patch("/api/v1/accounts/id/test_1")
+        .header(owner(client, "test_1"))
+        .header(merge_patch)
+        .body(json!({ "name": "Test One", "email": "Test2@Gmail.com" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        email_verification(client, "test_1"),
+        (true, verified_at.clone())
+    );
+
+    // Assert only unverified emails get new links, and callers cannot tell
+    assert_eq!(
+        resend_verification(client, "test2@gmail.com"),
+        Status::Accepted
+    );
+    assert_eq!(
+        resend_verification(client, "unknown@gmail.com"),
+        Status::Accepted
+    );
+    assert_eq!(
+        resend_verification(client, "not an email"),
+        Status::UnprocessableEntity
+    );
+
+    // Assert replacing the email resets the verification
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(client, "test_1"))
+        .json(&json!({ "id": "test_1", "name": "Test One", "email": "test3@gmail.com" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(email_verification(client, "test_1"), (false, Value::Null));
+    let messages = mailer.wait_for(3);
+    assert_eq!(messages[2].to, "test3@gmail.com");
+
+    // Assert a new link can be requested and verifies the email
+    assert_eq!(
+        resend_verification(client, "test3@gmail.com"),
+        Status::Accepted
+    );
+    let token = link_token(&mailer.wait_for(4)[3]);
+    assert_eq!(verify_email(client, &token), Status::NoContent);
+    assert!(email_verification(client, "test_1").0);
+    assert_eq!(mailer.messages().len(), 4);
+}
+
+/// Test email verification in memory.
+#[test]
+fn test_email_verification() {
+    let (client, mailer) = mail_client(None, None, Some(EmailVerificationConfig::default()));
+    check_email_verification(&client, &mailer);
+}
+
+/// Test email verification with the dapr store.
+#[test]
+fn test_email_verification_dapr() {
+    let sidecar = FakeSidecar::start();
+    let (client, mailer) = mail_client(
+        Some(&sidecar),
+        None,
+        Some(EmailVerificationConfig::default()),
+    );
+    check_email_verification(&client, &mailer);
+
+    // Assert used verification tokens are removed, the one link never followed is left
+    let keys = sidecar.keys();
+    assert_eq!(
+        keys.iter().filter(|key| key.starts_with("action:")).count(),
+        1
+    );
+}
+
+/// Test that logins can require a verified email.
+#[test]
+fn test_email_verification_required() {
+    let config = EmailVerificationConfig {
+        required: true,
+        ..EmailVerificationConfig::default()
+    };
+    let (client, mailer) = mail_client(None, None, Some(config));
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    // Assert unverified accounts cannot log in, wrong passwords still fail first
+    let validate = |password: &str| {
+        client
+            .post("/api/v1/accounts/validate")
+            .json(&json!({ "email": "test1@gmail.com", "password": password }))
+            .dispatch()
+    };
+    assert_eq!(validate("wrong").status(), Status::Unauthorized);
+    let response = validate("password");
+    assert_eq!(response.status(), Status::Forbidden);
+    let problem = response.into_json::<Value>().unwrap();
+    assert_eq!(
+        problem["type"],
+        "urn:account-api:problem:email-not-verified"
+    );
+    assert!(problem["access_token"].is_null());
+
+    // Assert verified accounts log in, with the verification in the token
+    let token = link_token(&mailer.wait_for(1)[0]);
+    assert_eq!(verify_email(&client, &token), Status::NoContent);
+    let response = validate("password");
+    assert_eq!(response.status(), Status::Ok);
+    let login = response.into_json::<Value>().unwrap();
+    assert_eq!(login["email_verified"], true);
+    let claims = login["access_token"].as_str().unwrap().split('.').nth(1);
+    let claims: Value = rocket::serde::json::from_slice(
+        &base64::engine::Engine::decode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            claims.unwrap(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims["email_verified"], true);
+}
+
+/// Check that an action token dao hands out every token once.
+///
+/// # Arguments
+/// * `dao` - An empty action token dao
+fn check_action_token_dao(dao: impl ActionTokenDao) {
+    let token = ActionTokenEntity {
+        id: "hash_1".to_string(),
+        purpose: ActionPurpose::PasswordReset,
+        account_id: "acc_1".to_string(),
+        email: None,
+        expires_at: u64::MAX / 2,
+        etag: None,
+    };
+    block_on(async {
+        // Assert tokens are unique
+        dao.create_action_token(token.clone()).await.unwrap();
+        assert!(matches!(
+            dao.create_action_token(token.clone()).await,
+            Err(AccountError::Conflict(_))
+        ));
+
+        // Assert a token is consumed once
+        let consumed = dao
+            .consume_action_token("hash_1".to_string())
+            .await
+            .unwrap();
+        assert_eq!(consumed.account_id, "acc_1");
+        assert_eq!(consumed.purpose, ActionPurpose::PasswordReset);
+        assert!(matches!(
+            dao.consume_action_token("hash_1".to_string()).await,
+            Err(AccountError::NotFound)
+        ));
+        assert!(matches!(
+            dao.consume_action_token("unknown".to_string()).await,
+            Err(AccountError::NotFound)
+        ));
+    });
+}
+
+/// Test the in-memory action token dao.
+#[test]
+fn test_action_token_dao_in_memory() {
+    check_action_token_dao(InMemoryActionTokenDao::new());
+}
+
+/// Test the dapr action token dao.
+#[test]
+fn test_action_token_dao_dapr() {
+    let sidecar = FakeSidecar::start();
+    check_action_token_dao(sidecar.dao().action_token_dao());
+}
+
+/// Check that a login attempts dao counts failures until they expire.
+///
+/// # Arguments
+/// * `dao` - An empty login attempts dao
+fn check_login_attempts_dao(dao: impl LoginAttemptsDao) {
+    let id = || "email:test1@gmail.com".to_string();
+    block_on(async {
+        // Assert subjects without failures have no counter
+        assert!(matches!(
+            dao.get_login_attempts(id(), 1_000).await,
+            Err(AccountError::NotFound)
+        ));
+
+        // Assert failures add up, and are kept for the window after the last one
+        dao.record_login_failure(id(), 1_000, 60).await.unwrap();
+        let attempts = dao.record_login_failure(id(), 1_010, 60).await.unwrap();
+        assert_eq!(attempts.failures, 2);
+        assert_eq!(attempts.expires_at, 1_070);
+        let attempts = dao.get_login_attempts(id(), 1_069).await.unwrap();
+        assert_eq!(attempts.failures, 2);
+        assert_eq!(attempts.last_failure_at, 1_010);
+        assert!(matches!(
+            dao.get_login_attempts(id(), 1_070).await,
+            Err(AccountError::NotFound)
+        ));
+
+        // Assert counting starts over after the window
+        let attempts = dao.record_login_failure(id(), 1_070, 60).await.unwrap();
+        assert_eq!(attempts.failures, 1);
+
+        // Assert subjects are counted apart, and clearing forgets one of them
+        dao.record_login_failure("ip:10.0.0.1".to_string(), 1_070, 60)
+            .await
+            .unwrap();
+        dao.clear_login_attempts(id()).await.unwrap();
+        dao.clear_login_attempts(id()).await.unwrap();
+        assert!(matches!(
+            dao.get_login_attempts(id(), 1_070).await,
+            Err(AccountError::NotFound)
+        ));
+        let attempts = dao
+            .get_login_attempts("ip:10.0.0.1".to_string(), 1_070)
+            .await
+            .unwrap();
+        assert_eq!(attempts.failures, 1);
+    });
+}
+
+/// Test the in-memory login attempts dao.
+#[test]
+fn test_login_attempts_dao_in_memory() {
+    check_login_attempts_dao(InMemoryLoginAttemptsDao::new());
+}
+
+/// Test the dapr login attempts dao.
+#[test]
+fn test_login_attempts_dao_dapr() {
+    let sidecar = FakeSidecar::start();
+    check_login_attempts_dao(sidecar.dao().login_attempts_dao());
+    assert!(sidecar.keys().contains(&"attempts:ip:10.0.0.1".to_string()));
+}
+
+/// Test lockouts double with every failure past the threshold, up to the maximum.
+#[test]
+fn test_lockout_backoff() {
+    let config = LockoutConfig {
+        base_lockout_secs: 30,
+        max_lockout_secs: 100,
+        ..LockoutConfig::default()
+    };
+    assert_eq!(config.lockout_secs(4, 5), 0);
+    assert_eq!(config.lockout_secs(5, 5), 30);
+    assert_eq!(config.lockout_secs(6, 5), 60);
+    assert_eq!(config.lockout_secs(7, 5), 100);
+    assert_eq!(config.lockout_secs(u32::MAX, 5), 100);
+    assert_eq!(config.lockout_secs(u32::MAX, 0), 0);
+}
+
+/// Create an `Authorization` header for an access token.
+///
+/// # Arguments
+/// * `token` - The access token
+///
+/// # Returns
+/// The bearer authorization header
+fn bearer(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+/// Create an `Authorization` header acting as an account.
+///
+/// # Arguments
+/// * `client` - The client whose token issuer signs the token
+/// * `id` - The id of the account
+///
+/// # Returns
+/// The bearer authorization header with an access token of the account
+fn owner(client: &Client, id: &str) -> Header<'static> {
+    let account = AccountDetails {
+        id: id.to_string(),
+        ..stub_account()
+    };
+    let tokens = &client.rocket().state::<ServiceProvider>().unwrap().tokens;
+    bearer(&tokens.issue(&account, "session_1").unwrap().access_token)
+}
+
+/// Test repeated failed logins lock out the email and the client.
+#[test]
+fn test_login_lockout() {
+    // Create a client with an admin account
+    let dao = InMemoryAccountDao::new();
+    block_on(dao.create_account(AccountEntity {
+        roles: [default_roles(), vec![ADMIN_ROLE.to_string()]].concat(),
+        ..account("admin_1", "Admin 1", "admin1@gmail.com")
+    }))
+    .unwrap();
+    let lockouts = Lockouts::new(
+        Box::new(InMemoryLoginAttemptsDao::new()),
+        LockoutConfig {
+            account_threshold: 3,
+            ip_threshold: 5,
+            base_lockout_secs: 60,
+            ..LockoutConfig::default()
+        },
+    );
+    let provider =
+        ServiceProvider::new(DaprAccountService::new(Box::new(dao))).with_lockouts(lockouts);
+    let client = Client::tracked(server().manage(provider)).expect("valid rocket instance");
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let login = |email: &str, password: &str, ip: &str| {
+        client
+            .post("/api/v1/accounts/validate")
+            .remote(format!("{}:50000", ip).parse().unwrap())
+            .json(&json!({ "email": email, "password": password }))
+            .dispatch()
+    };
+
+    // Assert a successful login forgets earlier failures
+    for _ in 0..2 {
+        assert_eq!(
+            login("test1@gmail.com", "wrong", "10.0.0.1").status(),
+            Status::Unauthorized
+        );
+    }
+    let response = login("test1@gmail.com", "password", "10.0.0.1");
+    assert_eq!(response.status(), Status::Ok);
+    let user_token = response.into_json::<Value>().unwrap()["access_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Assert the email is locked out after the threshold, even with the right password
+    for _ in 0..3 {
+        assert_eq!(
+            login("Test1@Gmail.com", "wrong", "10.0.0.2").status(),
+            Status::Unauthorized
+        );
+    }
+    let response = login("test1@gmail.com", "password", "10.0.0.3");
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("60"));
+    let problem = response.into_json::<Value>().unwrap();
+    assert_eq!(problem["type"], "urn:account-api:problem:locked-out");
+    assert!(problem["access_token"].is_null());
+
+    // Assert anonymous clients, forged tokens and plain users cannot unlock accounts
+    let unlock = |id: &str, token: Option<&str>| {
+        let request = client.delete(format!("/api/v1/accounts/id/{}/lockout", id));
+        match token {
+            Some(token) => request.header(bearer(token)).dispatch(),
+            None => request.dispatch(),
+        }
+    };
+    let response = unlock("test_1", None);
+    assert_eq!(response.status(), Status::Unauthorized);
+    let problem = response.into_json::<Value>().unwrap();
+    assert_eq!(problem["type"], "urn:account-api:problem:invalid-token");
+    let (signed, _) = user_token.rsplit_once('.').unwrap();
+    let forged = format!("{}.{}", signed, user_token.split('.').next().unwrap());
+    assert_eq!(
+        unlock("test_1", Some(&forged)).status(),
+        Status::Unauthorized
+    );
+    let response = unlock("test_1", Some(&user_token));
+    assert_eq!(response.status(), Status::Forbidden);
+    let problem = response.into_json::<Value>().unwrap();
+    assert_eq!(problem["type"], "urn:account-api:problem:forbidden");
+    assert_eq!(
+        login("test1@gmail.com", "password", "10.0.0.3").status(),
+        Status::TooManyRequests
+    );
+
+    // Assert an admin unlocks the account, unknown accounts cannot be unlocked
+    let response = login("admin1@gmail.com", "password", "10.0.0.5");
+    assert_eq!(response.status(), Status::Ok);
+    let admin = response.into_json::<Value>().unwrap();
+    assert_eq!(admin["roles"], json!(["user", "admin"]));
+    let admin_token = admin["access_token"].as_str().unwrap();
+    assert_eq!(
+        unlock("test_1", Some(admin_token)).status(),
+        Status::NoContent
+    );
+    assert_eq!(
+        unlock("unknown", Some(admin_token)).status(),
+        Status::NotFound
+    );
+    assert_eq!(
+        login("test1@gmail.com", "password", "10.0.0.3").status(),
+        Status::Ok
+    );
+
+    // Assert a client trying many emails is locked out, unknown emails count too
+    for i in 0..2 {
+        assert_eq!(
+            login(&format!("unknown{}@gmail.com", i), "wrong", "10.0.0.2").status(),
+            Status::Unauthorized
+        );
+    }
+    let response = login("test1@gmail.com", "password", "10.0.0.2");
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("60"));
+    assert_eq!(
+        login("test1@gmail.com", "password", "10.0.0.4").status(),
+        Status::Ok
+    );
+
+    // Assert unlocking an account keeps the client locked out unless it is named
+    assert_eq!(
+        unlock("test_1", Some(admin_token)).status(),
+        Status::NoContent
+    );
+    assert_eq!(
+        login("test1@gmail.com", "password", "10.0.0.2").status(),
+        Status::TooManyRequests
+    );
+    let response = client
+        .delete("/api/v1/accounts/id/test_1/lockout?ip=nonsense")
+        .header(bearer(admin_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let response = client
+        .delete("/api/v1/accounts/id/test_1/lockout?ip=10.0.0.2")
+        .header(bearer(admin_token))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        login("test1@gmail.com", "password", "10.0.0.2").status(),
+        Status::Ok
+    );
+
+    // Assert forged proxy headers do not change the counted client
+    let forged = |email: &str, password: &str, header: &str| {
+        client
+            .post("/api/v1/accounts/validate")
+            .remote("10.0.0.6:50000".parse().unwrap())
+            .header(Header::new("X-Real-IP", header.to_string()))
+            .header(Header::new("X-Forwarded-For", header.to_string()))
+            .json(&json!({ "email": email, "password": password }))
+            .dispatch()
+    };
+    for i in 0..5 {
+        assert_eq!(
+            forged(
+                &format!("other{}@gmail.com", i),
+                "wrong",
+                &format!("10.1.0.{}", i)
+            )
+            .status(),
+            Status::Unauthorized
+        );
+    }
+    assert_eq!(
+        forged("test1@gmail.com", "password", "10.1.0.9").status(),
+        Status::TooManyRequests
+    );
+    assert_eq!(
+        login("test1@gmail.com", "password", "10.1.0.9").status(),
+        Status::Ok
+    );
+}
+
+/// Test TOTP codes against the SHA-1 vectors of RFC 6238, and base32 against RFC 4648.
+#[test]
+fn test_totp() {
+    // Assert the reference codes, in eight and six digits
+    let secret = b"12345678901234567890";
+    for (time, code) in [
+        (59, "94287082"),
+        (1_111_111_109, "07081804"),
+        (1_111_111_111, "14050471"),
+        (1_234_567_890, "89005924"),
+        (2_000_000_000, "69279037"),
+        (20_000_000_000, "65353130"),
+    ] {
+        assert_eq!(code_at(secret, time_step(time), 8), code);
+        assert_eq!(code_at(secret, time_step(time), 6), code[2..]);
+    }
+
+    // Assert codes of neighbouring steps are accepted once, older ones never
+    let time = 1_111_111_109;
+    let step = time_step(time);
+    let code = |step| code_at(secret, step, 6);
+    assert_eq!(verify_code(secret, &code(step), time, 0), Some(step));
+    assert_eq!(
+        verify_code(secret, &code(step - 1), time, 0),
+        Some(step - 1)
+    );
+    assert_eq!(
+        verify_code(secret, &code(step + 1), time, 0),
+        Some(step + 1)
+    );
+    assert_eq!(verify_code(secret, &code(step + 2), time, 0), None);
+    assert_eq!(verify_code(secret, &code(step), time, step), None);
+    assert_eq!(
+        verify_code(secret, &code(step + 1), time, step),
+        Some(step + 1)
+    );
+    assert_eq!(verify_code(secret, "", time, 0), None);
+
+    // Assert the RFC 4648 base32 vectors, without padding
+    for (bytes, encoded) in [
+        ("", ""),
+        ("f", "MY"),
+        ("fo", "MZXQ"),
+        ("foo", "MZXW6"),
+        ("foob", "MZXW6YQ"),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI"),
+    ] {
+        assert_eq!(base32_encode(bytes.as_bytes()), encoded);
+    }
+
+    // Assert the provisioning uri names the issuer and account
+    assert_eq!(
+        provisioning_uri("Auction Games", "a+b@gmail.com", b"foobar").unwrap(),
+        "otpauth://totp/Auction%20Games:a+b@gmail.com?secret=MZXW6YTBOI\
+         &issuer=Auction%20Games&algorithm=SHA1&digits=6&period=30"
+    );
+}
+
+/// Get the code of an authenticator app.
+///
+/// # Arguments
+/// * `secret` - The base32 encoded secret
+/// * `step` - The time step of the code
+///
+/// # Returns
+/// The six digit code
+fn totp_code(secret: &str, step: u64) -> String {
+    let alphabet = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in secret.chars() {
+        buffer = (buffer << 5) | alphabet.find(c).expect("base32 secret") as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    code_at(&bytes, step, 6)
+}
+
+/// Get the current time step of authenticator apps.
+///
+/// # Returns
+/// The time step
+fn current_step() -> u64 {
+    time_step(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    )
+}
+
+/// Log in with a password.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `password` - The password of `test1@gmail.com`
+///
+/// # Returns
+/// The status and body of the response
+fn password_login(client: &Client, password: &str) -> (Status, Value) {
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": password }))
+        .dispatch();
+    (response.status(), response.into_json().unwrap())
+}
+
+/// Complete a login with a second factor.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `mfa_token` - The challenge token
+/// * `factor` - The `code` or `recovery_code`
+///
+/// # Returns
+/// The status and body of the response
+fn mfa_login(client: &Client, mfa_token: &Value, factor: Value) -> (Status, Value) {
+    let mut body = factor;
+    body["mfa_token"] = mfa_token.clone();
+    let response = client
+        .post("/api/v1/accounts/validate/mfa")
+        .json(&body)
+        .dispatch();
+    (response.status(), response.into_json().unwrap())
+}
+
+/// Post a second factor to an account endpoint.
+///
+/// # Arguments
+/// * `client` - The client to use
+/// * `token` - The access token of the account
+/// * `path` - The path below the account
+/// * `body` - The request body
+///
+/// # Returns
+/// The status and body of the response
+fn post_mfa(client: &Client, token: &str, path: &str, body: Value) -> (Status, Value) {
+    let response = client
+        .post(format!("/api/v1/accounts/id/test_1/mfa/{}", path))
+        .header(bearer(token))
+        .json(&body)
+        .dispatch();
+    let status = response.status();
+    (status, response.into_json().unwrap_or(Value::Null))
+}
+
+/// Check TOTP enrollment and two-step logins.
+///
+/// # Arguments
+/// * `client` - A client with no accounts
+fn check_two_factor(client: &Client) {
+    // Assert new accounts log in with the password alone
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let (status, login) = password_login(client, "password");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(login["mfa_enabled"], false);
+    let token = login["access_token"].as_str().unwrap().to_string();
+
+    // Assert only the owner can enroll, and only started enrollments are confirmed
+    let (status, _) = post_mfa(client, &token, "totp", json!({ "password": "wrong" }));
+    assert_eq!(status, Status::Unauthorized);
+    let (status, _) = post_mfa(client, &token, "totp", json!({ "password": "" }));
+    assert_eq!(status, Status::UnprocessableEntity);
+    let response = client
+        .post("/api/v1/accounts/id/unknown/mfa/totp")
+        .json(&json!({ "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let (status, _) = post_mfa(client, &token, "totp/confirm", json!({ "code": "123456" }));
+    assert_eq!(status, Status::Conflict);
+
+    // Assert an enrollment hands out the secret for authenticator apps
+    let (status, enrollment) = post_mfa(client, &token, "totp", json!({ "password": "password" }));
+    assert_eq!(status, Status::Ok);
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    assert_eq!(secret.len(), 32);
+    let uri = enrollment["otpauth_uri"].as_str().unwrap();
+    assert!(uri.starts_with("otpauth://totp/Auction%20Games:test1@gmail.com?"));
+    assert!(uri.contains(&format!("secret={}&issuer=Auction%20Games", secret)));
+
+    // Assert only the owner confirms the enrollment
+    let step = current_step();
+    let code = totp_code(&secret, step);
+    for token in ["", "forged"] {
+        let (status, _) = post_mfa(client, token, "totp/confirm", json!({ "code": code }));
+        assert_eq!(status, Status::Unauthorized);
+    }
+
+    // Assert malformed codes do not confirm the enrollment, a wrong code uses it up
+    let (status, _) = post_mfa(client, &token, "totp/confirm", json!({ "code": "12345" }));
+    assert_eq!(status, Status::UnprocessableEntity);
+    let (status, _) = post_mfa(client, &token, "totp/confirm", json!({}));
+    assert_eq!(status, Status::UnprocessableEntity);
+    let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+    let (status, problem) = post_mfa(client, &token, "totp/confirm", json!({ "code": wrong }));
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(problem["type"], "urn:account-api:problem:invalid-mfa-code");
+    let (status, _) = post_mfa(client, &token, "totp/confirm", json!({ "code": code }));
+    assert_eq!(status, Status::Conflict);
+    assert_eq!(password_login(client, "password").0, Status::Ok);
+
+    // Assert a new enrollment can be confirmed
+    let (status, enrollment) = post_mfa(client, &token, "totp", json!({ "password": "password" }));
+    assert_eq!(status, Status::Ok);
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    let code = totp_code(&secret, step);
+    let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+    // Assert the first code enables two-factor logins and hands out recovery codes
+    let (status, confirmed) = post_mfa(client, &token, "totp/confirm", json!({ "code": code }));
+    assert_eq!(status, Status::Ok);
+    let recovery_codes: Vec<String> = confirmed["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(recovery_codes.len(), 10);
+    let (status, _) = post_mfa(client, &token, "totp", json!({ "password": "password" }));
+    assert_eq!(status, Status::Conflict);
+    let account = client
+        .get("/api/v1/accounts/id/test_1")
+        .dispatch()
+        .into_json::<Value>()
+        .unwrap();
+    assert_eq!(account["mfa_enabled"], true);
+
+    // Assert the password alone only starts a challenge, allowing one try
+    let (status, challenge) = password_login(client, "password");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(challenge["mfa_required"], true);
+    assert_eq!(challenge["expires_in"], 300);
+    assert!(challenge["access_token"].is_null());
+    assert_eq!(password_login(client, "wrong").0, Status::Unauthorized);
+    let (status, problem) = mfa_login(client, &challenge["mfa_token"], json!({ "code": wrong }));
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(problem["type"], "urn:account-api:problem:invalid-mfa-code");
+    let next = totp_code(&secret, step + 1);
+    let (status, problem) = mfa_login(client, &challenge["mfa_token"], json!({ "code": next }));
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(problem["type"], "urn:account-api:problem:invalid-token");
+
+    // Assert a fresh code completes the login, used codes do not
+    let (_, challenge) = password_login(client, "password");
+    let (status, login) = mfa_login(client, &challenge["mfa_token"], json!({ "code": next }));
+    assert_eq!(status, Status::Ok);
+    assert!(login["access_token"].is_string());
+    assert!(login["refresh_token"].is_string());
+    assert_eq!(login["mfa_enabled"], true);
+    for used in [&next, &code] {
+        let (_, challenge) = password_login(client, "password");
+        let (status, _) = mfa_login(client, &challenge["mfa_token"], json!({ "code": used }));
+        assert_eq!(status, Status::Unauthorized);
+    }
+
+    // Assert recovery codes complete a login once, however they are typed
+    let typed = recovery_codes[0].replace('-', " ").to_uppercase();
+    let (_, challenge) = password_login(client, "password");
+    let (status, login) = mfa_login(
+        client,
+        &challenge["mfa_token"],
+        json!({ "recovery_code": typed }),
+    );
+    assert_eq!(status, Status::Ok);
+    assert!(login["access_token"].is_string());
+    let (_, challenge) = password_login(client, "password");
+    let (status, _) = mfa_login(
+        client,
+        &challenge["mfa_token"],
+        json!({ "recovery_code": recovery_codes[0] }),
+    );
+    assert_eq!(status, Status::Unauthorized);
+
+    // Assert profile updates keep two-factor authentication
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(client, "test_1"))
+        .json(&json!({ "id": "test_1", "name": "Test One", "email": "test1@gmail.com" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(password_login(client, "password").1["mfa_required"], true);
+
+    // Assert only the owner turns two-factor authentication off
+    let (status, _) = post_mfa(
+        client,
+        "",
+        "disable",
+        json!({ "recovery_code": recovery_codes[1] }),
+    );
+    assert_eq!(status, Status::Unauthorized);
+
+    // Assert a second factor turns two-factor authentication off
+    let (status, _) = post_mfa(
+        client,
+        &token,
+        "disable",
+        json!({ "recovery_code": "unknown" }),
+    );
+    assert_eq!(status, Status::Unauthorized);
+    let (status, _) = post_mfa(
+        client,
+        &token,
+        "disable",
+        json!({ "recovery_code": recovery_codes[1] }),
+    );
+    assert_eq!(status, Status::NoContent);
+    let (status, _) = post_mfa(
+        client,
+        &token,
+        "disable",
+        json!({ "recovery_code": recovery_codes[2] }),
+    );
+    assert_eq!(status, Status::Conflict);
+    let (status, login) = password_login(client, "password");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(login["mfa_enabled"], false);
+    assert!(login["access_token"].is_string());
+}
+
+/// Test two-factor authentication in memory.
+#[test]
+fn test_two_factor() {
+    check_two_factor(&client());
+}
+
+/// Test two-factor authentication with the dapr store.
+#[test]
+fn test_two_factor_dapr() {
+    let sidecar = FakeSidecar::start();
+    let client = dapr_client(&sidecar);
+    check_two_factor(&client);
+
+    // Assert every challenge was used up
+    let keys = sidecar.keys();
+    assert!(!keys.iter().any(|key| key.starts_with("action:")));
+
+    // Assert secrets are stored encrypted
+    let (_, login) = password_login(&client, "password");
+    let token = login["access_token"].as_str().unwrap().to_string();
+    let (status, enrollment) = post_mfa(&client, &token, "totp", json!({ "password": "password" }));
+    assert_eq!(status, Status::Ok);
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    let stored = block_on(sidecar.dao().get_account_by_id("test_1".to_string())).unwrap();
+    let encrypted = stored.mfa.pending_totp_secret.unwrap();
+    assert!(!encrypted.contains(&secret));
+    assert_eq!(stored.mfa.totp_secret, None);
+    assert!(stored.mfa.pending_totp_expires_at > 0);
+
+    // Assert expired enrollments cannot be confirmed
+    sidecar.edit("test_1", |account| {
+        account["mfa"]["pending_totp_expires_at"] = json!(1)
+    });
+    let code = totp_code(&secret, current_step());
+    let (status, _) = post_mfa(&client, &token, "totp/confirm", json!({ "code": code }));
+    assert_eq!(status, Status::Conflict);
+}
+
+/// Test wrong second factors count as failed logins.
+#[test]
+fn test_two_factor_lockout() {
+    let figment = Config::figment()
+        .merge(("account_store", "memory"))
+        .merge(("lockout", json!({ "account_threshold": 2 })));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_1",
+            "name": "Test 1",
+            "email": "test1@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let (_, login) = password_login(&client, "password");
+    let token = login["access_token"].as_str().unwrap().to_string();
+    let (_, enrollment) = post_mfa(&client, &token, "totp", json!({ "password": "password" }));
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    let step = current_step();
+    let (status, _) = post_mfa(
+        &client,
+        &token,
+        "totp/confirm",
+        json!({ "code": totp_code(&secret, step) }),
+    );
+    assert_eq!(status, Status::Ok);
+
+    // Assert the email is locked out after wrong codes, even with the right one
+    for _ in 0..2 {
+        let (_, challenge) = password_login(&client, "password");
+        let (status, _) = mfa_login(
+            &client,
+            &challenge["mfa_token"],
+            json!({ "recovery_code": "wrong" }),
+        );
+        assert_eq!(status, Status::Unauthorized);
+    }
+    assert_eq!(
+        password_login(&client, "password").0,
+        Status::TooManyRequests
+    );
+    let (status, _) = post_mfa(
+        &client,
+        &token,
+        "disable",
+        json!({ "code": totp_code(&secret, step + 1) }),
+    );
+    assert_eq!(status, Status::TooManyRequests);
+
+    // Assert the password cannot be checked outside of logins either
+    let (status, _) = post_mfa(&client, &token, "totp", json!({ "password": "password" }));
+    assert_eq!(status, Status::TooManyRequests);
+    let response = client
+        .post("/api/v1/accounts/id/test_1/password")
+        .json(&json!({ "current_password": "password", "new_password": "new password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+
+    // Assert wrong codes confirming an enrollment count as failed logins too
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&json!({
+            "id": "test_2",
+            "name": "Test 2",
+            "email": "test2@gmail.com",
+            "password": "password"
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let login = |password: &str| {
+        client
+            .post("/api/v1/accounts/validate")
+            .json(&json!({ "email": "test2@gmail.com", "password": password }))
+            .dispatch()
+    };
+    let response = login("password");
+    assert_eq!(response.status(), Status::Ok);
+    let token = response.into_json::<Value>().unwrap()["access_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let response = client
+        .post("/api/v1/accounts/id/test_2/mfa/totp")
+        .json(&json!({ "password": "password" }))
+        .dispatch();
+    let secret = response.into_json::<Value>().unwrap()["secret"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let code = totp_code(&secret, current_step());
+    let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+    let response = client
+        .post("/api/v1/accounts/id/test_2/mfa/totp/confirm")
+        .header(bearer(&token))
+        .json(&json!({ "code": wrong }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(login("wrong").status(), Status::Unauthorized);
+    assert_eq!(login("password").status(), Status::TooManyRequests);
+}
+
+/// Test mails are sent through the dapr SMTP output binding.
+#[test]
+fn test_dapr_binding_mailer() {
+    let sidecar = FakeSidecar::start();
+    let mailer = DaprBindingMailer::new(
+        sidecar.url(),
+        "smtp",
+        "no-reply@test.com",
+        DaprClientConfig::default(),
+    )
+    .unwrap();
+    let message = MailMessage {
+        to: "test1@gmail.com".to_string(),
+        subject: "Subject".to_string(),
+        body: "Body".to_string(),
+    };
+
+    // Assert the mail is handed to the binding with its addresses
+    block_on(mailer.send(message.clone())).unwrap();
+    assert_eq!(
+        sidecar.binding_requests(),
+        vec![(
+            "smtp".to_string(),
+            json!({
+                "operation": "create",
+                "data": "Body",
+                "metadata": {
+                    "emailFrom": "no-reply@test.com",
+                    "emailTo": "test1@gmail.com",
+                    "subject": "Subject"
+                }
+            })
+        )]
+    );
+
+    // Assert failed mails are reported and not sent again
+    sidecar.inject(Fault::Status(503));
+    let before = sidecar.request_count();
+    assert!(block_on(mailer.send(message)).is_err());
+    assert_eq!(sidecar.request_count(), before + 1);
+    assert_eq!(sidecar.binding_requests().len(), 1);
+}
+
+/// Test mails are written to files.
+#[test]
+fn test_file_mailer() {
+    let directory = std::env::temp_dir().join(format!("account-api-mail-{}", uuid::Uuid::new_v4()));
+    let mailer = FileMailer::new("no-reply@test.com", &directory);
+    for subject in ["First", "Second"] {
+        block_on(mailer.send(MailMessage {
+            to: "test1@gmail.com".to_string(),
+            subject: subject.to_string(),
+            body: "Body".to_string(),
+        }))
+        .unwrap();
+    }
+
+    // Assert one file per mail, in the order they were sent
+    let mut files: Vec<_> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    let contents: Vec<String> = files
+        .iter()
+        .map(|file| std::fs::read_to_string(file).unwrap())
+        .collect();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(contents.len(), 2);
+    assert!(contents[0].starts_with("From: no-reply@test.com\r\nTo: test1@gmail.com\r\n"));
+    assert!(contents[0].contains("Subject: First\r\n"));
+    assert!(contents[0].contains("\r\n\r\nBody"));
+    assert!(contents[1].contains("Subject: Second\r\n"));
+}
+
+/// Test the validate account endpoint.
+///
+/// # Note
+/// This will test creation, validation, and deletion.
+#[test]
+fn test_validate_account() {
+    // Create client
+    let client = client();
+
+    // Create account
+    let account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+
+    // Post the new account
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is ok
+    assert_eq!(response.status(), Status::Created);
+
+    // Create credentails model
+    let credentials = CredentialsModel {
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+
+    // Validate by email and password
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .header(ContentType::JSON)
+        .body(json!(&credentials).to_string())
+        .dispatch();
+
+    // Assert response is ok
+    assert_eq!(response.status(), Status::Ok);
+
+    // Attempt to parse the response
+    response.into_json::<AccountDetails>().unwrap();
+
+    // Delete account
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
+
+    // Assert response is no content
+    assert_eq!(response.status(), Status::NoContent);
+}
+
+/// Test creating an account with an email that is already in use.
+///
+/// # Note
+/// This will test creation, duplicate creation, and deletion.
+#[test]
+fn test_create_duplicate_email() {
+    // Create client
+    let client = client();
+
+    // Create account
+    let account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+
+    // Post the new account
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is ok
+    assert_eq!(response.status(), Status::Created);
+
+    // Post another account with the same email
+    let duplicate = AccountModel {
+        id: "test_2".to_string(),
+        ..account
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&duplicate).to_string())
+        .dispatch();
+
+    // Assert response is conflict
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Delete account
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
+
+    // Assert response is no content
+    assert_eq!(response.status(), Status::NoContent);
+
+    // Delete the account again
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .dispatch();
+
+    // Assert response is not found
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test the validate account endpoint with a wrong password.
+///
+/// # Note
+/// This will test creation and validation.
+#[test]
+fn test_validate_wrong_password() {
+    // Create client
+    let client = client();
+
+    // Create account
+    let account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+
+    // Post the new account
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is ok
+    assert_eq!(response.status(), Status::Created);
+
+    // Create credentials with the wrong password
+    let credentials = CredentialsModel {
+        email: "test1@gmail.com".to_string(),
+        password: "wrong password".to_string(),
+    };
+
+    // Validate by email and password
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .header(ContentType::JSON)
+        .body(json!(&credentials).to_string())
+        .dispatch();
+
+    // Assert response is unauthorized
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+/// An account service test double that knows a single account.
+struct StubAccountService;
+
+/// The stub account service implementation.
+#[async_trait]
+impl AccountService for StubAccountService {
+    async fn get_accounts(&self, _query: AccountQueryModel) -> AccountResult<AccountPageDetails> {
+        Ok(AccountPageDetails {
+            items: vec![stub_account()],
+            next_page_token: None,
+        })
+    }
+
+    async fn get_account_by_id(&self, id: String) -> AccountResult<AccountDetails> {
+        Some(stub_account())
+            .filter(|account| account.id == id)
+            .ok_or(AccountError::NotFound)
+    }
+
+    async fn get_account_by_email(&self, email: String) -> AccountResult<AccountDetails> {
+        Some(stub_account())
+            .filter(|account| account.email == email)
+            .ok_or(AccountError::NotFound)
+    }
+
+    async fn validate_account(
+        &self,
+        _credentials: CredentialsModel,
+    ) -> AccountResult<AccountDetails> {
+        Err(AccountError::InvalidCredentials)
+    }
+
+    async fn create_account(&self, _account: AccountModel) -> AccountResult<AccountDetails> {
+        Err(AccountError::StoreUnavailable("stub".to_string()))
+    }
+
+    async fn update_account(
+        &self,
+        _account: AccountUpdateModel,
+        _etag: Option<String>,
+    ) -> AccountResult<()> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn patch_account(
+        &self,
+        _id: String,
+        _patch: AccountPatchModel,
+        _etag: Option<String>,
+    ) -> AccountResult<AccountDetails> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn change_password(
+        &self,
+        _id: String,
+        _change: PasswordChangeModel,
+    ) -> AccountResult<()> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn reset_password(&self, _id: String, _reset: PasswordResetModel) -> AccountResult<()> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn verify_email(&self, _id: String, _email: String) -> AccountResult<()> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn get_mfa(&self, _id: String) -> AccountResult<(MfaSettings, Option<String>)> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn set_mfa(
+        &self,
+        _id: String,
+        _mfa: MfaSettings,
+        _etag: Option<String>,
+    ) -> AccountResult<()> {
+        Err(AccountError::NotFound)
+    }
+
+    async fn delete_account(&self, _id: String, _etag: Option<String>) -> AccountResult<()> {
+        Err(AccountError::NotFound)
+    }
+}
+
+/// The single account known by the stub account service.
+fn stub_account() -> AccountDetails {
+    AccountDetails {
+        id: "stub".to_string(),
+        name: "Stub".to_string(),
+        email: "stub@gmail.com".to_string(),
+        status: AccountStatus::Active,
+        roles: default_roles(),
+        email_verified: false,
+        email_verified_at: None,
+        mfa_enabled: false,
+        instance_id: String::new(),
+        etag: None,
+    }
+}
+
+/// Test that the routes work with any account service.
+#[test]
+fn test_custom_account_service() {
+    // Create client around the stub service
+    let rocket = server().manage(ServiceProvider::new(StubAccountService));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    // Get account by id
+    let response = client.get("/api/v1/accounts/id/stub").dispatch();
+
+    // Assert response is ok and served by the stub
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<AccountDetails>().unwrap().name, "Stub");
+
+    // Assert a failing store is reported as unavailable
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(
+            json!({ "id": "a", "name": "A", "email": "a@gmail.com", "password": "password" })
+                .to_string(),
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    // Assert store details are not leaked
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["type"], "urn:account-api:problem:store-unavailable");
+    assert!(!body["detail"].as_str().unwrap().contains("stub"));
+}
+
+/// Test that failures are reported as problem details.
+#[test]
+fn test_problem_details() {
+    // Create client
+    let client = client();
+
+    // Get a missing account with a request id
+    let response = client
+        .get("/api/v1/accounts/id/missing")
+        .header(Header::new("X-Request-Id", "test-request"))
+        .dispatch();
+
+    // Assert response is a not found problem
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("application", "problem+json"))
+    );
+    assert_eq!(
+        response.headers().get_one("X-Request-Id"),
+        Some("test-request")
+    );
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["type"], "urn:account-api:problem:account-not-found");
+    assert_eq!(body["title"], "Account not found");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["instance"], "/api/v1/accounts/id/missing");
+    assert_eq!(body["request_id"], "test-request");
+}
+
+/// Test that the catchers report problem details.
+#[test]
+fn test_catcher_problem_details() {
+    // Create client
+    let client = client();
+
+    // Request an unknown route
+    let response = client.get("/api/v1/unknown").dispatch();
+
+    // Assert response is a generic not found problem with a request id
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("application", "problem+json"))
+    );
+    let request_id = response
+        .headers()
+        .get_one("X-Request-Id")
+        .unwrap()
+        .to_string();
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["type"], "about:blank");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["request_id"], request_id);
+
+    // Post a body that is not an account
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!({ "name": 5 }).to_string())
+        .dispatch();
+
+    // Assert response is an unprocessable entity problem
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["status"], 422);
+}
+
+/// Test that invalid accounts are rejected with every offending field.
+#[test]
+fn test_create_invalid_account() {
+    // Create client
+    let client = client();
+
+    // Create an account where every field is invalid
+    let account = AccountModel {
+        id: "test/1".to_string(),
+        name: " ".to_string(),
+        email: "not-an-email".to_string(),
+        password: "short".to_string(),
+    };
+
+    // Post the new account
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is an unprocessable entity listing all fields
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["type"], "urn:account-api:problem:validation-failed");
+    let fields: Vec<&str> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["id", "name", "email", "password"]);
+
+    // Assert nothing was stored
+    let accounts = client
+        .get("/api/v1/accounts")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert!(accounts.items.is_empty());
+
+    // Validate with a malformed email
+    let credentials = CredentialsModel {
+        email: "test1@".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .header(ContentType::JSON)
+        .body(json!(&credentials).to_string())
+        .dispatch();
+
+    // Assert response is an unprocessable entity
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body = response.into_json::<Value>().unwrap();
+    assert_eq!(body["errors"][0]["field"], "email");
+}
+
+/// Test that the server generates an id when none is given.
+///
+/// # Note
+/// This will test creation, get by location, and duplicate ids.
+#[test]
+fn test_create_generates_id() {
+    // Create client
+    let client = client();
+
+    // Post a new account without an id
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(
+            json!({ "name": "Test 1", "email": "test1@gmail.com", "password": "password" })
+                .to_string(),
+        )
+        .dispatch();
+
+    // Assert response is created with a location
+    assert_eq!(response.status(), Status::Created);
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let created = response.into_json::<AccountDetails>().unwrap();
+    assert!(uuid::Uuid::parse_str(&created.id).is_ok());
+    assert_eq!(location, format!("/api/v1/accounts/id/{}", created.id));
+
+    // Assert the account is found at the location
+    let response = client.get(location).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_json::<AccountDetails>().unwrap().email,
+        "test1@gmail.com"
+    );
+
+    // Post another account reusing the generated id
+    let account = AccountModel {
+        id: created.id,
+        name: "Test 2".to_string(),
+        email: "test2@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is conflict
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+/// Test optimistic concurrency with etags.
+///
+/// # Note
+/// This will test creation, get by id, conditional update, and conditional deletion.
+#[test]
+fn test_update_if_match() {
+    // Create client
+    let client = client();
+
+    // Create account
+    let mut account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+
+    // Post the new account
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is ok
+    assert_eq!(response.status(), Status::Created);
+
+    // Get the account version
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+    // Put the updated account at the read version
+    account.name = "Updated".to_string();
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", etag.clone()))
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is no content and the version changed
+    assert_eq!(response.status(), Status::NoContent);
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_ne!(response.headers().get_one("ETag").unwrap(), etag);
+
+    // Put again with the stale version
+    account.name = "Stale".to_string();
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", etag.clone()))
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is precondition failed and nothing changed
+    assert_eq!(response.status(), Status::PreconditionFailed);
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(
+        response.into_json::<AccountDetails>().unwrap().name,
+        "Updated"
+    );
+
+    // Delete with the stale version
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .header(Header::new("If-Match", etag))
+        .dispatch();
+
+    // Assert response is precondition failed
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    // Delete with any version
+    let response = client
+        .delete("/api/v1/accounts/id/test_1")
+        .header(owner(&client, "test_1"))
+        .header(Header::new("If-Match", "*"))
+        .dispatch();
+
+    // Assert response is no content
+    assert_eq!(response.status(), Status::NoContent);
+}
+
+/// Test that emails stay unique through creation and email changes.
+///
+/// # Note
+/// This will test creation, email changes, and reuse of a released email.
+#[test]
+fn test_email_uniqueness() {
+    // Create client
+    let client = client();
+
+    // Create two accounts
+    for (id, email) in [("test_1", "test1@gmail.com"), ("test_2", "test2@gmail.com")] {
+        let account = AccountModel {
+            id: id.to_string(),
+            name: "Test".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+        };
+        let response = client
+            .post("/api/v1/accounts")
+            .header(ContentType::JSON)
+            .body(json!(&account).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    // Post an account whose email only differs in case
+    let account = AccountModel {
+        id: "test_3".to_string(),
+        name: "Test".to_string(),
+        email: "TEST1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is conflict
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Change the email of the second account to the email of the first
+    let mut account = AccountModel {
+        id: "test_2".to_string(),
+        name: "Test".to_string(),
+        email: "test1@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_2"))
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is conflict
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Change the email of the second account to a new email
+    account.email = "test4@gmail.com".to_string();
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_2"))
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is no content
+    assert_eq!(response.status(), Status::NoContent);
+
+    // Post an account with the released email
+    let account = AccountModel {
+        id: "test_3".to_string(),
+        name: "Test".to_string(),
+        email: "test2@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is created
+    assert_eq!(response.status(), Status::Created);
+}
+
+/// Test that accounts are found by email regardless of case.
+#[test]
+fn test_get_by_email_case_insensitive() {
+    // Create client
+    let client = client();
+
+    // Create account
+    let account = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test 1".to_string(),
+        email: "Test1@Gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&account).to_string())
+        .dispatch();
+
+    // Assert response is created
+    assert_eq!(response.status(), Status::Created);
+
+    // Get account by the lowercased email
+    let response = client
+        .get("/api/v1/accounts/email/test1@gmail.com")
+        .dispatch();
+
+    // Assert the account is found with its original email
+    assert_eq!(response.status(), Status::Ok);
+    let details = response.into_json::<AccountDetails>().unwrap();
+    assert_eq!(details.id, "test_1");
+    assert_eq!(details.email, "Test1@Gmail.com");
+
+    // Get account by an unknown email
+    let response = client
+        .get("/api/v1/accounts/email/test9@gmail.com")
+        .dispatch();
+
+    // Assert response is not found
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test paging, sorting and filtering the account listing.
+#[test]
+fn test_get_accounts_page() {
+    // Create accounts, the last one suspended in the store
+    let dao = InMemoryAccountDao::new();
+    for (i, name) in ["Dave", "Alice", "Carol", "Bob", "Eve"].iter().enumerate() {
+        let status = if i == 4 {
+            AccountStatus::Suspended
+        } else {
+            AccountStatus::Active
+        };
+        block_on(dao.create_account(AccountEntity {
+            status,
+            ..account(
+                &format!("test_{}", i),
+                name,
+                &format!("{}@gmail.com", name.to_lowercase()),
+            )
+        }))
+        .unwrap();
+    }
+    let provider = ServiceProvider::new(DaprAccountService::new(Box::new(dao)));
+    let client = Client::tracked(server().manage(provider)).expect("valid rocket instance");
+
+    // Follow the pages sorted by name, descending
+    let mut names = vec![];
+    let mut uri = "/api/v1/accounts?limit=2&sort=-name".to_string();
+    loop {
+        let response = client.get(uri.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let page = response.into_json::<AccountPageDetails>().unwrap();
+        assert!(page.items.len() <= 2);
+        names.extend(page.items.into_iter().map(|account| account.name));
+        match page.next_page_token {
+            Some(token) => {
+                uri = format!("/api/v1/accounts?limit=2&sort=-name&page_token={}", token)
+            }
+            None => break,
+        }
+    }
+    assert_eq!(names, vec!["Eve", "Dave", "Carol", "Bob", "Alice"]);
+
+    // Filter by name
+    let page = client
+        .get("/api/v1/accounts?name=Carol")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_2");
+    assert_eq!(page.next_page_token, None);
+
+    // Filter by email, regardless of case
+    let page = client
+        .get("/api/v1/accounts?email=BOB@gmail.com")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_3");
+
+    // Filter by status
+    let page = client
+        .get("/api/v1/accounts?status=suspended")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].status, AccountStatus::Suspended);
+
+    // Assert invalid parameters are rejected field by field
+    let response = client
+        .get("/api/v1/accounts?limit=0&sort=password&status=deleted")
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let problem = response.into_json::<Value>().unwrap();
+    let fields: Vec<&str> = problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["limit", "sort", "status"]);
+}
+
+/// Test the dapr account store against a fake sidecar.
+///
+/// # Note
+/// This will test creation, lookup, update, listing, and deletion.
+#[test]
+fn test_dapr_store() {
+    // Create client around a fake sidecar
+    let sidecar = FakeSidecar::start();
+    let client = dapr_client(&sidecar);
+
+    // Post two accounts
+    for (id, email) in [("test_1", "Test1@gmail.com"), ("test_2", "test2@gmail.com")] {
+        let account = AccountModel {
+            id: id.to_string(),
+            name: "Test".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+        };
+        let response = client
+            .post("/api/v1/accounts")
+            .header(ContentType::JSON)
+            .body(json!(&account).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    // Assert each account is stored next to its email index entry
+    assert_eq!(
+        sidecar.keys(),
+        vec![
+            "email:test1@gmail.com",
+            "email:test2@gmail.com",
+            "test_1",
+            "test_2"
+        ]
+    );
+
+    // Assert a duplicate email is rejected
+    let duplicate = AccountModel {
+        id: "test_3".to_string(),
+        name: "Test".to_string(),
+        email: "TEST2@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .header(ContentType::JSON)
+        .body(json!(&duplicate).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Get account by email, regardless of case
+    let response = client
+        .get("/api/v1/accounts/email/test1@gmail.com")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<AccountDetails>().unwrap().id, "test_1");
+
+    // Get account by id with its version
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+    // Update the email of the account at its version
+    let update = AccountModel {
+        id: "test_1".to_string(),
+        name: "Test".to_string(),
+        email: "test4@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", etag.clone()))
+        .body(json!(&update).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    // Assert a stale version is rejected
+    let response = client
+        .put("/api/v1/accounts")
+        .header(owner(&client, "test_1"))
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", etag))
+        .body(json!(&update).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    // Assert the old email was released
+    assert!(!sidecar
+        .keys()
+        .contains(&"email:test1@gmail.com".to_string()));
+    assert!(sidecar
+        .keys()
+        .contains(&"email:test4@gmail.com".to_string()));
+
+    // Page through the accounts, skipping email index entries
+    let page = client
+        .get("/api/v1/accounts?limit=1&name=Test")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_1");
+    let token = page.next_page_token.unwrap();
+    let page = client
+        .get(format!(
+            "/api/v1/accounts?limit=1&name=Test&page_token={}",
+            token
+        ))
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items[0].id, "test_2");
+    assert_eq!(page.next_page_token, None);
+
+    // Page through the accounts with the email index entries sorted first
+    let page = client
+        .get("/api/v1/accounts?limit=1&sort=-id")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_2");
+    let token = page.next_page_token.unwrap();
+    let page = client
+        .get(format!(
+            "/api/v1/accounts?limit=1&sort=-id&page_token={}",
+            token
+        ))
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_1");
+    assert_eq!(page.next_page_token, None);
+
+    // Assert accounts stored without a status are listed as active
+    sidecar.edit("test_1", |account| {
+        account.as_object_mut().unwrap().remove("status");
+    });
+    sidecar.edit("test_2", |account| account["status"] = json!("suspended"));
+    let page = client
+        .get("/api/v1/accounts?status=active")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_1");
+    assert_eq!(page.items[0].status, AccountStatus::Active);
+    let page = client
+        .get("/api/v1/accounts?status=suspended")
+        .dispatch()
+        .into_json::<AccountPageDetails>()
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, "test_2");
+
+    // Delete the accounts
+    for id in ["test_1", "test_2"] {
+        let response = client
+            .delete(format!("/api/v1/accounts/id/{}", id))
+            .header(owner(&client, id))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+    }
+
+    // Assert nothing is left behind
+    assert!(sidecar.keys().is_empty());
+}
+
+/// Test that sidecar failures are mapped to problem responses.
+#[test]
+fn test_dapr_store_faults() {
+    // Create client around a fake sidecar
+    let sidecar = FakeSidecar::start();
+    let client = dapr_client(&sidecar);
+
+    // Assert a failing sidecar is reported as unavailable
+    sidecar.inject(Fault::Status(500));
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    // Assert a malformed answer is reported as an internal error
+    sidecar.inject(Fault::MalformedJson);
+    let response = client.get("/api/v1/accounts").dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+
+    // Assert a slow sidecar still answers
+    sidecar.inject(Fault::Delay(std::time::Duration::from_millis(50)));
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Assert every request fails until faults are cleared
+    sidecar.inject_always(Fault::Status(500));
+    let before = sidecar.request_count();
+    for _ in 0..3 {
+        let response = client
+            .get("/api/v1/accounts/email/test1@gmail.com")
+            .dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+    }
+    assert_eq!(sidecar.request_count(), before + 3);
+    sidecar.clear_faults();
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test retries, timeouts and the circuit breaker of the dapr store.
+#[test]
+fn test_dapr_store_resilience() {
+    // Create a dao with short timeouts around a fake sidecar
+    let sidecar = FakeSidecar::start();
+    let dao = sidecar.dao_with_config(DaprClientConfig {
+        request_timeout_ms: 200,
+        max_retries: 1,
+        retry_backoff_ms: 10,
+        breaker_threshold: 3,
+        breaker_cooldown_ms: 200,
+        ..DaprClientConfig::default()
+    });
+    let get = || block_on(dao.get_account_by_id("test_1".to_string()));
+
+    // Assert an unavailable sidecar is retried
+    let before = sidecar.request_count();
+    sidecar.inject(Fault::Status(503));
+    assert!(matches!(get(), Err(AccountError::NotFound)));
+    assert_eq!(sidecar.request_count(), before + 2);
+
+    // Assert a slow sidecar times out and is retried
+    let before = sidecar.request_count();
+    sidecar.inject(Fault::Delay(std::time::Duration::from_secs(1)));
+    assert!(matches!(get(), Err(AccountError::NotFound)));
+    assert_eq!(sidecar.request_count(), before + 2);
+
+    // Assert transactions are not retried
+    sidecar.inject(Fault::Status(503));
+    let account = account("test_1", "Test", "test1@gmail.com");
+    assert!(matches!(
+        block_on(dao.create_account(account.clone())),
+        Err(AccountError::StoreUnavailable(_))
+    ));
+    assert!(sidecar.keys().is_empty());
+
+    // Assert a transaction committed before its reply was lost is not a conflict
+    sidecar.inject(Fault::LostReply(503));
+    let created = AccountEntity {
+        id: "test_2".to_string(),
+        email: "test2@gmail.com".to_string(),
+        instance_id: "created".to_string(),
+        ..account
+    };
+    assert!(block_on(dao.create_account(created.clone())).is_ok());
+    let stored = block_on(dao.get_account_by_id("test_2".to_string())).unwrap();
+    assert_eq!(stored.instance_id, "created");
+
+    // Assert another account with the same id still is a conflict
+    sidecar.inject(Fault::LostReply(503));
+    let other = AccountEntity {
+        instance_id: "other".to_string(),
+        ..created
+    };
+    assert!(matches!(
+        block_on(dao.create_account(other)),
+        Err(AccountError::Conflict(message)) if message == "id already in use"
+    ));
+
+    // Assert a delete committed before its reply was lost succeeds, once
+    sidecar.inject(Fault::LostReply(503));
+    assert!(block_on(dao.delete_account("test_2".to_string(), None)).is_ok());
+    assert!(sidecar.keys().is_empty());
+    assert!(matches!(
+        block_on(dao.delete_account("test_2".to_string(), None)),
+        Err(AccountError::NotFound)
+    ));
+
+    // Assert the circuit opens after repeated failures and fails fast
+    sidecar.inject_always(Fault::Status(503));
+    for _ in 0..2 {
+        assert!(matches!(get(), Err(AccountError::StoreUnavailable(_))));
+    }
+    let before = sidecar.request_count();
+    assert!(matches!(get(), Err(AccountError::StoreUnavailable(_))));
+    assert_eq!(sidecar.request_count(), before);
+
+    // Assert a failed probe after the cooldown opens the circuit again
+    std::thread::sleep(std::time::Duration::from_millis(250));
+    let before = sidecar.request_count();
+    assert!(matches!(get(), Err(AccountError::StoreUnavailable(_))));
+    assert_eq!(sidecar.request_count(), before + 1);
+    assert!(matches!(get(), Err(AccountError::StoreUnavailable(_))));
+    assert_eq!(sidecar.request_count(), before + 1);
+
+    // Assert only one probe is let through while it is in flight
+    sidecar.clear_faults();
+    sidecar.inject(Fault::Delay(std::time::Duration::from_millis(100)));
+    std::thread::sleep(std::time::Duration::from_millis(250));
+    let before = sidecar.request_count();
+    let (probe, other) = block_on(rocket::futures::future::join(
+        dao.get_account_by_id("test_1".to_string()),
+        dao.get_account_by_id("test_1".to_string()),
+    ));
+    assert!(matches!(probe, Err(AccountError::NotFound)));
+    assert!(matches!(other, Err(AccountError::StoreUnavailable(_))));
+    assert_eq!(sidecar.request_count(), before + 1);
+
+    // Assert the circuit closes once the probe succeeds
+    assert!(matches!(get(), Err(AccountError::NotFound)));
+    assert!(matches!(get(), Err(AccountError::NotFound)));
+}
+
+/// Test that password hashing beyond the queue limit is rejected.
+#[rocket::async_test]
+async fn test_password_hashing_queue_full() {
+    // Create a client that hashes one password at a time, without a queue
+    let hasher = PasswordHasher::new(&PasswordHashingConfig {
+        max_concurrency: 1,
+        max_queue: 0,
+        ..Default::default()
+    })
+    .unwrap();
+    let dao = InMemoryAccountDao::new().with_password_hasher(hasher);
+    let rocket = server().manage(ServiceProvider::new(DaprAccountService::new(Box::new(dao))));
+    let client = rocket::local::asynchronous::Client::tracked(rocket)
+        .await
+        .expect("valid rocket instance");
+
+    // Post two accounts at once
+    let accounts: Vec<AccountModel> = (1..=2)
+        .map(|i| AccountModel {
+            id: format!("test_{}", i),
+            name: "Test".to_string(),
+            email: format!("test{}@gmail.com", i),
+            password: "password".to_string(),
+        })
+        .collect();
+    let (first, second) = rocket::futures::future::join(
+        client
+            .post("/api/v1/accounts")
+            .json(&accounts[0])
+            .dispatch(),
+        client
+            .post("/api/v1/accounts")
+            .json(&accounts[1])
+            .dispatch(),
+    )
+    .await;
+
+    // Assert the second one is asked to retry later
+    assert_eq!(first.status(), Status::Created);
+    assert_eq!(second.status(), Status::TooManyRequests);
+    assert_eq!(second.headers().get_one("Retry-After"), Some("1"));
+    let problem = second.into_json::<Value>().await.unwrap();
+    assert_eq!(problem["type"], "urn:account-api:problem:too-many-requests");
+
+    // Assert the slot is free again afterwards
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&accounts[1])
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+/// Test logins with unknown emails verify a password like logins with known emails.
+#[rocket::async_test]
+async fn test_unknown_email_verifies_dummy_hash() {
+    // Create a client that verifies one password at a time, without a queue
+    let hasher = PasswordHasher::new(&PasswordHashingConfig {
+        max_concurrency: 1,
+        max_queue: 0,
+        ..Default::default()
+    })
+    .unwrap();
+    let dao = InMemoryAccountDao::new().with_password_hasher(hasher);
+    let rocket = server().manage(ServiceProvider::new(DaprAccountService::new(Box::new(dao))));
+    let client = rocket::local::asynchronous::Client::tracked(rocket)
+        .await
+        .expect("valid rocket instance");
+
+    // Log in with two unknown emails at once
+    let login = |email: &str| {
+        client
+            .post("/api/v1/accounts/validate")
+            .json(&json!({ "email": email, "password": "password" }))
+            .dispatch()
+    };
+    let (first, second) =
+        rocket::futures::future::join(login("unknown1@gmail.com"), login("unknown2@gmail.com"))
+            .await;
+
+    // Assert both needed the hasher, so only one of them got it
+    let mut statuses = vec![first.status(), second.status()];
+    statuses.sort_by_key(|status| status.code);
+    assert_eq!(
+        statuses,
+        vec![Status::Unauthorized, Status::TooManyRequests]
+    );
+}
+
+/// Test enumeration safe signups answer alike for new and taken emails.
+#[test]
+fn test_enumeration_safe_signup() {
+    let mailer = Arc::new(RecordingMailer::new());
+    let provider =
+        ServiceProvider::new(DaprAccountService::new(Box::new(InMemoryAccountDao::new())))
+            .with_email_verifications(EmailVerifications::new(
+                Arc::new(InMemoryActionTokenDao::new()),
+                mailer.clone(),
+                EmailVerificationConfig::default(),
+            ))
+            .with_signups(Signups::new(
+                mailer.clone(),
+                SignupConfig {
+                    enumeration_safe: true,
+                },
+            ));
+    let client = Client::tracked(server().manage(provider)).expect("valid rocket instance");
+    let signup = |id: &str, email: &str| {
+        let response = client
+            .post("/api/v1/accounts")
+            .json(&json!({
+                "id": id,
+                "name": "Test",
+                "email": email,
+                "password": "password"
+            }))
+            .dispatch();
+        let location = response.headers().get_one("Location").map(str::to_string);
+        (response.status(), location, response.into_string())
+    };
+
+    // Assert new and taken emails get the same answer, without the account
+    let created = signup("test_1", "test1@gmail.com");
+    assert_eq!(created, (Status::Accepted, None, None));
+    assert_eq!(signup("test_2", "Test1@Gmail.com"), created);
+
+    // Assert only the new email got an account, the owner of the taken one a notice
+    let response = client.get("/api/v1/accounts/id/test_1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get("/api/v1/accounts/id/test_2").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let messages = mailer.wait_for(2);
+    let notice = messages
+        .iter()
+        .find(|message| message.subject == "Sign up attempt with your email")
+        .expect("notice to the owner");
+    assert_eq!(notice.to, "test1@gmail.com");
+
+    // Assert taken ids and invalid accounts are still rejected, they reveal no email
+    assert_eq!(signup("test_1", "test2@gmail.com").0, Status::Conflict);
+    assert_eq!(
+        signup("test_3", "not an email").0,
+        Status::UnprocessableEntity
+    );
+    assert_eq!(mailer.messages().len(), 2);
+
+    // Assert emails cannot be looked up without the admin role
+    let lookup = |email: &str, token: Option<&str>| {
+        let request = client.get(format!("/api/v1/accounts/email/{}", email));
+        match token {
+            Some(token) => request.header(bearer(token)).dispatch().status(),
+            None => request.dispatch().status(),
+        }
+    };
+    let response = client
+        .post("/api/v1/accounts/validate")
+        .json(&json!({ "email": "test1@gmail.com", "password": "password" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let token = response.into_json::<Value>().unwrap()["access_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for email in ["test1@gmail.com", "test2@gmail.com"] {
+        assert_eq!(lookup(email, None), Status::Unauthorized);
+        assert_eq!(lookup(email, Some(&token)), Status::Forbidden);
+    }
+    let list = |uri: &str, token: Option<&str>| {
+        let request = client.get(uri.to_string());
+        match token {
+            Some(token) => request.header(bearer(token)).dispatch(),
+            None => request.dispatch(),
+        }
+    };
+    for uri in [
+        "/api/v1/accounts",
+        "/api/v1/accounts?email=test1@gmail.com",
+        "/api/v1/accounts?email=test2@gmail.com",
+    ] {
+        assert_eq!(list(uri, None).status(), Status::Unauthorized);
+        assert_eq!(list(uri, Some(&token)).status(), Status::Forbidden);
+    }
+
+    // Assert admins still look up emails
+    let admin = AccountDetails {
+        id: "admin_1".to_string(),
+        roles: [default_roles(), vec![ADMIN_ROLE.to_string()]].concat(),
+        ..stub_account()
+    };
+    let tokens = &client.rocket().state::<ServiceProvider>().unwrap().tokens;
+    let admin_token = tokens.issue(&admin, "session_1").unwrap().access_token;
+    assert_eq!(lookup("Test1@Gmail.com", Some(&admin_token)), Status::Ok);
+    let response = list("/api/v1/accounts?email=test1@gmail.com", Some(&admin_token));
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_json::<Value>().unwrap()["items"][0]["id"],
+        "test_1"
+    );
+    assert_eq!(
+        lookup("test2@gmail.com", Some(&admin_token)),
+        Status::NotFound
+    );
+}
+
+/// Create a password hasher for a scheme with cheap costs.
+///
+/// # Arguments
+/// * `algorithm` - The scheme of new hashes
+///
+/// # Returns
+/// The password hasher
+fn cheap_hasher(algorithm: &str) -> PasswordHasher {
+    PasswordHasher::new(&PasswordHashingConfig {
+        algorithm: algorithm.to_string(),
+        bcrypt_cost: 4,
+        argon2_memory_kib: 64,
+        argon2_iterations: 1,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// Check that a login upgrades an outdated hash.
+///
+/// # Arguments
+/// * `legacy` - A dao hashing with bcrypt
+/// * `upgrade` - Gets a dao over the same store hashing with argon2id
+fn check_password_upgrade<D: AccountDao>(legacy: D, upgrade: impl FnOnce(D) -> D) {
+    // Create an account with a bcrypt hash
+    block_on(legacy.create_account(account("test_1", "Test", "test1@gmail.com"))).unwrap();
+    let dao = upgrade(legacy);
+    let hash = block_on(dao.get_account_by_id("test_1".to_string()))
+        .unwrap()
+        .password;
+    assert!(hash.starts_with("$2b$04$"));
+
+    // Assert a wrong password neither logs in nor upgrades the hash
+    let result = block_on(dao.validate_account("test1@gmail.com".to_string(), "wrong".to_string()));
+    assert!(matches!(result, Err(AccountError::InvalidCredentials)));
+    let stored = block_on(dao.get_account_by_id("test_1".to_string())).unwrap();
+    assert_eq!(stored.password, hash);
+
+    // Assert a login rehashes the password with argon2id
+    let validated =
+        block_on(dao.validate_account("test1@gmail.com".to_string(), "password".to_string()))
+            .unwrap();
+    let stored = block_on(dao.get_account_by_id("test_1".to_string())).unwrap();
+    assert!(stored.password.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+    assert_eq!(validated.password, stored.password);
+
+    // Assert the new hash logs in without another upgrade
+    block_on(dao.validate_account("test1@gmail.com".to_string(), "password".to_string())).unwrap();
+    let again = block_on(dao.get_account_by_id("test_1".to_string())).unwrap();
+    assert_eq!(again.password, stored.password);
+    assert_eq!(again.etag, stored.etag);
+}
+
+/// Test that logins upgrade outdated password hashes in memory.
+#[test]
+fn test_password_upgrade_in_memory() {
+    check_password_upgrade(
+        InMemoryAccountDao::new().with_password_hasher(cheap_hasher("bcrypt")),
+        |dao| dao.with_password_hasher(cheap_hasher("argon2id")),
+    );
+}
+
+/// Test that logins upgrade outdated password hashes in the dapr store.
+#[test]
+fn test_password_upgrade_dapr() {
+    let sidecar = FakeSidecar::start();
+    check_password_upgrade(
+        sidecar.dao().with_password_hasher(cheap_hasher("bcrypt")),
+        |_| sidecar.dao().with_password_hasher(cheap_hasher("argon2id")),
+    );
+}
+
+/// Benchmark login throughput under load.
+///
+/// # Note
+/// Run with `cargo test --release -- --ignored bench_login_throughput --nocapture`.
+#[rocket::async_test]
+#[ignore]
+async fn bench_login_throughput() {
+    // Create client
+    let figment = Config::figment().merge(("account_store", "memory"));
+    let client = rocket::local::asynchronous::Client::tracked(rocket().configure(figment))
+        .await
+        .expect("valid rocket instance");
+
+    // Post the account to log in to
+    let account = AccountModel {
+        id: "bench".to_string(),
+        name: "Bench".to_string(),
+        email: "bench@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let response = client
+        .post("/api/v1/accounts")
+        .json(&account)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    // Log in many times at once
+    let logins = 64;
+    let credentials = CredentialsModel {
+        email: "bench@gmail.com".to_string(),
+        password: "password".to_string(),
+    };
+    let start = std::time::Instant::now();
+    let burst = rocket::futures::future::join_all((0..logins).map(|_| {
+        client
+            .post("/api/v1/accounts/validate")
+            .json(&credentials)
+            .dispatch()
+    }));
+
+    // Time a request that does not hash while the logins are running
+    let probe = async {
+        let start = std::time::Instant::now();
+        let response = client.get("/api/v1/accounts/id/bench").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        start.elapsed()
+    };
+    let (responses, probe_latency) = rocket::futures::future::join(burst, probe).await;
+    let elapsed = start.elapsed();
+
+    // Report the throughput
+    let succeeded = responses
+        .iter()
+        .filter(|response| response.status() == Status::Ok)
+        .count();
+    let rejected = responses
+        .iter()
+        .filter(|response| response.status() == Status::TooManyRequests)
+        .count();
+    println!(
+        "{} logins in {:?}: {:.1} logins/s, {} ok, {} rejected, lookup during the burst took {:?}",
+        logins,
+        elapsed,
+        logins as f64 / elapsed.as_secs_f64(),
+        succeeded,
+        rejected,
+        probe_latency
+    );
+    assert_eq!(succeeded + rejected, logins);
+    assert!(probe_latency < elapsed / 4);
+}
+
+/// The account dao conformance suite run against the in-memory dao.
+mod in_memory_dao_conformance {
+    use crate::data::InMemoryAccountDao;
+    use crate::test_support::account_dao_conformance_tests;
+
+    account_dao_conformance_tests!(|| ((), InMemoryAccountDao::new()));
+}
+
+/// The account dao conformance suite run against the dapr dao.
+mod dapr_dao_conformance {
+    use crate::test_support::{account_dao_conformance_tests, FakeSidecar};
+
+    account_dao_conformance_tests!(|| {
+        let sidecar = FakeSidecar::start();
+        let dao = sidecar.dao();
+        (sidecar, dao)
+    });
 }
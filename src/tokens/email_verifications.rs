@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use super::opaque_token::{generate_token, hash_token};
+use super::token_issuer::unix_time;
+use crate::data::{normalize_email, ActionPurpose, ActionTokenDao, ActionTokenEntity};
+use crate::errors::{AccountError, AccountResult};
+use crate::mail::{send_in_background, MailMessage, Mailer};
+use crate::services::{
+    AccountDetails, AccountService, EmailVerificationModel, Validate, VerificationRequestModel,
+};
+use rocket::serde::Deserialize;
+
+/// The placeholder of the token in the verification link.
+const TOKEN_PLACEHOLDER: &str = "{token}";
+
+/// The email verification configuration.
+///
+/// # Fields
+/// * `required` - Reject logins of accounts with an unverified email
+/// * `token_ttl_secs` - The lifetime of verification tokens
+/// * `link` - The verification page of the frontend, with `{token}` where the token goes
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct EmailVerificationConfig {
+    pub required: bool,
+    pub token_ttl_secs: u64,
+    pub link: String,
+}
+
+/// The default email verification configuration.
+impl Default for EmailVerificationConfig {
+    fn default() -> Self {
+        EmailVerificationConfig {
+            required: false,
+            token_ttl_secs: 86_400,
+            link: "http://localhost:3000/verify-email?token={token}".to_string(),
+        }
+    }
+}
+
+/// The email verifications.
+///
+/// New accounts and accounts with a changed email get a single-use
+/// token mailed to the email. Using the token proves access to the
+/// email, if the account still has it. Only a hash of the token is
+/// stored.
+///
+/// # Fields
+/// * `dao` - The action token data access object
+/// * `mailer` - The mailer delivering verification links
+/// * `config` - The policy, token lifetime and verification link
+///
+/// # Methods
+/// * `new` - Creates new email verifications
+/// * `check` - Checks an account may log in under the verification policy
+/// * `start` - Mails a verification link to an account
+/// * `restart` - Mails a verification link if the email of an account changed
+/// * `send` - Stores a verification token for an account and mails it
+/// * `request` - Mails a new verification link to the owner of an email
+/// * `verify` - Verifies an email with a verification token
+pub struct EmailVerifications {
+    dao: Arc<dyn ActionTokenDao>,
+    mailer: Arc<dyn Mailer>,
+    config: EmailVerificationConfig,
+}
+
+/// The email verifications implementation.
+impl EmailVerifications {
+    /// Creates new email verifications.
+    ///
+    /// # Arguments
+    /// * `dao` - The action token data access object
+    /// * `mailer` - The mailer delivering verification links
+    /// * `config` - The policy, token lifetime and verification link
+    ///
+    /// # Returns
+    /// The new email verifications
+    pub fn new(
+        dao: Arc<dyn ActionTokenDao>,
+        mailer: Arc<dyn Mailer>,
+        config: EmailVerificationConfig,
+    ) -> Self {
+        EmailVerifications {
+            dao,
+            mailer,
+            config,
+        }
+    }
+
+    /// Checks an account may log in under the verification policy.
+    ///
+    /// # Arguments
+    /// * `account` - The account logging in
+    ///
+    /// # Returns
+    /// Nothing, or `EmailNotVerified` if verification is required and missing
+    pub fn check(&self, account: &AccountDetails) -> AccountResult<()> {
+        if self.config.required && !account.email_verified {
+            return Err(AccountError::EmailNotVerified);
+        }
+        Ok(())
+    }
+
+    /// Mails a verification link to an account.
+    ///
+    /// The account was already stored, so failures are only logged.
+    /// A new link can be requested later.
+    ///
+    /// # Arguments
+    /// * `account` - The account to verify the email of
+    pub async fn start(&self, account: &AccountDetails) {
+        if let Err(e) = self.send(account).await {
+            error!("Email verification could not be started: {}", e);
+        }
+    }
+
+    /// Mails a verification link if the email of an account changed.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `previous` - The account before it was updated
+    pub async fn restart(&self, service: &dyn AccountService, previous: &AccountDetails) {
+        match service.get_account_by_id(previous.id.clone()).await {
+            Ok(account) if normalize_email(&account.email) != normalize_email(&previous.email) => {
+                self.start(&account).await
+            }
+            Ok(_) | Err(AccountError::NotFound) => {}
+            Err(e) => error!("Email verification could not be started: {}", e),
+        }
+    }
+
+    /// Stores a verification token for an account and mails it.
+    ///
+    /// # Arguments
+    /// * `account` - The account to verify the email of
+    ///
+    /// # Returns
+    /// Nothing once the token is stored, the mail is sent in the background
+    async fn send(&self, account: &AccountDetails) -> AccountResult<()> {
+        // Store the hash of a new token, bound to the current email
+        let token = generate_token()?;
+        self.dao
+            .create_action_token(ActionTokenEntity {
+                id: hash_token(&token),
+                purpose: ActionPurpose::EmailVerification,
+                account_id: account.id.clone(),
+                email: Some(account.email.clone()),
+                expires_at: unix_time()? + self.config.token_ttl_secs,
+                etag: None,
+            })
+            .await?;
+
+        // Mail the link without holding up the response
+        let message = MailMessage {
+            to: account.email.clone(),
+            subject: "Verify your email".to_string(),
+            body: format!(
+                "Please confirm this email belongs to your account.\n\n\
+                 Follow this link within {} hours to verify it:\n{}\n\n\
+                 If you did not sign up, ignore this mail.",
+                self.config.token_ttl_secs / 3600,
+                self.config.link.replace(TOKEN_PLACEHOLDER, &token)
+            ),
+        };
+        send_in_background(self.mailer.clone(), message);
+        Ok(())
+    }
+
+    /// Mails a new verification link to the owner of an email.
+    ///
+    /// Callers cannot tell if the email belongs to an account, or if it
+    /// was verified already, so failures after validation are only logged.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `request` - The email of the account
+    ///
+    /// # Returns
+    /// Nothing, or `Validation` if the email is malformed
+    pub async fn request(
+        &self,
+        service: &dyn AccountService,
+        request: VerificationRequestModel,
+    ) -> AccountResult<()> {
+        request.validate()?;
+
+        // Unknown and verified emails get no mail
+        match service.get_account_by_email(request.email).await {
+            Ok(account) if !account.email_verified => self.start(&account).await,
+            Ok(_) | Err(AccountError::NotFound) => {}
+            Err(e) => error!("Email verification could not be started: {}", e),
+        }
+        Ok(())
+    }
+
+    /// Verifies an email with a verification token.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `verification` - The verification token
+    ///
+    /// # Returns
+    /// The id of the account, or `InvalidToken` if the token is unknown,
+    /// expired, already used or the account has another email by now
+    pub async fn verify(
+        &self,
+        service: &dyn AccountService,
+        verification: EmailVerificationModel,
+    ) -> AccountResult<String> {
+        verification.validate()?;
+
+        // Use up the token, only one verification can get it
+        let token = match self
+            .dao
+            .consume_action_token(hash_token(&verification.token))
+            .await
+        {
+            Ok(token) => token,
+            Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+            Err(e) => return Err(e),
+        };
+        let email = match token.email {
+            Some(email) if token.purpose == ActionPurpose::EmailVerification => email,
+            _ => return Err(AccountError::InvalidToken),
+        };
+        if token.expires_at <= unix_time()? {
+            return Err(AccountError::InvalidToken);
+        }
+
+        // Deleted accounts and changed emails cannot be verified
+        match service.verify_email(token.account_id.clone(), email).await {
+            Ok(()) => Ok(token.account_id),
+            Err(AccountError::NotFound) | Err(AccountError::PreconditionFailed) => {
+                Err(AccountError::InvalidToken)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
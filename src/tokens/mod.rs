@@ -0,0 +1,17 @@
+// Exports the access token modules
+mod email_verifications;
+mod opaque_token;
+mod password_resets;
+mod refresh_tokens;
+mod secret_cipher;
+mod signing_key;
+mod token_issuer;
+pub mod totp;
+mod two_factor;
+
+// Public exports
+pub use email_verifications::{EmailVerificationConfig, EmailVerifications};
+pub use password_resets::{PasswordResetConfig, PasswordResets};
+pub use refresh_tokens::{RefreshTokenModel, RefreshTokens};
+pub use token_issuer::{LoginDetails, TokenConfig, TokenIssuer};
+pub use two_factor::{MfaConfig, TwoFactor};
@@ -0,0 +1,32 @@
+use crate::errors::{AccountError, AccountResult};
+use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// The random bytes of an opaque token.
+const OPAQUE_TOKEN_BYTES: usize = 32;
+
+/// Generates an opaque token.
+///
+/// # Returns
+/// The url safe random token, or an internal error if no randomness is available
+pub fn generate_token() -> AccountResult<String> {
+    let mut bytes = [0u8; OPAQUE_TOKEN_BYTES];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| AccountError::Internal("token generation failed".to_string()))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Hashes an opaque token for storage.
+///
+/// # Arguments
+/// * `token` - The opaque token
+///
+/// # Returns
+/// The url safe SHA-256 hash of the token
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, token.as_bytes()).as_ref())
+}
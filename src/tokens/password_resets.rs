@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use super::opaque_token::{generate_token, hash_token};
+use super::token_issuer::unix_time;
+use crate::data::{ActionPurpose, ActionTokenDao, ActionTokenEntity};
+use crate::errors::{AccountError, AccountResult};
+use crate::mail::{send_in_background, MailMessage, Mailer};
+use crate::services::{AccountService, ForgotPasswordModel, PasswordResetModel, Validate};
+use rocket::serde::Deserialize;
+
+/// The placeholder of the token in the reset link.
+const TOKEN_PLACEHOLDER: &str = "{token}";
+
+/// The password reset configuration.
+///
+/// # Fields
+/// * `token_ttl_secs` - The lifetime of password reset tokens
+/// * `link` - The reset page of the frontend, with `{token}` where the token goes
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct PasswordResetConfig {
+    pub token_ttl_secs: u64,
+    pub link: String,
+}
+
+/// The default password reset configuration.
+impl Default for PasswordResetConfig {
+    fn default() -> Self {
+        PasswordResetConfig {
+            token_ttl_secs: 3600,
+            link: "http://localhost:3000/reset-password?token={token}".to_string(),
+        }
+    }
+}
+
+/// The password resets.
+///
+/// A forgotten password is replaced with a single-use token that is
+/// mailed to the account. Only a hash of the token is stored, and
+/// the token is removed when it is used, whether the reset succeeds
+/// or not.
+///
+/// # Fields
+/// * `dao` - The action token data access object
+/// * `mailer` - The mailer delivering reset links
+/// * `config` - The token lifetime and reset link
+///
+/// # Methods
+/// * `new` - Creates new password resets
+/// * `request` - Mails a reset link to the owner of an email
+/// * `start` - Stores a reset token for the owner of an email and mails it
+/// * `reset` - Sets a new password with a reset token
+pub struct PasswordResets {
+    dao: Arc<dyn ActionTokenDao>,
+    mailer: Arc<dyn Mailer>,
+    config: PasswordResetConfig,
+}
+
+/// The password resets implementation.
+impl PasswordResets {
+    /// Creates new password resets.
+    ///
+    /// # Arguments
+    /// * `dao` - The action token data access object
+    /// * `mailer` - The mailer delivering reset links
+    /// * `config` - The token lifetime and reset link
+    ///
+    /// # Returns
+    /// The new password resets
+    pub fn new(
+        dao: Arc<dyn ActionTokenDao>,
+        mailer: Arc<dyn Mailer>,
+        config: PasswordResetConfig,
+    ) -> Self {
+        PasswordResets {
+            dao,
+            mailer,
+            config,
+        }
+    }
+
+    /// Mails a reset link to the owner of an email.
+    ///
+    /// Callers cannot tell if the email belongs to an account, so
+    /// unknown emails and failures after validation are only logged.
+    /// The mail is sent in the background.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `forgot` - The email of the account
+    ///
+    /// # Returns
+    /// Nothing, or `Validation` if the email is malformed
+    pub async fn request(
+        &self,
+        service: &dyn AccountService,
+        forgot: ForgotPasswordModel,
+    ) -> AccountResult<()> {
+        forgot.validate()?;
+        if let Err(e) = self.start(service, forgot.email).await {
+            error!("Password reset could not be started: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Stores a reset token for the owner of an email and mails it.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `email` - The email of the account
+    ///
+    /// # Returns
+    /// Nothing, also if no account has the email
+    async fn start(&self, service: &dyn AccountService, email: String) -> AccountResult<()> {
+        // Unknown emails and suspended accounts get no mail
+        let account = match service.get_account_by_email(email).await {
+            Ok(account) => account,
+            Err(AccountError::NotFound) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if account.check_active().is_err() {
+            return Ok(());
+        }
+
+        // Store the hash of a new token
+        let token = generate_token()?;
+        self.dao
+            .create_action_token(ActionTokenEntity {
+                id: hash_token(&token),
+                purpose: ActionPurpose::PasswordReset,
+                account_id: account.id,
+                email: None,
+                expires_at: unix_time()? + self.config.token_ttl_secs,
+                etag: None,
+            })
+            .await?;
+
+        // Mail the link without holding up the response
+        let message = MailMessage {
+            to: account.email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Someone asked to reset the password of your account.\n\n\
+                 Follow this link within {} minutes to choose a new password:\n{}\n\n\
+                 If it was not you, ignore this mail and your password stays the same.",
+                self.config.token_ttl_secs / 60,
+                self.config.link.replace(TOKEN_PLACEHOLDER, &token)
+            ),
+        };
+        send_in_background(self.mailer.clone(), message);
+        Ok(())
+    }
+
+    /// Sets a new password with a reset token.
+    ///
+    /// The new password is validated before the token is used, so a
+    /// password breaking the policy does not use up the token.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `reset` - The reset token and the new password
+    ///
+    /// # Returns
+    /// The id of the account, `Validation` if the new password is invalid,
+    /// or `InvalidToken` if the token is unknown, expired or already used
+    pub async fn reset(
+        &self,
+        service: &dyn AccountService,
+        reset: PasswordResetModel,
+    ) -> AccountResult<String> {
+        reset.validate()?;
+
+        // Use up the token, only one reset can get it
+        let token = match self
+            .dao
+            .consume_action_token(hash_token(&reset.token))
+            .await
+        {
+            Ok(token) => token,
+            Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+            Err(e) => return Err(e),
+        };
+        if token.purpose != ActionPurpose::PasswordReset || token.expires_at <= unix_time()? {
+            return Err(AccountError::InvalidToken);
+        }
+
+        // Deleted accounts cannot be reset, suspended ones are refused
+        let account = match service.get_account_by_id(token.account_id).await {
+            Ok(account) => account,
+            Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+            Err(e) => return Err(e),
+        };
+        account.check_active()?;
+        match service.reset_password(account.id.clone(), reset).await {
+            Ok(()) => Ok(account.id),
+            Err(AccountError::NotFound) => Err(AccountError::InvalidToken),
+            Err(e) => Err(e),
+        }
+    }
+}
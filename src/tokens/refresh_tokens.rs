@@ -0,0 +1,323 @@
+use super::opaque_token::{generate_token, hash_token};
+use super::token_issuer::unix_time;
+use crate::data::{RefreshFamilyEntity, RefreshTokenDao, RefreshTokenEntity};
+use crate::errors::{AccountError, AccountResult};
+use crate::services::AccountDetails;
+use rocket::serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A refresh token, as sent by clients.
+///
+/// # Fields
+/// * `refresh_token` - The opaque refresh token
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct RefreshTokenModel {
+    pub refresh_token: String,
+}
+
+/// An issued refresh token.
+///
+/// # Fields
+/// * `account_id` - The account the token belongs to
+/// * `account_instance_id` - The instance id of the account the token was issued to
+/// * `session_id` - The session the token belongs to
+/// * `refresh_token` - The opaque refresh token
+#[derive(Clone, Debug)]
+pub struct IssuedToken {
+    pub account_id: String,
+    pub account_instance_id: String,
+    pub session_id: String,
+    pub refresh_token: String,
+}
+
+/// The details of a session, as returned to clients.
+///
+/// # Fields
+/// * `id` - The session id
+/// * `created_at` - The login time, in seconds since the epoch
+/// * `last_used_at` - The time of the last refresh, in seconds since the epoch
+/// * `expires_at` - The time the session ends unless refreshed, in seconds since the epoch
+/// * `user_agent` - The user agent of the login, if sent
+/// * `ip` - The client ip address of the login, if known
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionDetails {
+    pub id: String,
+    pub created_at: u64,
+    pub last_used_at: u64,
+    pub expires_at: u64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// The session details implementation.
+impl SessionDetails {
+    /// Creates session details from a refresh token family.
+    ///
+    /// # Arguments
+    /// * `family` - The refresh token family of the session
+    ///
+    /// # Returns
+    /// The session details
+    fn from_family(family: RefreshFamilyEntity) -> Self {
+        SessionDetails {
+            id: family.id,
+            created_at: family.created_at,
+            last_used_at: family.last_used_at,
+            expires_at: family.expires_at,
+            user_agent: family.user_agent,
+            ip: family.ip,
+        }
+    }
+}
+
+/// The refresh tokens.
+///
+/// Refresh tokens are opaque random values, only their hashes are
+/// stored. Every exchange consumes the token and hands out a new one
+/// of the same family. A consumed token that is presented again was
+/// leaked, so its whole family is revoked. Each family is the session
+/// of one login, which its account can list and revoke.
+///
+/// # Fields
+/// * `dao` - The refresh token data access object
+/// * `ttl_secs` - The lifetime of refresh tokens
+///
+/// # Methods
+/// * `new` - Creates new refresh tokens
+/// * `generate` - Generates a token and its stored entity
+/// * `issue` - Issues a refresh token starting a new family
+/// * `rotate` - Exchanges a refresh token for its successor
+/// * `revoke` - Revokes the family of a refresh token
+/// * `sessions` - Gets the live sessions of an account
+/// * `revoke_session` - Revokes one session of an account
+/// * `revoke_sessions` - Revokes every session of an account but one
+pub struct RefreshTokens {
+    dao: Box<dyn RefreshTokenDao>,
+    ttl_secs: u64,
+}
+
+/// The refresh tokens implementation.
+impl RefreshTokens {
+    /// Creates new refresh tokens.
+    ///
+    /// # Arguments
+    /// * `dao` - The refresh token data access object
+    /// * `ttl_secs` - The lifetime of refresh tokens
+    ///
+    /// # Returns
+    /// The new refresh tokens
+    pub fn new(dao: Box<dyn RefreshTokenDao>, ttl_secs: u64) -> Self {
+        RefreshTokens { dao, ttl_secs }
+    }
+
+    /// Generates a token and its stored entity.
+    ///
+    /// # Arguments
+    /// * `family_id` - The family of the token
+    /// * `account_id` - The account the token belongs to
+    ///
+    /// # Returns
+    /// The token and its entity, or an internal error if no randomness is available
+    fn generate(
+        &self,
+        family_id: &str,
+        account_id: &str,
+    ) -> AccountResult<(String, RefreshTokenEntity)> {
+        let token = generate_token()?;
+        let entity = RefreshTokenEntity {
+            id: hash_token(&token),
+            family_id: family_id.to_string(),
+            account_id: account_id.to_string(),
+            expires_at: unix_time()? + self.ttl_secs,
+            consumed: false,
+            etag: None,
+        };
+        Ok((token, entity))
+    }
+
+    /// Issues a refresh token starting a new family.
+    ///
+    /// # Arguments
+    /// * `account` - The account that logged in
+    /// * `user_agent` - The user agent of the login, if sent
+    /// * `ip` - The client ip address of the login, if known
+    ///
+    /// # Returns
+    /// The opaque refresh token and its session
+    pub async fn issue(
+        &self,
+        account: &AccountDetails,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    ) -> AccountResult<IssuedToken> {
+        let family_id = Uuid::new_v4().to_string();
+        let (token, entity) = self.generate(&family_id, &account.id)?;
+        let now = unix_time()?;
+        let family = RefreshFamilyEntity {
+            id: family_id.clone(),
+            account_id: account.id.clone(),
+            account_instance_id: account.instance_id.clone(),
+            created_at: now,
+            last_used_at: now,
+            expires_at: entity.expires_at,
+            user_agent,
+            ip,
+            revoked: false,
+            etag: None,
+        };
+        self.dao.create_refresh_family(family, entity).await?;
+        Ok(IssuedToken {
+            account_id: account.id.clone(),
+            account_instance_id: account.instance_id.clone(),
+            session_id: family_id,
+            refresh_token: token,
+        })
+    }
+
+    /// Exchanges a refresh token for its successor.
+    ///
+    /// # Arguments
+    /// * `token` - The refresh token to exchange
+    ///
+    /// # Returns
+    /// The account and the new refresh token, or `InvalidToken` if the
+    /// token is unknown, expired, revoked or was already exchanged
+    pub async fn rotate(&self, token: &str) -> AccountResult<IssuedToken> {
+        // Unknown tokens are rejected
+        let current = match self.dao.get_refresh_token(hash_token(token)).await {
+            Ok(current) => current,
+            Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+            Err(e) => return Err(e),
+        };
+        let family = match self.dao.get_refresh_family(current.family_id.clone()).await {
+            Ok(family) => family,
+            Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+            Err(e) => return Err(e),
+        };
+        let now = unix_time()?;
+        if family.revoked || current.expires_at <= now {
+            return Err(AccountError::InvalidToken);
+        }
+
+        // A consumed token was leaked, revoke every token of its family
+        if current.consumed {
+            warn!(
+                "Refresh token reuse detected for account {}, revoking its family",
+                current.account_id
+            );
+            self.dao.revoke_refresh_family(family.id).await?;
+            return Err(AccountError::InvalidToken);
+        }
+
+        // Consume the token, the loser of a concurrent exchange is reusing it
+        let (next_token, next) = self.generate(&family.id, &current.account_id)?;
+        let account_id = current.account_id.clone();
+        let account_instance_id = family.account_instance_id.clone();
+        let family_id = family.id.clone();
+        let family = RefreshFamilyEntity {
+            last_used_at: now,
+            expires_at: next.expires_at,
+            ..family
+        };
+        match self.dao.rotate_refresh_token(current, family, next).await {
+            Ok(()) => Ok(IssuedToken {
+                account_id,
+                account_instance_id,
+                session_id: family_id,
+                refresh_token: next_token,
+            }),
+            Err(AccountError::PreconditionFailed) => {
+                self.dao.revoke_refresh_family(family_id).await?;
+                Err(AccountError::InvalidToken)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Revokes the family of a refresh token.
+    ///
+    /// Unknown tokens are ignored, so logging out twice succeeds.
+    ///
+    /// # Arguments
+    /// * `token` - The refresh token to revoke
+    ///
+    /// # Returns
+    /// Nothing once the family is revoked
+    pub async fn revoke(&self, token: &str) -> AccountResult<()> {
+        let current = match self.dao.get_refresh_token(hash_token(token)).await {
+            Ok(current) => current,
+            Err(AccountError::NotFound) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match self.dao.revoke_refresh_family(current.family_id).await {
+            Err(AccountError::NotFound) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Gets the live sessions of an account.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    ///
+    /// # Returns
+    /// The unrevoked, unexpired sessions, most recently used first
+    pub async fn sessions(&self, account_id: &str) -> AccountResult<Vec<SessionDetails>> {
+        let now = unix_time()?;
+        let mut sessions: Vec<SessionDetails> = self
+            .dao
+            .get_refresh_families(account_id.to_string())
+            .await?
+            .into_iter()
+            .filter(|family| !family.revoked && family.expires_at > now)
+            .map(SessionDetails::from_family)
+            .collect();
+        sessions.sort_by(|a, b| {
+            b.last_used_at
+                .cmp(&a.last_used_at)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        Ok(sessions)
+    }
+
+    /// Revokes one session of an account.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    /// * `session_id` - The id of the session
+    ///
+    /// # Returns
+    /// Nothing once the session is revoked, `NotFound` if the account has no such session
+    pub async fn revoke_session(&self, account_id: &str, session_id: &str) -> AccountResult<()> {
+        let family = self.dao.get_refresh_family(session_id.to_string()).await?;
+        if family.account_id != account_id {
+            return Err(AccountError::NotFound);
+        }
+        self.dao.revoke_refresh_family(family.id).await
+    }
+
+    /// Revokes every session of an account but one.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    /// * `except` - The session to keep, usually the caller's own
+    ///
+    /// # Returns
+    /// The number of revoked sessions
+    pub async fn revoke_sessions(
+        &self,
+        account_id: &str,
+        except: Option<&str>,
+    ) -> AccountResult<usize> {
+        let mut revoked = 0;
+        for session in self.sessions(account_id).await? {
+            if Some(session.id.as_str()) != except {
+                self.dao.revoke_refresh_family(session.id).await?;
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+}
@@ -0,0 +1,129 @@
+use crate::errors::{AccountError, AccountResult};
+use base64::engine::{
+    general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// The secret cipher.
+///
+/// Encrypts secrets stored on accounts with AES-256-GCM. The id of
+/// the account is authenticated with every secret, so a secret copied
+/// to another account does not decrypt.
+///
+/// # Fields
+/// * `key` - The encryption key
+/// * `random` - The source of nonces
+///
+/// # Methods
+/// * `new` - Creates a new secret cipher from a configured key
+/// * `generate` - Creates a new secret cipher with a random key
+/// * `encrypt` - Encrypts a secret of an account
+/// * `decrypt` - Decrypts a secret of an account
+pub struct SecretCipher {
+    key: LessSafeKey,
+    random: SystemRandom,
+}
+
+/// The secret cipher implementation.
+impl SecretCipher {
+    /// Creates a new secret cipher from a configured key.
+    ///
+    /// # Arguments
+    /// * `key` - The base64 encoded 256 bit key
+    ///
+    /// # Returns
+    /// The new secret cipher, or an internal error if the key is malformed
+    pub fn new(key: &str) -> AccountResult<Self> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| AccountError::Internal(format!("mfa encryption key invalid: {}", e)))?;
+        SecretCipher::from_bytes(&key)
+    }
+
+    /// Creates a new secret cipher with a random key.
+    ///
+    /// Secrets encrypted with the key cannot be decrypted after a restart.
+    ///
+    /// # Returns
+    /// The new secret cipher, or an internal error if no randomness is available
+    pub fn generate() -> AccountResult<Self> {
+        let mut key = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| AccountError::Internal("key generation failed".to_string()))?;
+        SecretCipher::from_bytes(&key)
+    }
+
+    /// Creates a new secret cipher from raw key bytes.
+    ///
+    /// # Arguments
+    /// * `key` - The 256 bit key
+    ///
+    /// # Returns
+    /// The new secret cipher, or an internal error if the key has the wrong length
+    fn from_bytes(key: &[u8]) -> AccountResult<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| {
+            AccountError::Internal("mfa encryption key must be 32 bytes".to_string())
+        })?;
+        Ok(SecretCipher {
+            key: LessSafeKey::new(key),
+            random: SystemRandom::new(),
+        })
+    }
+
+    /// Encrypts a secret of an account.
+    ///
+    /// # Arguments
+    /// * `account_id` - The account the secret belongs to
+    /// * `secret` - The secret to encrypt
+    ///
+    /// # Returns
+    /// The url safe nonce and ciphertext, or an internal error if encryption failed
+    pub fn encrypt(&self, account_id: &str, secret: &[u8]) -> AccountResult<String> {
+        // Every secret gets a fresh random nonce, stored in front of it
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| AccountError::Internal("nonce generation failed".to_string()))?;
+        let mut sealed = secret.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(account_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| AccountError::Internal("secret encryption failed".to_string()))?;
+        Ok(URL_SAFE_NO_PAD.encode([nonce.as_slice(), &sealed].concat()))
+    }
+
+    /// Decrypts a secret of an account.
+    ///
+    /// # Arguments
+    /// * `account_id` - The account the secret belongs to
+    /// * `encrypted` - The url safe nonce and ciphertext
+    ///
+    /// # Returns
+    /// The secret, or an internal error if it was encrypted with another
+    /// key, for another account or was tampered with
+    pub fn decrypt(&self, account_id: &str, encrypted: &str) -> AccountResult<Vec<u8>> {
+        let undecryptable = || AccountError::Internal("secret cannot be decrypted".to_string());
+        let bytes = URL_SAFE_NO_PAD
+            .decode(encrypted)
+            .map_err(|_| undecryptable())?;
+        if bytes.len() < NONCE_LEN {
+            return Err(undecryptable());
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| undecryptable())?;
+        let mut sealed = sealed.to_vec();
+        let secret = self
+            .key
+            .open_in_place(nonce, Aad::from(account_id.as_bytes()), &mut sealed)
+            .map_err(|_| undecryptable())?;
+        Ok(secret.to_vec())
+    }
+}
@@ -0,0 +1,275 @@
+use crate::errors::{AccountError, AccountResult};
+use base64::engine::{
+    general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::{
+    digest::{digest, SHA256},
+    rand::SystemRandom,
+    signature::{
+        Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents, UnparsedPublicKey, ED25519,
+        RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_SHA256,
+    },
+};
+use rocket::serde::{
+    json::{json, Value},
+    Deserialize,
+};
+
+/// The signing algorithm of access tokens.
+///
+/// # Variants
+/// * `EdDsa` - Ed25519 signatures
+/// * `Rs256` - RSA PKCS#1 v1.5 signatures with SHA-256
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde")]
+pub enum TokenAlgorithm {
+    #[default]
+    #[serde(rename = "EdDSA")]
+    EdDsa,
+    #[serde(rename = "RS256")]
+    Rs256,
+}
+
+/// The token algorithm implementation.
+impl TokenAlgorithm {
+    /// Gets the name of the algorithm in JOSE headers.
+    ///
+    /// # Returns
+    /// The `alg` header value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenAlgorithm::EdDsa => "EdDSA",
+            TokenAlgorithm::Rs256 => "RS256",
+        }
+    }
+}
+
+/// The private key pair of a signing key.
+///
+/// # Variants
+/// * `Ed25519` - An Ed25519 key pair
+/// * `Rsa` - An RSA key pair
+enum PrivateKey {
+    Ed25519(Ed25519KeyPair),
+    Rsa(RsaKeyPair),
+}
+
+/// A key signing access tokens.
+///
+/// The public half is published as a JSON web key, identified by
+/// its RFC 7638 thumbprint.
+///
+/// # Fields
+/// * `algorithm` - The signing algorithm
+/// * `private_key` - The private key pair
+/// * `jwk` - The public JSON web key
+/// * `key_id` - The thumbprint of the public key
+///
+/// # Methods
+/// * `generate` - Generates an ephemeral Ed25519 key
+/// * `from_pem` - Loads a PEM encoded private key
+/// * `algorithm` - Gets the signing algorithm
+/// * `key_id` - Gets the key id
+/// * `jwk` - Gets the public JSON web key
+/// * `sign` - Signs a message
+/// * `verify` - Verifies the signature of a message
+pub struct SigningKey {
+    algorithm: TokenAlgorithm,
+    private_key: PrivateKey,
+    jwk: Value,
+    key_id: String,
+}
+
+/// The signing key implementation.
+impl SigningKey {
+    /// Generates an ephemeral Ed25519 key.
+    ///
+    /// # Returns
+    /// The new signing key, or an internal error if no randomness is available
+    pub fn generate() -> AccountResult<Self> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| AccountError::Internal("signing key generation failed".to_string()))?;
+        SigningKey::from_der(TokenAlgorithm::EdDsa, pkcs8.as_ref())
+    }
+
+    /// Loads a PEM encoded private key.
+    ///
+    /// Ed25519 keys must be PKCS#8, RSA keys PKCS#8 or PKCS#1.
+    ///
+    /// # Arguments
+    /// * `algorithm` - The signing algorithm of the key
+    /// * `pem` - The PEM encoded private key
+    ///
+    /// # Returns
+    /// The signing key, or an internal error if the key is invalid
+    pub fn from_pem(algorithm: TokenAlgorithm, pem: &str) -> AccountResult<Self> {
+        // Decode the base64 body between the armor lines
+        let body: String = pem
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+            .collect();
+        let der = STANDARD
+            .decode(body)
+            .map_err(|e| AccountError::Internal(format!("invalid signing key pem: {}", e)))?;
+
+        // PKCS#1 RSA keys have their own armor label
+        if pem.contains("BEGIN RSA PRIVATE KEY") {
+            let key_pair = RsaKeyPair::from_der(&der).map_err(invalid_key)?;
+            return SigningKey::from_key_pair(algorithm, PrivateKey::Rsa(key_pair));
+        }
+        SigningKey::from_der(algorithm, &der)
+    }
+
+    /// Loads a PKCS#8 DER encoded private key.
+    ///
+    /// # Arguments
+    /// * `algorithm` - The signing algorithm of the key
+    /// * `der` - The DER encoded private key
+    ///
+    /// # Returns
+    /// The signing key, or an internal error if the key is invalid
+    fn from_der(algorithm: TokenAlgorithm, der: &[u8]) -> AccountResult<Self> {
+        let private_key = match algorithm {
+            TokenAlgorithm::EdDsa => PrivateKey::Ed25519(
+                Ed25519KeyPair::from_pkcs8_maybe_unchecked(der).map_err(invalid_key)?,
+            ),
+            TokenAlgorithm::Rs256 => {
+                PrivateKey::Rsa(RsaKeyPair::from_pkcs8(der).map_err(invalid_key)?)
+            }
+        };
+        SigningKey::from_key_pair(algorithm, private_key)
+    }
+
+    /// Creates a signing key from a key pair.
+    ///
+    /// # Arguments
+    /// * `algorithm` - The signing algorithm of the key
+    /// * `private_key` - The key pair
+    ///
+    /// # Returns
+    /// The signing key, or an internal error if the key does not fit the algorithm
+    fn from_key_pair(algorithm: TokenAlgorithm, private_key: PrivateKey) -> AccountResult<Self> {
+        // The required members in lexicographic order, as hashed by RFC 7638
+        let members = match (&private_key, algorithm) {
+            (PrivateKey::Ed25519(key_pair), TokenAlgorithm::EdDsa) => json!({
+                "crv": "Ed25519",
+                "kty": "OKP",
+                "x": URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+            }),
+            (PrivateKey::Rsa(key_pair), TokenAlgorithm::Rs256) => {
+                let public_key: RsaPublicKeyComponents<Vec<u8>> = key_pair.public().into();
+                json!({
+                    "e": URL_SAFE_NO_PAD.encode(public_key.e),
+                    "kty": "RSA",
+                    "n": URL_SAFE_NO_PAD.encode(public_key.n),
+                })
+            }
+            _ => {
+                return Err(AccountError::Internal(format!(
+                    "signing key does not fit the {} algorithm",
+                    algorithm.as_str()
+                )))
+            }
+        };
+        let key_id =
+            URL_SAFE_NO_PAD.encode(digest(&SHA256, members.to_string().as_bytes()).as_ref());
+
+        // Publish the key with its intended use
+        let mut jwk = members;
+        jwk["use"] = json!("sig");
+        jwk["alg"] = json!(algorithm.as_str());
+        jwk["kid"] = json!(key_id);
+
+        Ok(SigningKey {
+            algorithm,
+            private_key,
+            jwk,
+            key_id,
+        })
+    }
+
+    /// Gets the signing algorithm.
+    ///
+    /// # Returns
+    /// The algorithm
+    pub fn algorithm(&self) -> TokenAlgorithm {
+        self.algorithm
+    }
+
+    /// Gets the key id.
+    ///
+    /// # Returns
+    /// The RFC 7638 thumbprint of the public key
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Gets the public JSON web key.
+    ///
+    /// # Returns
+    /// The JSON web key
+    pub fn jwk(&self) -> &Value {
+        &self.jwk
+    }
+
+    /// Signs a message.
+    ///
+    /// # Arguments
+    /// * `message` - The message to sign
+    ///
+    /// # Returns
+    /// The signature, or an internal error if signing failed
+    pub fn sign(&self, message: &[u8]) -> AccountResult<Vec<u8>> {
+        match &self.private_key {
+            PrivateKey::Ed25519(key_pair) => Ok(key_pair.sign(message).as_ref().to_vec()),
+            PrivateKey::Rsa(key_pair) => {
+                let mut signature = vec![0; key_pair.public().modulus_len()];
+                key_pair
+                    .sign(
+                        &RSA_PKCS1_SHA256,
+                        &SystemRandom::new(),
+                        message,
+                        &mut signature,
+                    )
+                    .map_err(|_| AccountError::Internal("token signing failed".to_string()))?;
+                Ok(signature)
+            }
+        }
+    }
+
+    /// Verifies the signature of a message.
+    ///
+    /// # Arguments
+    /// * `message` - The signed message
+    /// * `signature` - The signature to verify
+    ///
+    /// # Returns
+    /// True if the signature was made with this key
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match &self.private_key {
+            PrivateKey::Ed25519(key_pair) => {
+                UnparsedPublicKey::new(&ED25519, key_pair.public_key().as_ref())
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            PrivateKey::Rsa(key_pair) => {
+                UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key_pair.public().as_ref())
+                    .verify(message, signature)
+                    .is_ok()
+            }
+        }
+    }
+}
+
+/// Maps a rejected key to an internal error.
+///
+/// # Arguments
+/// * `error` - The reason the key was rejected
+///
+/// # Returns
+/// The internal error
+fn invalid_key(error: ring::error::KeyRejected) -> AccountError {
+    AccountError::Internal(format!("invalid signing key: {}", error))
+}
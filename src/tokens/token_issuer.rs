@@ -0,0 +1,330 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::signing_key::{SigningKey, TokenAlgorithm};
+use crate::errors::{AccountError, AccountResult};
+use crate::services::AccountDetails;
+use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+use rocket::serde::{
+    de::DeserializeOwned,
+    json::{json, serde_json, Value},
+    Deserialize, Serialize,
+};
+use uuid::Uuid;
+
+/// The access token configuration.
+///
+/// Without a private key an ephemeral Ed25519 key is generated on
+/// startup, so tokens do not survive a restart and every instance
+/// signs with a different key.
+///
+/// # Fields
+/// * `issuer` - The `iss` claim of issued tokens
+/// * `audience` - The `aud` claim of issued tokens
+/// * `access_token_ttl_secs` - The lifetime of access tokens
+/// * `refresh_token_ttl_secs` - The lifetime of refresh tokens, renewed on every refresh
+/// * `algorithm` - The signing algorithm, `EdDSA` or `RS256`
+/// * `private_key` - The PEM encoded signing key, if given inline
+/// * `private_key_file` - The path of the PEM encoded signing key
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct TokenConfig {
+    pub issuer: String,
+    pub audience: String,
+    pub access_token_ttl_secs: u64,
+    pub refresh_token_ttl_secs: u64,
+    pub algorithm: TokenAlgorithm,
+    pub private_key: Option<String>,
+    pub private_key_file: Option<String>,
+}
+
+/// The default access token configuration.
+impl Default for TokenConfig {
+    fn default() -> Self {
+        TokenConfig {
+            issuer: "account-api".to_string(),
+            audience: "auction-games".to_string(),
+            access_token_ttl_secs: 900,
+            refresh_token_ttl_secs: 2_592_000,
+            algorithm: TokenAlgorithm::EdDsa,
+            private_key: None,
+            private_key_file: None,
+        }
+    }
+}
+
+/// The access token configuration implementation.
+impl TokenConfig {
+    /// Checks if a signing key is configured.
+    ///
+    /// # Returns
+    /// True unless an ephemeral key is used
+    pub fn has_key(&self) -> bool {
+        self.private_key.is_some() || self.private_key_file.is_some()
+    }
+}
+
+/// The claims of an access token.
+///
+/// # Fields
+/// * `iss` - The issuer
+/// * `aud` - The audience
+/// * `sub` - The account id
+/// * `iat` - The issue time, in seconds since the epoch
+/// * `exp` - The expiry time, in seconds since the epoch
+/// * `jti` - The unique token id
+/// * `sid` - The session the token was issued to
+/// * `roles` - The roles of the account
+/// * `email_verified` - True if the account verified its email, for services gating on it
+///
+/// # Methods
+/// * `require_account` - Checks the token was issued to an account
+/// * `require_role` - Checks the account has a role
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct AccessClaims {
+    pub iss: String,
+    pub aud: String,
+    pub sub: String,
+    pub iat: u64,
+    pub exp: u64,
+    pub jti: String,
+    pub sid: String,
+    pub roles: Vec<String>,
+    pub email_verified: bool,
+}
+
+/// The access claims implementation.
+impl AccessClaims {
+    /// Checks the token was issued to an account.
+    ///
+    /// # Arguments
+    /// * `account_id` - The id of the account
+    ///
+    /// # Returns
+    /// Nothing, or `Forbidden` if the token belongs to another account
+    pub fn require_account(&self, account_id: &str) -> AccountResult<()> {
+        if self.sub == account_id {
+            Ok(())
+        } else {
+            Err(AccountError::Forbidden)
+        }
+    }
+
+    /// Checks the account has a role.
+    ///
+    /// # Arguments
+    /// * `role` - The required role
+    ///
+    /// # Returns
+    /// Nothing, or `Forbidden` if the role was not granted
+    pub fn require_role(&self, role: &str) -> AccountResult<()> {
+        if self.roles.iter().any(|granted| granted == role) {
+            Ok(())
+        } else {
+            Err(AccountError::Forbidden)
+        }
+    }
+}
+
+/// An issued access token, as returned to clients.
+///
+/// # Fields
+/// * `access_token` - The signed JWT
+/// * `token_type` - The token type, always `Bearer`
+/// * `expires_in` - The lifetime of the token in seconds
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AccessToken {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+}
+
+/// The details of a successful login or token refresh.
+///
+/// # Fields
+/// * `account` - The account that logged in
+/// * `token` - The access token of the account
+/// * `session_id` - The session of the login
+/// * `refresh_token` - The refresh token exchanging for the next access token
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct LoginDetails {
+    #[serde(flatten)]
+    pub account: AccountDetails,
+    #[serde(flatten)]
+    pub token: AccessToken,
+    pub session_id: String,
+    pub refresh_token: String,
+}
+
+/// Gets the current time.
+///
+/// # Returns
+/// The seconds since the epoch, or an internal error if the clock is before it
+pub fn unix_time() -> AccountResult<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| AccountError::Internal(e.to_string()))?
+        .as_secs())
+}
+
+/// The access token issuer.
+///
+/// Issues JWTs signed with one key and publishes that key as a
+/// JSON web key set, so other services can verify tokens offline.
+///
+/// # Fields
+/// * `config` - The access token configuration
+/// * `key` - The signing key
+///
+/// # Methods
+/// * `new` - Creates a new token issuer
+/// * `issue` - Issues an access token for an account
+/// * `verify` - Verifies an access token issued by this issuer
+/// * `jwks` - Gets the JSON web key set of the signing key
+pub struct TokenIssuer {
+    config: TokenConfig,
+    key: SigningKey,
+}
+
+/// The default token issuer signs with an ephemeral key.
+impl Default for TokenIssuer {
+    fn default() -> Self {
+        TokenIssuer::new(&TokenConfig::default()).expect("ephemeral signing key")
+    }
+}
+
+/// The token issuer implementation.
+impl TokenIssuer {
+    /// Creates a new token issuer.
+    ///
+    /// # Arguments
+    /// * `config` - The access token configuration
+    ///
+    /// # Returns
+    /// The new token issuer, or an internal error if the key cannot be loaded
+    pub fn new(config: &TokenConfig) -> AccountResult<Self> {
+        // Prefer an inline key over a key file
+        let pem = match (&config.private_key, &config.private_key_file) {
+            (Some(pem), _) => Some(pem.clone()),
+            (None, Some(path)) => Some(std::fs::read_to_string(path).map_err(|e| {
+                AccountError::Internal(format!("signing key {} unreadable: {}", path, e))
+            })?),
+            (None, None) => None,
+        };
+
+        // Only Ed25519 keys can be generated
+        let key = match (pem, config.algorithm) {
+            (Some(pem), algorithm) => SigningKey::from_pem(algorithm, &pem)?,
+            (None, TokenAlgorithm::EdDsa) => SigningKey::generate()?,
+            (None, TokenAlgorithm::Rs256) => {
+                return Err(AccountError::Internal(
+                    "RS256 access tokens need a private key".to_string(),
+                ))
+            }
+        };
+        Ok(TokenIssuer {
+            config: config.clone(),
+            key,
+        })
+    }
+
+    /// Issues an access token for an account.
+    ///
+    /// # Arguments
+    /// * `account` - The account to issue the token for
+    /// * `session_id` - The session the token is issued to
+    ///
+    /// # Returns
+    /// The access token, or an internal error if signing failed
+    pub fn issue(&self, account: &AccountDetails, session_id: &str) -> AccountResult<AccessToken> {
+        let now = unix_time()?;
+        let claims = AccessClaims {
+            iss: self.config.issuer.clone(),
+            aud: self.config.audience.clone(),
+            sub: account.id.clone(),
+            iat: now,
+            exp: now + self.config.access_token_ttl_secs,
+            jti: Uuid::new_v4().to_string(),
+            sid: session_id.to_string(),
+            roles: account.roles.clone(),
+            email_verified: account.email_verified,
+        };
+        let header = json!({
+            "alg": self.key.algorithm().as_str(),
+            "typ": "JWT",
+            "kid": self.key.key_id(),
+        });
+
+        // Sign the encoded header and claims
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(json!(claims).to_string())
+        );
+        let signature = self.key.sign(message.as_bytes())?;
+        Ok(AccessToken {
+            access_token: format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)),
+            token_type: "Bearer".to_string(),
+            expires_in: self.config.access_token_ttl_secs,
+        })
+    }
+
+    /// Verifies an access token issued by this issuer.
+    ///
+    /// # Arguments
+    /// * `token` - The signed JWT
+    ///
+    /// # Returns
+    /// The claims of the token, or `InvalidToken` if it is malformed,
+    /// signed with another key, meant for another audience or expired
+    pub fn verify(&self, token: &str) -> AccountResult<AccessClaims> {
+        let (message, signature) = token.rsplit_once('.').ok_or(AccountError::InvalidToken)?;
+        let (header, claims) = message.split_once('.').ok_or(AccountError::InvalidToken)?;
+
+        // Only tokens of the current key are accepted
+        let header: Value = decode_part(header)?;
+        if header["alg"] != self.key.algorithm().as_str() || header["kid"] != self.key.key_id() {
+            return Err(AccountError::InvalidToken);
+        }
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| AccountError::InvalidToken)?;
+        if !self.key.verify(message.as_bytes(), &signature) {
+            return Err(AccountError::InvalidToken);
+        }
+
+        // Check the claims only once the signature is known to be ours
+        let claims: AccessClaims = decode_part(claims)?;
+        if claims.iss != self.config.issuer
+            || claims.aud != self.config.audience
+            || claims.exp <= unix_time()?
+        {
+            return Err(AccountError::InvalidToken);
+        }
+        Ok(claims)
+    }
+
+    /// Gets the JSON web key set of the signing key.
+    ///
+    /// # Returns
+    /// The key set, holding the public signing key
+    pub fn jwks(&self) -> Value {
+        json!({ "keys": [self.key.jwk()] })
+    }
+}
+
+/// Decodes a base64url encoded JSON part of a token.
+///
+/// # Arguments
+/// * `part` - The encoded part
+///
+/// # Returns
+/// The decoded part, or `InvalidToken` if it is malformed
+fn decode_part<T: DeserializeOwned>(part: &str) -> AccountResult<T> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| AccountError::InvalidToken)?;
+    serde_json::from_slice(&bytes).map_err(|_| AccountError::InvalidToken)
+}
@@ -0,0 +1,167 @@
+use crate::errors::{AccountError, AccountResult};
+use reqwest::Url;
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// The random bytes of a TOTP secret, the length of an HMAC-SHA1 key.
+const SECRET_BYTES: usize = 20;
+
+/// The digits of a TOTP code.
+pub const TOTP_DIGITS: u32 = 6;
+
+/// The seconds one TOTP code is valid for.
+pub const TOTP_PERIOD_SECS: u64 = 30;
+
+/// The time steps a code may be early or late, for clock drift.
+const ALLOWED_SKEW: u64 = 1;
+
+/// The alphabet of RFC 4648 base32.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generates a TOTP secret.
+///
+/// # Returns
+/// The random secret, or an internal error if no randomness is available
+pub fn generate_secret() -> AccountResult<Vec<u8>> {
+    let mut secret = vec![0u8; SECRET_BYTES];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .map_err(|_| AccountError::Internal("secret generation failed".to_string()))?;
+    Ok(secret)
+}
+
+/// Encodes bytes as base32, as authenticator apps expect secrets.
+///
+/// # Arguments
+/// * `bytes` - The bytes to encode
+///
+/// # Returns
+/// The RFC 4648 base32 encoding, without padding
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for byte in bytes {
+        // Emit five bits at a time, keeping the rest for the next byte
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    encoded
+}
+
+/// Gets the time step of a time.
+///
+/// # Arguments
+/// * `time` - The time, in seconds since the epoch
+///
+/// # Returns
+/// The number of whole periods since the epoch
+pub fn time_step(time: u64) -> u64 {
+    time / TOTP_PERIOD_SECS
+}
+
+/// Computes the code of a time step, see RFC 4226 and RFC 6238.
+///
+/// # Arguments
+/// * `secret` - The shared secret
+/// * `step` - The time step
+/// * `digits` - The digits of the code
+///
+/// # Returns
+/// The zero padded code
+pub fn code_at(secret: &[u8], step: u64, digits: u32) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let mac = hmac::sign(&key, &step.to_be_bytes());
+    let mac = mac.as_ref();
+
+    // Dynamically truncate the mac to 31 bits
+    let offset = usize::from(mac[mac.len() - 1] & 0x0f);
+    let binary = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        u64::from(binary) % 10u64.pow(digits),
+        width = digits as usize
+    )
+}
+
+/// Verifies a code of an authenticator app.
+///
+/// Codes of the time steps next to the current one are accepted too,
+/// but never of a step at or before the last accepted one.
+///
+/// # Arguments
+/// * `secret` - The shared secret
+/// * `code` - The code to verify
+/// * `time` - The current time, in seconds since the epoch
+/// * `last_step` - The time step of the last accepted code
+///
+/// # Returns
+/// The time step of the code, or `None` if the code is wrong or used
+pub fn verify_code(secret: &[u8], code: &str, time: u64, last_step: u64) -> Option<u64> {
+    let current = time_step(time);
+    (current.saturating_sub(ALLOWED_SKEW)..=current + ALLOWED_SKEW)
+        .filter(|step| *step > last_step)
+        .find(|step| constant_time_eq(&code_at(secret, *step, TOTP_DIGITS), code))
+}
+
+/// Compares two strings without leaking where they differ.
+///
+/// # Arguments
+/// * `a` - The first string
+/// * `b` - The second string
+///
+/// # Returns
+/// True if the strings are equal
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// Builds the provisioning uri of a TOTP secret.
+///
+/// Authenticator apps read the uri from a QR code, see
+/// https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+///
+/// # Arguments
+/// * `issuer` - The name of the service shown in the app
+/// * `account` - The name of the account shown in the app
+/// * `secret` - The shared secret
+///
+/// # Returns
+/// The `otpauth://totp/` uri, or an internal error if it cannot be built
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &[u8]) -> AccountResult<String> {
+    let mut uri = Url::parse("otpauth://totp/")
+        .map_err(|e| AccountError::Internal(format!("otpauth uri invalid: {}", e)))?;
+    uri.path_segments_mut()
+        .map_err(|_| AccountError::Internal("otpauth uri has no path".to_string()))?
+        .pop_if_empty()
+        .push(&format!("{}:{}", issuer, account));
+    uri.query_pairs_mut()
+        .append_pair("secret", &base32_encode(secret))
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &TOTP_DIGITS.to_string())
+        .append_pair("period", &TOTP_PERIOD_SECS.to_string());
+
+    // Apps read spaces in the query encoded as %20 only
+    let query = uri.query().unwrap_or_default().replace('+', "%20");
+    uri.set_query(Some(&query));
+    Ok(uri.to_string())
+}
@@ -0,0 +1,460 @@
+use std::sync::Arc;
+
+use super::opaque_token::{generate_token, hash_token};
+use super::secret_cipher::SecretCipher;
+use super::token_issuer::unix_time;
+use super::totp::{base32_encode, generate_secret, provisioning_uri, verify_code};
+use crate::data::{ActionPurpose, ActionTokenDao, ActionTokenEntity, MfaSettings};
+use crate::errors::{AccountError, AccountResult};
+use crate::services::{
+    AccountDetails, AccountService, CredentialsModel, MfaCodeModel, MfaEnrollmentModel, Validate,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use rocket::serde::{Deserialize, Serialize};
+
+/// The tries to store a used second factor while the account changes concurrently.
+const WRITE_ATTEMPTS: usize = 3;
+
+/// The random bytes of a recovery code.
+const RECOVERY_CODE_BYTES: usize = 10;
+
+/// The two-factor authentication configuration.
+///
+/// Without an encryption key an ephemeral key is generated on
+/// startup, so enrolled authenticators stop working on a restart.
+///
+/// # Fields
+/// * `issuer` - The name of the service shown in authenticator apps
+/// * `encryption_key` - The base64 encoded 256 bit key encrypting TOTP secrets
+/// * `challenge_ttl_secs` - The time to enter a code after the password
+/// * `enrollment_ttl_secs` - The time to confirm an enrollment with a first code
+/// * `recovery_codes` - The number of recovery codes handed out on enrollment
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default)]
+pub struct MfaConfig {
+    pub issuer: String,
+    pub encryption_key: Option<String>,
+    pub challenge_ttl_secs: u64,
+    pub enrollment_ttl_secs: u64,
+    pub recovery_codes: usize,
+}
+
+/// The default two-factor authentication configuration.
+impl Default for MfaConfig {
+    fn default() -> Self {
+        MfaConfig {
+            issuer: "Auction Games".to_string(),
+            encryption_key: None,
+            challenge_ttl_secs: 300,
+            enrollment_ttl_secs: 600,
+            recovery_codes: 10,
+        }
+    }
+}
+
+/// A started TOTP enrollment, as returned to clients.
+///
+/// # Fields
+/// * `secret` - The base32 encoded secret, for manual entry
+/// * `otpauth_uri` - The provisioning uri, for a QR code
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// The recovery codes of a confirmed enrollment, as returned to clients.
+///
+/// # Fields
+/// * `recovery_codes` - The single-use codes replacing a lost authenticator
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// A login waiting for a second factor, as returned to clients.
+///
+/// # Fields
+/// * `mfa_required` - Always true, telling the login apart from a finished one
+/// * `mfa_token` - The challenge token to send with the code
+/// * `expires_in` - The lifetime of the challenge in seconds
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: u64,
+}
+
+/// A login whose challenge was taken.
+///
+/// # Fields
+/// * `account_id` - The account logging in
+/// * `email` - The email the password was given for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChallengedLogin {
+    pub account_id: String,
+    pub email: String,
+}
+
+/// The two-factor authentication.
+///
+/// Accounts enroll an authenticator app with a TOTP secret (RFC 6238)
+/// and confirm it with a first code. From then on a correct password
+/// only starts a challenge, the login finishes with a code or one of
+/// the recovery codes. Secrets are stored encrypted, recovery codes
+/// hashed, and both codes and recovery codes are single-use.
+///
+/// # Fields
+/// * `dao` - The action token data access object, holding challenges
+/// * `cipher` - The cipher of stored TOTP secrets
+/// * `config` - The issuer, challenge and enrollment lifetimes and recovery codes
+///
+/// # Methods
+/// * `new` - Creates new two-factor authentication
+/// * `enroll` - Starts a TOTP enrollment
+/// * `confirm` - Confirms a TOTP enrollment with a code
+/// * `disable` - Turns two-factor authentication off with a second factor
+/// * `challenge` - Starts a challenge for a login with a correct password
+/// * `take_challenge` - Uses up a challenge
+/// * `verify` - Verifies and uses up a second factor
+/// * `spend_factor` - Verifies a second factor and marks it used
+pub struct TwoFactor {
+    dao: Arc<dyn ActionTokenDao>,
+    cipher: SecretCipher,
+    config: MfaConfig,
+}
+
+/// Generates a recovery code.
+///
+/// # Returns
+/// The random code in groups of four, or an internal error if no randomness is available
+fn generate_recovery_code() -> AccountResult<String> {
+    let mut bytes = [0u8; RECOVERY_CODE_BYTES];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| AccountError::Internal("recovery code generation failed".to_string()))?;
+    let encoded = base32_encode(&bytes).to_lowercase();
+    let groups: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(4)
+        .map(|group| std::str::from_utf8(group).unwrap_or_default())
+        .collect();
+    Ok(groups.join("-"))
+}
+
+/// Hashes a recovery code for storage.
+///
+/// Case, dashes and spaces do not matter, so codes can be typed as read.
+///
+/// # Arguments
+/// * `code` - The recovery code
+///
+/// # Returns
+/// The url safe SHA-256 hash of the normalized code
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    hash_token(&normalized)
+}
+
+/// The two-factor authentication implementation.
+impl TwoFactor {
+    /// Creates new two-factor authentication.
+    ///
+    /// # Arguments
+    /// * `dao` - The action token data access object, holding challenges
+    /// * `config` - The issuer, encryption key, challenge lifetime and recovery codes
+    ///
+    /// # Returns
+    /// The new two-factor authentication, or an internal error if the key is malformed
+    pub fn new(dao: Arc<dyn ActionTokenDao>, config: MfaConfig) -> AccountResult<Self> {
+        let cipher = match &config.encryption_key {
+            Some(key) => SecretCipher::new(key)?,
+            None => SecretCipher::generate()?,
+        };
+        Ok(TwoFactor {
+            dao,
+            cipher,
+            config,
+        })
+    }
+
+    /// Starts a TOTP enrollment.
+    ///
+    /// A new enrollment replaces an unconfirmed one.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `id` - The id of the account
+    /// * `enrollment` - The password of the account
+    ///
+    /// # Returns
+    /// The secret and its provisioning uri, `InvalidCredentials` if the
+    /// password is wrong, or `Conflict` if an enrollment was confirmed already
+    pub async fn enroll(
+        &self,
+        service: &dyn AccountService,
+        id: String,
+        enrollment: MfaEnrollmentModel,
+    ) -> AccountResult<TotpEnrollment> {
+        enrollment.validate()?;
+
+        // Only the owner may enroll, and only once
+        let account = service.get_account_by_id(id.clone()).await?;
+        service
+            .validate_account(CredentialsModel {
+                email: account.email.clone(),
+                password: enrollment.password,
+            })
+            .await?;
+        let (mfa, etag) = service.get_mfa(id.clone()).await?;
+        if mfa.is_enabled() {
+            return Err(AccountError::Conflict(
+                "two-factor authentication already enabled".to_string(),
+            ));
+        }
+
+        // Keep the secret encrypted until the first code confirms it
+        let secret = generate_secret()?;
+        let pending = MfaSettings {
+            pending_totp_secret: Some(self.cipher.encrypt(&id, &secret)?),
+            pending_totp_expires_at: unix_time()? + self.config.enrollment_ttl_secs,
+            ..mfa
+        };
+        service.set_mfa(id, pending, etag).await?;
+        Ok(TotpEnrollment {
+            secret: base32_encode(&secret),
+            otpauth_uri: provisioning_uri(&self.config.issuer, &account.email, &secret)?,
+        })
+    }
+
+    /// Confirms a TOTP enrollment with a code.
+    ///
+    /// Every enrollment allows one try before it expires, a wrong code
+    /// needs a new enrollment with the password.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `id` - The id of the account
+    /// * `confirmation` - The first code of the authenticator app
+    ///
+    /// # Returns
+    /// The recovery codes, `InvalidMfaCode` if the code is wrong, or
+    /// `Conflict` if no enrollment was started or it expired
+    pub async fn confirm(
+        &self,
+        service: &dyn AccountService,
+        id: String,
+        confirmation: MfaCodeModel,
+    ) -> AccountResult<RecoveryCodes> {
+        confirmation.validate()?;
+        let (mfa, etag) = service.get_mfa(id.clone()).await?;
+        let now = unix_time()?;
+        let pending = match &mfa.pending_totp_secret {
+            Some(pending) if mfa.pending_totp_expires_at > now => pending.clone(),
+            _ => {
+                return Err(AccountError::Conflict(
+                    "no two-factor enrollment to confirm".to_string(),
+                ))
+            }
+        };
+
+        // Recovery codes cannot confirm an enrollment, and a wrong code uses it up
+        let secret = self.cipher.decrypt(&id, &pending)?;
+        let step = confirmation
+            .code
+            .and_then(|code| verify_code(&secret, &code, now, mfa.last_totp_step));
+        let Some(step) = step else {
+            let abandoned = MfaSettings {
+                pending_totp_secret: None,
+                pending_totp_expires_at: 0,
+                ..mfa
+            };
+            service.set_mfa(id, abandoned, etag).await?;
+            return Err(AccountError::InvalidMfaCode);
+        };
+
+        // Enable the secret with fresh recovery codes, only their hashes are kept
+        let recovery_codes = (0..self.config.recovery_codes)
+            .map(|_| generate_recovery_code())
+            .collect::<AccountResult<Vec<String>>>()?;
+        let enabled = MfaSettings {
+            totp_secret: Some(pending),
+            pending_totp_secret: None,
+            pending_totp_expires_at: 0,
+            last_totp_step: step,
+            recovery_codes: recovery_codes
+                .iter()
+                .map(|code| hash_recovery_code(code))
+                .collect(),
+        };
+        service.set_mfa(id, enabled, etag).await?;
+        Ok(RecoveryCodes { recovery_codes })
+    }
+
+    /// Turns two-factor authentication off with a second factor.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `id` - The id of the account
+    /// * `factor` - A code or a recovery code
+    ///
+    /// # Returns
+    /// Nothing, `InvalidMfaCode` if the factor is wrong, or `Conflict`
+    /// if two-factor authentication is not enabled
+    pub async fn disable(
+        &self,
+        service: &dyn AccountService,
+        id: String,
+        factor: MfaCodeModel,
+    ) -> AccountResult<()> {
+        factor.validate()?;
+        let (mfa, _) = service.get_mfa(id.clone()).await?;
+        if !mfa.is_enabled() {
+            return Err(AccountError::Conflict(
+                "two-factor authentication not enabled".to_string(),
+            ));
+        }
+        self.spend_factor(service, &id, &factor, |_| MfaSettings::default())
+            .await
+    }
+
+    /// Starts a challenge for a login with a correct password.
+    ///
+    /// # Arguments
+    /// * `account` - The account logging in
+    ///
+    /// # Returns
+    /// The challenge, or an error if it cannot be stored
+    pub async fn challenge(&self, account: &AccountDetails) -> AccountResult<MfaChallenge> {
+        let token = generate_token()?;
+        self.dao
+            .create_action_token(ActionTokenEntity {
+                id: hash_token(&token),
+                purpose: ActionPurpose::MfaChallenge,
+                account_id: account.id.clone(),
+                email: Some(account.email.clone()),
+                expires_at: unix_time()? + self.config.challenge_ttl_secs,
+                etag: None,
+            })
+            .await?;
+        Ok(MfaChallenge {
+            mfa_required: true,
+            mfa_token: token,
+            expires_in: self.config.challenge_ttl_secs,
+        })
+    }
+
+    /// Uses up a challenge.
+    ///
+    /// Every challenge allows a single try, a wrong code needs the
+    /// password again.
+    ///
+    /// # Arguments
+    /// * `mfa_token` - The challenge token
+    ///
+    /// # Returns
+    /// The login of the challenge, or `InvalidToken` if the token is
+    /// unknown, expired or already used
+    pub async fn take_challenge(&self, mfa_token: &str) -> AccountResult<ChallengedLogin> {
+        let token = match self.dao.consume_action_token(hash_token(mfa_token)).await {
+            Ok(token) => token,
+            Err(AccountError::NotFound) => return Err(AccountError::InvalidToken),
+            Err(e) => return Err(e),
+        };
+        match token.email {
+            Some(email)
+                if token.purpose == ActionPurpose::MfaChallenge
+                    && token.expires_at > unix_time()? =>
+            {
+                Ok(ChallengedLogin {
+                    account_id: token.account_id,
+                    email,
+                })
+            }
+            _ => Err(AccountError::InvalidToken),
+        }
+    }
+
+    /// Verifies and uses up a second factor.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `id` - The id of the account
+    /// * `factor` - A code or a recovery code
+    ///
+    /// # Returns
+    /// Nothing, or `InvalidMfaCode` if the factor is wrong or already used
+    pub async fn verify(
+        &self,
+        service: &dyn AccountService,
+        id: &str,
+        factor: &MfaCodeModel,
+    ) -> AccountResult<()> {
+        factor.validate()?;
+        self.spend_factor(service, id, factor, |mfa| mfa).await
+    }
+
+    /// Verifies a second factor and marks it used.
+    ///
+    /// The settings are stored over the version the factor was checked
+    /// against, so a factor used concurrently is accepted only once.
+    ///
+    /// # Arguments
+    /// * `service` - The account service
+    /// * `id` - The id of the account
+    /// * `factor` - A code or a recovery code
+    /// * `then` - Changes the settings further once the factor is used
+    ///
+    /// # Returns
+    /// Nothing, or `InvalidMfaCode` if the factor is wrong or already used
+    async fn spend_factor(
+        &self,
+        service: &dyn AccountService,
+        id: &str,
+        factor: &MfaCodeModel,
+        then: fn(MfaSettings) -> MfaSettings,
+    ) -> AccountResult<()> {
+        let mut result = Err(AccountError::PreconditionFailed);
+        for _ in 0..WRITE_ATTEMPTS {
+            let (mut mfa, etag) = service.get_mfa(id.to_string()).await?;
+            let encrypted = mfa
+                .totp_secret
+                .clone()
+                .ok_or(AccountError::InvalidMfaCode)?;
+
+            // Codes must be newer than the last one, recovery codes are removed
+            match (&factor.code, &factor.recovery_code) {
+                (Some(code), _) => {
+                    let secret = self.cipher.decrypt(id, &encrypted)?;
+                    mfa.last_totp_step =
+                        verify_code(&secret, code, unix_time()?, mfa.last_totp_step)
+                            .ok_or(AccountError::InvalidMfaCode)?;
+                }
+                (None, Some(recovery_code)) => {
+                    let hash = hash_recovery_code(recovery_code);
+                    let before = mfa.recovery_codes.len();
+                    mfa.recovery_codes.retain(|stored| *stored != hash);
+                    if mfa.recovery_codes.len() == before {
+                        return Err(AccountError::InvalidMfaCode);
+                    }
+                }
+                (None, None) => return Err(AccountError::InvalidMfaCode),
+            }
+
+            // Try again if the account changed, the factor may be used by now
+            result = service.set_mfa(id.to_string(), then(mfa), etag).await;
+            match result {
+                Err(AccountError::PreconditionFailed) => continue,
+                result => return result,
+            }
+        }
+        result
+    }
+}